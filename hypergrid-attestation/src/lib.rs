@@ -0,0 +1,245 @@
+//! Verification of the `X-Hypergrid-Attestation` header that Hypergrid providers attach to
+//! every upstream request. The header is `<payload>.<signature>`, both base64url without
+//! padding. The payload is JSON:
+//!
+//! ```json
+//! {"v":1,"iss":"provider-node.os@provider:hypergrid:ware.hypr","provider_name":"weather",
+//!  "caller":"operator-node.os","tx_hash":"0xabc…","iat":1760000000,"exp":1760000060,
+//!  "body_sha256":"e3b0c442…"}
+//! ```
+//!
+//! The signature is the provider node's Ed25519 networking key over the bytes of `iss`
+//! followed by the raw payload bytes. That key is the node's `~net-key` on Hypermap.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ed25519_dalek::{Signature, Verifier as _, VerifyingKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+
+pub const ATTESTATION_HEADER: &str = "X-Hypergrid-Attestation";
+
+/// Payload format this crate understands
+pub const SUPPORTED_VERSION: u8 = 1;
+
+/// Signed claims about a provider call
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct Attestation {
+    #[serde(rename = "v")]
+    pub version: u8,
+    #[serde(rename = "iss")]
+    pub issuer: String,
+    // `<provider>/<step>` for steps of composite providers
+    pub provider_name: String,
+    // Calling node, or the payer address for x402 calls
+    pub caller: String,
+    pub tx_hash: Option<String>,
+    #[serde(rename = "iat")]
+    pub issued_at: u64,
+    #[serde(rename = "exp")]
+    pub expires_at: u64,
+    pub body_sha256: String,
+}
+
+impl Attestation {
+    /// Node that signed the attestation
+    pub fn provider_node(&self) -> &str {
+        self.issuer.split('@').next().unwrap_or_default()
+    }
+
+    /// Process that signed the attestation, e.g. `provider:hypergrid:ware.hypr`
+    pub fn provider_process(&self) -> &str {
+        self.issuer.split_once('@').map(|(_, process)| process).unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerifyError {
+    Malformed(String),
+    UnsupportedVersion(u8),
+    UntrustedIssuer(String),
+    BadSignature,
+    Expired,
+    NotYetValid,
+    BodyMismatch,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Malformed(reason) => write!(f, "malformed attestation: {}", reason),
+            VerifyError::UnsupportedVersion(v) => write!(f, "unsupported attestation version {}", v),
+            VerifyError::UntrustedIssuer(issuer) => write!(f, "untrusted issuer {}", issuer),
+            VerifyError::BadSignature => write!(f, "signature does not match"),
+            VerifyError::Expired => write!(f, "attestation expired"),
+            VerifyError::NotYetValid => write!(f, "attestation issued in the future"),
+            VerifyError::BodyMismatch => write!(f, "body hash does not match the request body"),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Split a header value into the decoded claims, the raw payload and the signature
+pub fn decode(header: &str) -> Result<(Attestation, Vec<u8>, Vec<u8>), VerifyError> {
+    let (payload_b64, signature_b64) = header
+        .trim()
+        .split_once('.')
+        .ok_or_else(|| VerifyError::Malformed("expected <payload>.<signature>".to_string()))?;
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload_b64)
+        .map_err(|e| VerifyError::Malformed(format!("payload: {}", e)))?;
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature_b64)
+        .map_err(|e| VerifyError::Malformed(format!("signature: {}", e)))?;
+    let attestation = serde_json::from_slice(&payload)
+        .map_err(|e| VerifyError::Malformed(format!("payload JSON: {}", e)))?;
+    Ok((attestation, payload, signature))
+}
+
+/// Checks attestations against the networking keys of trusted provider nodes
+pub struct Verifier {
+    node_keys: HashMap<String, VerifyingKey>,
+    provider_process: String,
+    clock_skew_secs: u64,
+}
+
+impl Verifier {
+    /// Trust attestations from `provider_process` (e.g. `provider:hypergrid:ware.hypr`)
+    pub fn new(provider_process: impl Into<String>) -> Self {
+        Verifier {
+            node_keys: HashMap::new(),
+            provider_process: provider_process.into(),
+            clock_skew_secs: 5,
+        }
+    }
+
+    /// Trust a provider node, given its 32-byte networking key as hex (with or without `0x`)
+    pub fn trust_node(mut self, node: impl Into<String>, net_key_hex: &str) -> Result<Self, VerifyError> {
+        let bytes = hex::decode(net_key_hex.trim_start_matches("0x"))
+            .map_err(|e| VerifyError::Malformed(format!("net key: {}", e)))?;
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| VerifyError::Malformed("net key must be 32 bytes".to_string()))?;
+        let key = VerifyingKey::from_bytes(&bytes)
+            .map_err(|e| VerifyError::Malformed(format!("net key: {}", e)))?;
+        self.node_keys.insert(node.into(), key);
+        Ok(self)
+    }
+
+    /// Tolerated clock difference with the provider node, in seconds
+    pub fn with_clock_skew(mut self, secs: u64) -> Self {
+        self.clock_skew_secs = secs;
+        self
+    }
+
+    /// Verify `header` for a request carrying `body`, at unix time `now`
+    pub fn verify(&self, header: &str, body: &[u8], now: u64) -> Result<Attestation, VerifyError> {
+        let (attestation, payload, signature) = decode(header)?;
+        if attestation.version != SUPPORTED_VERSION {
+            return Err(VerifyError::UnsupportedVersion(attestation.version));
+        }
+        if attestation.provider_process() != self.provider_process {
+            return Err(VerifyError::UntrustedIssuer(attestation.issuer));
+        }
+        let key = self
+            .node_keys
+            .get(attestation.provider_node())
+            .ok_or_else(|| VerifyError::UntrustedIssuer(attestation.issuer.clone()))?;
+
+        let signature = Signature::from_slice(&signature).map_err(|_| VerifyError::BadSignature)?;
+        let message = [attestation.issuer.as_bytes(), &payload].concat();
+        key.verify(&message, &signature)
+            .map_err(|_| VerifyError::BadSignature)?;
+
+        if now > attestation.expires_at + self.clock_skew_secs {
+            return Err(VerifyError::Expired);
+        }
+        if attestation.issued_at > now + self.clock_skew_secs {
+            return Err(VerifyError::NotYetValid);
+        }
+        if hex::encode(Sha256::digest(body)) != attestation.body_sha256 {
+            return Err(VerifyError::BodyMismatch);
+        }
+        Ok(attestation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const PROCESS: &str = "provider:hypergrid:ware.hypr";
+    const ISSUER: &str = "weather-node.os@provider:hypergrid:ware.hypr";
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[7u8; 32])
+    }
+
+    fn header(payload: &serde_json::Value) -> String {
+        let payload = serde_json::to_vec(payload).unwrap();
+        let signature = signing_key().sign(&[ISSUER.as_bytes(), &payload].concat());
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(&payload),
+            URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        )
+    }
+
+    fn payload(body: &[u8]) -> serde_json::Value {
+        serde_json::json!({
+            "v": 1,
+            "iss": ISSUER,
+            "provider_name": "weather",
+            "caller": "operator-node.os",
+            "tx_hash": "0xabc",
+            "iat": 1_000,
+            "exp": 1_060,
+            "body_sha256": hex::encode(Sha256::digest(body)),
+        })
+    }
+
+    fn verifier() -> Verifier {
+        let key = hex::encode(signing_key().verifying_key().to_bytes());
+        Verifier::new(PROCESS).trust_node("weather-node.os", &key).unwrap()
+    }
+
+    #[test]
+    fn test_valid_attestation() {
+        let body = br#"{"city":"Paris"}"#;
+        let attestation = verifier().verify(&header(&payload(body)), body, 1_010).unwrap();
+        assert_eq!(attestation.provider_node(), "weather-node.os");
+        assert_eq!(attestation.caller, "operator-node.os");
+        assert_eq!(attestation.tx_hash.as_deref(), Some("0xabc"));
+    }
+
+    #[test]
+    fn test_tampered_payload() {
+        let body = b"";
+        let signed = header(&payload(body));
+        let (_, signature) = signed.split_once('.').unwrap();
+        let mut forged = payload(body);
+        forged["caller"] = "someone-else.os".into();
+        let forged_payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&forged).unwrap());
+        let result = verifier().verify(&format!("{}.{}", forged_payload, signature), body, 1_010);
+        assert_eq!(result, Err(VerifyError::BadSignature));
+    }
+
+    #[test]
+    fn test_expired_and_body_mismatch() {
+        let body = b"{}";
+        let signed = header(&payload(body));
+        assert_eq!(verifier().verify(&signed, body, 2_000), Err(VerifyError::Expired));
+        assert_eq!(verifier().verify(&signed, b"[]", 1_010), Err(VerifyError::BodyMismatch));
+    }
+
+    #[test]
+    fn test_untrusted_node() {
+        let body = b"";
+        let result = Verifier::new(PROCESS).verify(&header(&payload(body)), body, 1_010);
+        assert!(matches!(result, Err(VerifyError::UntrustedIssuer(_))));
+    }
+}
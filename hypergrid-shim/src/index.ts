@@ -248,6 +248,90 @@ async function main() {
         },
     );
 
+    // Get job result tool - polls async providers for the outcome of an earlier call
+    server.tool("get-job-result", { jobId: z.string() }, async ({ jobId }) => {
+        if (!currentConfig) {
+            return {
+                content: [{
+                    type: "text",
+                    text: "⚠️ This MCP server is not configured yet. Please use the 'authorize' tool first with your Hypergrid credentials.\n\nExample: Use the authorize tool with url \"...\", token \"...\", client_id \"...\", and node \"...\""
+                }]
+            };
+        }
+
+        const body = { GetJobResult: { jobId } };
+        console.error(`get-job-result: Forwarding to ${currentConfig.url}`);
+        const headers: any = {
+            "Content-type": "application/json",
+            "X-Client-ID": currentConfig.client_id,
+            "X-Token": currentConfig.token
+        };
+        if (currentConfig.name) {
+            headers["X-Client-Name"] = currentConfig.name;
+        }
+
+        try {
+            const res = await fetch(currentConfig.url, {
+                method: "POST",
+                headers: headers,
+                body: JSON.stringify(body),
+            });
+            const resBody = await res.text();
+            console.error(`get-job-result: Response received (status ${res.status})`);
+            return { content: [{ type: "text", text: String(resBody) }] };
+        } catch (e: any) {
+            console.error(`get-job-result: Request failed: ${e.message}`);
+            return {
+                content: [{ type: "text", text: String(`{"error": "Request Failed: ${e.message}"}`) }],
+            };
+        }
+    });
+
+    // Get response chunk tool - fetches the remaining parts of an oversized response
+    server.tool(
+        "get-response-chunk",
+        { continuationToken: z.string(), chunkIndex: z.number().int().nonnegative() },
+        async ({ continuationToken, chunkIndex }) => {
+            if (!currentConfig) {
+                return {
+                    content: [{
+                        type: "text",
+                        text: "⚠️ This MCP server is not configured yet. Please use the 'authorize' tool first with your Hypergrid credentials.\n\nExample: Use the authorize tool with url \"...\", token \"...\", client_id \"...\", and node \"...\""
+                    }]
+                };
+            }
+
+            const body = { GetResponseChunk: { continuationToken, chunkIndex } };
+            console.error(`get-response-chunk: Forwarding to ${currentConfig.url}`);
+            const headers: any = {
+                "Content-type": "application/json",
+                "X-Client-ID": currentConfig.client_id,
+                "X-Token": currentConfig.token
+            };
+            if (currentConfig.name) {
+                headers["X-Client-Name"] = currentConfig.name;
+            }
+
+            try {
+                const res = await fetch(currentConfig.url, {
+                    method: "POST",
+                    headers: headers,
+                    body: JSON.stringify(body),
+                });
+                const resBody = await res.text();
+                console.error(`get-response-chunk: Response received (status ${res.status})`);
+                return { content: [{ type: "text", text: String(resBody) }] };
+            } catch (e: any) {
+                console.error(`get-response-chunk: Request failed: ${e.message}`);
+                return {
+                    content: [
+                        { type: "text", text: String(`{"error": "Request Failed: ${e.message}"}`) },
+                    ],
+                };
+            }
+        },
+    );
+
     console.error(`Connecting transport...`);
     const transport = new StdioServerTransport();
     await server.connect(transport);
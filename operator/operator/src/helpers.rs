@@ -147,6 +147,52 @@ pub fn get_provider_id(provider_name: &str) -> String {
     format!("{:x}", digest)
 } 
 
+/// Refuse to pay a provider whose live terms differ from what the index says, or that
+/// reports itself unavailable
+pub fn check_provider_health(
+    status: &ProviderHealthStatus,
+    indexed_price: &str,
+    indexed_wallet: &str,
+) -> Result<(), String> {
+    if !status.available {
+        return Err(format!(
+            "Provider unavailable: {}",
+            status.unavailable_reason.as_deref().unwrap_or("no reason given")
+        ));
+    }
+    if !status.wallet.eq_ignore_ascii_case(indexed_wallet.trim()) {
+        return Err(format!(
+            "Provider wallet {} does not match the indexed wallet {}",
+            status.wallet, indexed_wallet
+        ));
+    }
+    // Compared in USDC units, so "0.010" and 0.01 agree
+    let live_units = usdc_display_to_units(&status.price.to_string());
+    let indexed_units = usdc_display_to_units(indexed_price);
+    if live_units.is_none() || live_units != indexed_units {
+        return Err(format!(
+            "Provider price {} USDC does not match the indexed price {} USDC",
+            status.price, indexed_price
+        ));
+    }
+    Ok(())
+}
+
+/// Version to carry in the messages following a health ping; None for providers that don't
+/// negotiate, which get unversioned messages
+pub fn negotiated_protocol_version(status: &ProviderHealthStatus) -> Result<Option<u32>, String> {
+    if status.protocol_version == 0 {
+        return Ok(None);
+    }
+    if !HYPERGRID_PROTOCOL_VERSIONS.contains(&status.protocol_version) {
+        return Err(format!(
+            "Provider chose Hypergrid protocol version {}, this operator speaks {:?}; update the operator",
+            status.protocol_version, HYPERGRID_PROTOCOL_VERSIONS
+        ));
+    }
+    Ok(Some(status.protocol_version))
+}
+
 // Helper function to authenticate a shim client
 pub fn authenticate_shim_client<'a>(
     state: &'a State,
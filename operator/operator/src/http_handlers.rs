@@ -21,7 +21,7 @@ use crate::{
     authorized_services::{HotWalletAuthorizedClient, ServiceCapabilities},
     db as dbm,
     graph::handle_get_hypergrid_graph_layout,
-    helpers::send_json_response,
+    helpers::{check_provider_health, negotiated_protocol_version, send_json_response},
     hyperwallet_client::{payments as hyperwallet_payments, service as hyperwallet_service},
     structs::{
         ApiRequest, ConfigureAuthorizedClientRequest, ConfigureAuthorizedClientResponse,
@@ -280,6 +280,89 @@ fn handle_shim_mcp_route(
     }
 }
 
+/// Client ID under which composite providers' downstream calls are recorded and limited.
+/// Its `max_total` client limit is their budget; until one is set they can't call anything.
+pub const LOCAL_PROVIDER_CLIENT_ID: &str = "local-provider";
+
+/// MCP requests from the provider process on this node, paying the remote steps of composite
+/// providers. Calls are paid from the operator TBA, which must be the wallet the provider
+/// registered, recorded under `LOCAL_PROVIDER_CLIENT_ID` and held to its spending limit, and
+/// answered like the UI's MCP route.
+pub fn handle_local_mcp(
+    our: &Address,
+    body: &[u8],
+    state: &mut State,
+    db: &Sqlite,
+) -> anyhow::Result<()> {
+    let LocalProviderRequest { payer_wallet, request } = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(e) => {
+            error!("Failed to deserialize local MCP request: {}", e);
+            return send_json_response(
+                StatusCode::BAD_REQUEST,
+                &json!({ "error": format!("Invalid MCP request body: {}", e) }),
+            );
+        }
+    };
+
+    // We can only sign for our own TBA, so the provider's downstream payments come out of its
+    // wallet only if that is the wallet it registered
+    let pays_from_operator_tba = state
+        .operator_tba_address
+        .as_deref()
+        .is_some_and(|tba| tba.eq_ignore_ascii_case(&payer_wallet));
+    if !pays_from_operator_tba {
+        warn!(
+            "Refusing local MCP request: provider wallet {} is not the operator TBA {:?}",
+            payer_wallet, state.operator_tba_address
+        );
+        return send_json_response(
+            StatusCode::FORBIDDEN,
+            &json!({
+                "error": format!(
+                    "Provider wallet {} is not this node's operator TBA, so the operator can't pay from it",
+                    payer_wallet
+                )
+            }),
+        );
+    }
+
+    let has_budget = state
+        .client_limits_cache
+        .get(LOCAL_PROVIDER_CLIENT_ID)
+        .and_then(|limits| limits.max_total.as_deref())
+        .is_some_and(|max_total| !max_total.trim().is_empty());
+    if !has_budget {
+        warn!("Refusing local MCP request: no spending limit set for '{}'", LOCAL_PROVIDER_CLIENT_ID);
+        return send_json_response(
+            StatusCode::FORBIDDEN,
+            &json!({
+                "error": format!(
+                    "No budget for composite providers: set a max_total client limit for '{}'",
+                    LOCAL_PROVIDER_CLIENT_ID
+                )
+            }),
+        );
+    }
+    let wallet_id = match determine_ui_payment_wallet(state) {
+        Ok(wallet_id) => wallet_id,
+        Err(reason) => {
+            return send_json_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                &json!({ "error": format!("No wallet to pay from: {:?}", reason) }),
+            )
+        }
+    };
+    let client_config = HotWalletAuthorizedClient {
+        id: LOCAL_PROVIDER_CLIENT_ID.to_string(),
+        name: "Composite providers".to_string(),
+        associated_hot_wallet_address: wallet_id,
+        authentication_token: String::new(),
+        capabilities: ServiceCapabilities::All,
+    };
+    handle_mcp(our, request, state, db, Some(client_config))
+}
+
 fn handle_api_actions_route(_our: &Address, state: &mut State, _db: &Sqlite) -> anyhow::Result<()> {
     info!("Routing to handle_api_actions (for UI API operations)");
     handle_api_actions(state)
@@ -314,7 +397,7 @@ fn handle_shim_auth_error(auth_error: AuthError) -> anyhow::Result<()> {
 // ===========================================================================================
 
 /// Main MCP request dispatcher - routes Model Context Provider operations
-/// Only handles SearchRegistry and CallProvider - the actual MCP operations
+/// Only handles SearchRegistry, CallProvider, GetJobResult and GetResponseChunk - the actual MCP operations
 fn handle_mcp(
     our: &Address,
     req: McpRequest,
@@ -341,6 +424,13 @@ fn handle_mcp(
             arguments,
             client_config_opt,
         ),
+        McpRequest::GetJobResult { job_id } => {
+            handle_get_job_result(state, job_id, client_config_opt)
+        }
+        McpRequest::GetResponseChunk {
+            continuation_token,
+            chunk_index,
+        } => handle_get_response_chunk(state, continuation_token, chunk_index, client_config_opt),
     }
 }
 
@@ -804,12 +894,13 @@ fn execute_provider_flow(
     client_config_opt: Option<HotWalletAuthorizedClient>,
 ) -> anyhow::Result<()> {
     // First, do a health check ping to see if the provider is responsive
-    match perform_provider_health_check(&provider_details, Some(&provider_name)) {
-        Ok(()) => {
+    let protocol_version = match perform_provider_health_check(&provider_details, Some(&provider_name)) {
+        Ok(protocol_version) => {
             info!(
-                "Provider {} health check passed",
-                provider_details.provider_id
+                "Provider {} health check passed (protocol version {:?})",
+                provider_details.provider_id, protocol_version
             );
+            protocol_version
         }
         Err(health_error) => {
             error!(
@@ -841,7 +932,7 @@ fn execute_provider_flow(
                 }),
             );
         }
-    }
+    };
 
     // Provider is responsive, proceed with payment if required
     match handle_payment(state, &provider_details, client_config_opt.as_ref()) {
@@ -859,6 +950,7 @@ fn execute_provider_flow(
                 timestamp_start_ms,
                 call_args_json,
                 None,
+                protocol_version,
                 client_config_opt,
             )
         }
@@ -876,6 +968,7 @@ fn execute_provider_flow(
                 timestamp_start_ms,
                 call_args_json,
                 Some(tx_hash),
+                protocol_version,
                 client_config_opt,
             )
         }
@@ -1455,6 +1548,7 @@ fn execute_provider_call(
     timestamp_start_ms: u128,
     call_args_json: String,
     payment_tx_hash: Option<String>,
+    protocol_version: Option<u32>,
     client_config_opt: Option<HotWalletAuthorizedClient>,
 ) -> anyhow::Result<()> {
     // Prepare target address
@@ -1474,6 +1568,7 @@ fn execute_provider_call(
         provider_name: provider_name.clone(),
         arguments,
         payment_tx_hash: payment_tx_hash_clone,
+        protocol_version,
     };
     // Wrap the ProviderCall data in a JSON structure that mimics the enum variant
     let wrapped_request = serde_json::json!({
@@ -1494,6 +1589,15 @@ fn execute_provider_call(
     let response_timestamp_ms = Utc::now().timestamp_millis() as u128;
     let call_success = matches!(provider_call_result, ProviderCallResult::Success(_));
 
+    // Async providers answer with a job ticket instead of the result, and oversized
+    // responses arrive as a first chunk with a continuation token
+    let provider_json = match &provider_call_result {
+        ProviderCallResult::Success(body) => provider_ok_json(body),
+        _ => None,
+    };
+    let job_id = provider_json.as_ref().and_then(job_ticket_id);
+    let continuation_token = provider_json.as_ref().and_then(chunk_continuation_token);
+
     let payment_result = if let Some(tx) = payment_tx_hash {
         Some(PaymentAttemptResult::Success {
             tx_hash: tx,
@@ -1528,7 +1632,14 @@ fn execute_provider_call(
         operator_wallet_id: actual_operator_wallet_id,
         client_id: client_config_opt.as_ref().map(|c| c.id.clone()),
         provider_name: Some(provider_name.clone()),
+        job_id: job_id.clone(),
+        job_status: job_id.as_ref().map(|_| "Pending".to_string()),
+        continuation_token,
+        protocol_version,
     };
+    if let Some(job_id) = &job_id {
+        info!("Provider {} queued async job {}", provider_name, job_id);
+    }
 
     state.call_history.push(record);
     limit_call_history(state);
@@ -1623,7 +1734,11 @@ fn execute_provider_call(
 
 /// Perform a lightweight health check on a provider before payment
 
-fn perform_provider_health_check(provider_details: &ProviderDetails, provider_name: Option<&str>) -> anyhow::Result<()> {
+/// Returns the negotiated protocol version, None for providers that don't negotiate
+fn perform_provider_health_check(
+    provider_details: &ProviderDetails,
+    provider_name: Option<&str>,
+) -> anyhow::Result<Option<u32>> {
     info!("Performing health check for provider {}", provider_details.provider_id);
     
     let target_address = Address::new(
@@ -1632,11 +1747,8 @@ fn perform_provider_health_check(provider_details: &ProviderDetails, provider_na
     );
 
     let health_check_request = serde_json::json!({
-        "provider_name": provider_name.unwrap_or(&provider_details.provider_id)
-    });
-
-    let dummy_argument = serde_json::json!({
-        "argument": "swag"
+        "provider_name": provider_name.unwrap_or(&provider_details.provider_id),
+        "protocol_versions": HYPERGRID_PROTOCOL_VERSIONS,
     });
 
     let wrapped_request = serde_json::json!({
@@ -1669,6 +1781,25 @@ fn perform_provider_health_check(provider_details: &ProviderDetails, provider_na
                         "Provider {} responded to health check: {:?}",
                         provider_details.provider_id, response_json
                     );
+                    // Unavailable providers (e.g. upstream circuit open) must not be paid
+                    if let Some(reason) = response_json.get("Err") {
+                        return Err(anyhow::anyhow!(
+                            "Provider unavailable: {}",
+                            reason.as_str().unwrap_or(&reason.to_string())
+                        ));
+                    }
+                    // Older providers answer a bare "Ack" and can't be checked further
+                    if let Some(status) = response_json.get("Ok").filter(|ok| ok.is_object()) {
+                        let status: ProviderHealthStatus = serde_json::from_value(status.clone())
+                            .map_err(|e| anyhow::anyhow!("Unreadable health status: {}", e))?;
+                        check_provider_health(
+                            &status,
+                            &provider_details.price_str,
+                            &provider_details.wallet_address,
+                        )
+                        .map_err(|e| anyhow::anyhow!("{}", e))?;
+                        return negotiated_protocol_version(&status).map_err(|e| anyhow::anyhow!("{}", e));
+                    }
                 }
                 Err(_) => {
                     info!(
@@ -1677,7 +1808,7 @@ fn perform_provider_health_check(provider_details: &ProviderDetails, provider_na
                     );
                 }
             }
-            Ok(())
+            Ok(None)
         }
         Ok(Err(send_error)) => {
             error!(
@@ -1721,6 +1852,167 @@ pub fn send_request_to_provider(
     }
 }
 
+/// JSON carried in a provider's `{"Ok": "<JSON string>"}` answer
+fn provider_ok_json(body: &[u8]) -> Option<Value> {
+    let response: Value = serde_json::from_slice(body).ok()?;
+    serde_json::from_str(response.get("Ok")?.as_str()?).ok()
+}
+
+/// Job ID of an async provider's ticket, `{"job_id", "provider_name", "status"}`
+fn job_ticket_id(ticket: &Value) -> Option<String> {
+    ticket.get("provider_name")?;
+    ticket.get("status")?;
+    ticket.get("job_id")?.as_str().map(String::from)
+}
+
+/// Token of the first chunk of an oversized response, `{"chunk", "total_chunks", "continuation_token", ...}`
+fn chunk_continuation_token(envelope: &Value) -> Option<String> {
+    envelope.get("chunk")?;
+    envelope.get("total_chunks")?;
+    envelope.get("continuation_token")?.as_str().map(String::from)
+}
+
+/// Forward a chunk request to the provider that issued the continuation token
+fn handle_get_response_chunk(
+    state: &State,
+    continuation_token: String,
+    chunk_index: u64,
+    client_config_opt: Option<HotWalletAuthorizedClient>,
+) -> anyhow::Result<()> {
+    let client_id = client_config_opt.as_ref().map(|c| c.id.clone());
+    let Some(record) = state.call_history.iter().find(|r| {
+        r.continuation_token.as_deref() == Some(continuation_token.as_str())
+            && (client_id.is_none() || r.client_id == client_id)
+    }) else {
+        return send_json_response(
+            StatusCode::NOT_FOUND,
+            &json!({ "error": "No call with this continuation token" }),
+        );
+    };
+
+    let target_address = Address::new(
+        &record.target_provider_id,
+        ("provider", "hypergrid", PUBLISHER),
+    );
+    let request_body_bytes = serde_json::to_vec(&json!({
+        "GetResponseChunk": {
+            "continuation_token": continuation_token,
+            "chunk_index": chunk_index,
+            "protocol_version": record.protocol_version,
+        }
+    }))?;
+    match send_request_to_provider(target_address.clone(), request_body_bytes) {
+        Ok(Ok(body)) => {
+            send_response(
+                StatusCode::OK,
+                Some(HashMap::from([(
+                    String::from("Content-Type"),
+                    String::from("application/json"),
+                )])),
+                body,
+            );
+            Ok(())
+        }
+        Ok(Err(e)) | Err(e) => {
+            error!("Provider failed to answer chunk request: {:?}", e);
+            send_json_response(
+                StatusCode::BAD_GATEWAY,
+                &json!({
+                    "error": format!("Provider {} failed to respond: {:?}", target_address, e)
+                }),
+            )
+        }
+    }
+}
+
+/// Poll the provider for an async job and record the outcome on its call record.
+/// Finished jobs are answered from call history, since providers drop results after a TTL.
+fn handle_get_job_result(
+    state: &mut State,
+    job_id: String,
+    client_config_opt: Option<HotWalletAuthorizedClient>,
+) -> anyhow::Result<()> {
+    let client_id = client_config_opt.as_ref().map(|c| c.id.clone());
+    let Some(index) = state.call_history.iter().position(|r| {
+        r.job_id.as_deref() == Some(job_id.as_str()) && (client_id.is_none() || r.client_id == client_id)
+    }) else {
+        return send_json_response(
+            StatusCode::NOT_FOUND,
+            &json!({ "error": format!("No call with job ID {}", job_id) }),
+        );
+    };
+
+    let finished = |status: Option<&str>| matches!(status, Some("Succeeded") | Some("Failed"));
+    if !finished(state.call_history[index].job_status.as_deref()) {
+        let target_address = Address::new(
+            &state.call_history[index].target_provider_id,
+            ("provider", "hypergrid", PUBLISHER),
+        );
+        let request_body_bytes =
+            serde_json::to_vec(&json!({
+                "GetJobResult": {
+                    "job_id": job_id,
+                    "protocol_version": state.call_history[index].protocol_version,
+                }
+            }))?;
+        let response: Value = match send_request_to_provider(target_address.clone(), request_body_bytes) {
+            Ok(Ok(body)) => serde_json::from_slice(&body)?,
+            Ok(Err(e)) | Err(e) => {
+                error!("Provider failed to answer job poll: {:?}", e);
+                return send_json_response(
+                    StatusCode::BAD_GATEWAY,
+                    &json!({
+                        "error": format!("Provider {} failed to respond: {:?}", target_address, e)
+                    }),
+                );
+            }
+        };
+        let Some(job) = response.get("Ok") else {
+            return send_json_response(
+                StatusCode::BAD_GATEWAY,
+                &json!({ "error": response.get("Err").cloned().unwrap_or(response.clone()) }),
+            );
+        };
+
+        let status = job.get("status").and_then(Value::as_str).map(String::from);
+        let record = &mut state.call_history[index];
+        if finished(status.as_deref()) {
+            let outcome = match status.as_deref() {
+                Some("Succeeded") => job.get("result"),
+                _ => job.get("error"),
+            };
+            record.response_json = outcome.and_then(Value::as_str).map(String::from);
+            record.continuation_token = record
+                .response_json
+                .as_deref()
+                .and_then(|r| serde_json::from_str::<Value>(r).ok())
+                .as_ref()
+                .and_then(chunk_continuation_token);
+            record.response_timestamp_ms = Utc::now().timestamp_millis() as u128;
+            record.duration_ms = record.response_timestamp_ms - record.timestamp_start_ms;
+        }
+        record.job_status = status;
+        state.save();
+    }
+
+    let record = &state.call_history[index];
+    let response_json = record.response_json.clone();
+    let (result, error) = match record.job_status.as_deref() {
+        Some("Succeeded") => (response_json, None),
+        Some("Failed") => (None, response_json),
+        _ => (None, None),
+    };
+    send_json_response(
+        StatusCode::OK,
+        &json!({
+            "job_id": job_id,
+            "status": record.job_status,
+            "result": result,
+            "error": error,
+        }),
+    )
+}
+
 fn limit_call_history(state: &mut State) {
     let max_history = 100;
     if state.call_history.len() > max_history {
@@ -2051,6 +2343,10 @@ fn record_call_failure(
         operator_wallet_id, // Use passed-in operator_wallet_id
         client_id: client_config_opt.map(|c| c.id.clone()),
         provider_name: provider_name_opt,
+        job_id: None,
+        job_status: None,
+        continuation_token: None,
+        protocol_version: None,
     };
     state.call_history.push(record);
     limit_call_history(state);
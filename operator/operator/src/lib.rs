@@ -245,6 +245,11 @@ fn handle_request(
         _ => {
             if pkg == "terminal:sys" {
                 helpers::handle_terminal_debug(our, &body, state, db)?;
+            } else if source.node == our.node
+                && process == format!("provider:hypergrid:{}", constants::PUBLISHER)
+            {
+                // Composite providers on this node pay their remote steps through us
+                http_handlers::handle_local_mcp(our, &body, state, db)?;
             } else {
                 info!("Ignoring unexpected direct request from: {}", source);
             }
@@ -78,6 +78,35 @@ pub struct ProviderDetails {
     pub price_str: String,
     pub wallet_address: String,
 }
+
+/// A provider's answer to a health ping. Providers predating it answer a bare "Ack".
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProviderHealthStatus {
+    pub provider_name: String,
+    #[serde(default)]
+    pub provider_version: String,
+    #[serde(default)]
+    pub protocol_version: u32, // Negotiated by the provider, 0 if it predates versioning
+    #[serde(default)]
+    pub supported_protocol_versions: Vec<u32>,
+    pub price: f64,
+    pub wallet: String,
+    pub available: bool,
+    #[serde(default)]
+    pub unavailable_reason: Option<String>,
+    #[serde(default)]
+    pub configured: bool,
+    #[serde(default)]
+    pub circuit_state: String,
+    #[serde(default)]
+    pub latency_p50_ms: Option<u64>,
+    #[serde(default)]
+    pub latency_p95_ms: Option<u64>,
+    #[serde(default)]
+    pub queue_depth: u32,
+    #[serde(default)]
+    pub payment_modes: Vec<String>,
+}
 // --- End Wallet Management Structs ---
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -119,6 +148,14 @@ pub struct CallRecord {
     pub client_id: Option<String>,
     #[serde(default)]
     pub provider_name: Option<String>, // Human tool name (e.g., haiku-message-answering-machine)
+    #[serde(default)]
+    pub job_id: Option<String>, // Set when an async provider answered with a job ticket
+    #[serde(default)]
+    pub job_status: Option<String>, // Pending, Running, Succeeded or Failed
+    #[serde(default)]
+    pub continuation_token: Option<String>, // Set when the provider chunked an oversized response
+    #[serde(default)]
+    pub protocol_version: Option<u32>, // Negotiated in the health ping, None for providers predating it
 }
 // --- End Call History Structs ---
 
@@ -370,6 +407,26 @@ pub enum McpRequest {
         provider_name: String,
         arguments: Vec<(String, String)>,
     },
+    // Poll an async provider for the result of an earlier call
+    GetJobResult {
+        #[serde(alias = "jobId")]
+        job_id: String,
+    },
+    // Fetch further chunks of an oversized response, already paid for
+    GetResponseChunk {
+        #[serde(alias = "continuationToken")]
+        continuation_token: String,
+        #[serde(alias = "chunkIndex")]
+        chunk_index: u64,
+    },
+}
+
+// MCP request from a composite provider on this node, paying one of its remote steps
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct LocalProviderRequest {
+    // Wallet the provider registered; its downstream payments must come out of it
+    pub payer_wallet: String,
+    pub request: McpRequest,
 }
 
 // NEW: Regular API requests for UI operations - not MCP related
@@ -531,6 +588,10 @@ pub enum ClientRequest {
 pub struct CallProvider {
     pub request: ProviderCall,
 }
+/// Hypergrid wire-protocol versions this operator speaks, oldest first. Version 1 is the
+/// format that predates versioning.
+pub const HYPERGRID_PROTOCOL_VERSIONS: [u32; 1] = [1];
+
 // changed from HashMap to Vec<String>
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct ProviderCall {
@@ -538,6 +599,8 @@ pub struct ProviderCall {
     pub arguments: Vec<(String, String)>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub payment_tx_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol_version: Option<u32>,
 }
 
 /// Structure for storing shim authentication configuration.
@@ -225,6 +225,7 @@ mod api_tests {
                 ("country".to_string(), "US".to_string()),
             ],
             payment_tx_hash: Some("0xabcdef123456".to_string()),
+            protocol_version: Some(1),
         };
         
         let json = serde_json::to_string(&provider_request).unwrap();
@@ -233,6 +234,20 @@ mod api_tests {
         assert_eq!(provider_request.provider_name, deserialized.provider_name);
         assert_eq!(provider_request.arguments, deserialized.arguments);
         assert_eq!(provider_request.payment_tx_hash, deserialized.payment_tx_hash);
+        assert_eq!(provider_request.protocol_version, deserialized.protocol_version);
+    }
+
+    #[test]
+    fn test_unversioned_provider_request_omits_protocol_version() {
+        let provider_request = ProviderCall {
+            provider_name: "weather-service".to_string(),
+            arguments: vec![],
+            payment_tx_hash: None,
+            protocol_version: None,
+        };
+
+        let json = serde_json::to_value(&provider_request).unwrap();
+        assert!(json.get("protocol_version").is_none());
     }
 
     #[test]
@@ -247,4 +262,35 @@ mod api_tests {
             let _deserialized: ClientRequest = serde_json::from_str(&json).unwrap();
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_mcp_request_get_job_result() {
+        // The shim sends camelCase field names
+        let json = r#"{"GetJobResult": {"jobId": "4f1c2a"}}"#;
+        let deserialized: McpRequest = serde_json::from_str(json).unwrap();
+
+        match deserialized {
+            McpRequest::GetJobResult { job_id } => {
+                assert_eq!(job_id, "4f1c2a");
+            }
+            _ => panic!("Expected GetJobResult variant"),
+        }
+    }
+
+    #[test]
+    fn test_mcp_request_get_response_chunk() {
+        let json = r#"{"GetResponseChunk": {"continuationToken": "9b2e", "chunkIndex": 2}}"#;
+        let deserialized: McpRequest = serde_json::from_str(json).unwrap();
+
+        match deserialized {
+            McpRequest::GetResponseChunk {
+                continuation_token,
+                chunk_index,
+            } => {
+                assert_eq!(continuation_token, "9b2e");
+                assert_eq!(chunk_index, 2);
+            }
+            _ => panic!("Expected GetResponseChunk variant"),
+        }
+    }
+}
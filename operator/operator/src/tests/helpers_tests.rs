@@ -261,4 +261,79 @@ mod helpers_tests {
             assert!(!(c >= ' ' && c <= '~'), "Character '{}' should not be printable ASCII", c);
         }
     }
-}
\ No newline at end of file
+
+    fn health_status(price: f64, wallet: &str) -> ProviderHealthStatus {
+        ProviderHealthStatus {
+            provider_name: "weather".to_string(),
+            provider_version: "0.1.0".to_string(),
+            protocol_version: 1,
+            supported_protocol_versions: vec![1],
+            price,
+            wallet: wallet.to_string(),
+            available: true,
+            unavailable_reason: None,
+            configured: true,
+            circuit_state: "closed".to_string(),
+            latency_p50_ms: Some(120),
+            latency_p95_ms: Some(480),
+            queue_depth: 0,
+            payment_modes: vec!["tx_hash".to_string(), "x402".to_string()],
+        }
+    }
+
+    const INDEXED_WALLET: &str = "0xAbC0000000000000000000000000000000000001";
+
+    #[test]
+    fn test_check_provider_health_matching_terms() {
+        let status = health_status(0.01, "0xabc0000000000000000000000000000000000001");
+        assert!(check_provider_health(&status, "0.010", INDEXED_WALLET).is_ok());
+    }
+
+    #[test]
+    fn test_check_provider_health_price_mismatch() {
+        let status = health_status(0.02, INDEXED_WALLET);
+        let err = check_provider_health(&status, "0.01", INDEXED_WALLET).unwrap_err();
+        assert!(err.contains("price"));
+    }
+
+    #[test]
+    fn test_check_provider_health_wallet_mismatch() {
+        let status = health_status(0.01, "0x0000000000000000000000000000000000000002");
+        let err = check_provider_health(&status, "0.01", INDEXED_WALLET).unwrap_err();
+        assert!(err.contains("wallet"));
+    }
+
+    #[test]
+    fn test_check_provider_health_unavailable() {
+        let mut status = health_status(0.01, INDEXED_WALLET);
+        status.available = false;
+        status.unavailable_reason = Some("upstream is failing".to_string());
+        let err = check_provider_health(&status, "0.01", INDEXED_WALLET).unwrap_err();
+        assert!(err.contains("upstream is failing"));
+    }
+
+    #[test]
+    fn test_provider_health_status_tolerates_missing_fields() {
+        let status: ProviderHealthStatus = serde_json::from_value(serde_json::json!({
+            "provider_name": "weather",
+            "price": 0.01,
+            "wallet": INDEXED_WALLET,
+            "available": true,
+        }))
+        .unwrap();
+        assert!(status.payment_modes.is_empty());
+        assert_eq!(status.latency_p95_ms, None);
+        assert_eq!(negotiated_protocol_version(&status), Ok(None));
+    }
+
+    #[test]
+    fn test_negotiated_protocol_version() {
+        let status = health_status(0.01, INDEXED_WALLET);
+        assert_eq!(negotiated_protocol_version(&status), Ok(Some(1)));
+
+        let mut newer = health_status(0.01, INDEXED_WALLET);
+        newer.protocol_version = 99;
+        let err = negotiated_protocol_version(&newer).unwrap_err();
+        assert!(err.contains("update the operator"));
+    }
+}
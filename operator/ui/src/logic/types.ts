@@ -90,6 +90,10 @@ export interface CallRecord {
    payment_result?: PaymentAttemptResult | null;
    duration_ms: number;
    operator_wallet_id?: string | null; 
+   job_id?: string | null; // Set for calls to async providers
+   job_status?: 'Pending' | 'Running' | 'Succeeded' | 'Failed' | null;
+   continuation_token?: string | null; // Set when the response was chunked
+   protocol_version?: number | null; // Negotiated in the health ping, null for older providers
 }
 
 // Exported for use in ActiveAccountDisplay.tsx
@@ -0,0 +1,77 @@
+use crate::util::now_unix_secs;
+use base64ct::{Base64UrlUnpadded, Encoding};
+use hyperware_process_lib::{net, our};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Header carrying the signed caller attestation on upstream requests
+pub const ATTESTATION_HEADER: &str = "X-Hypergrid-Attestation";
+
+/// Attestation format version, bumped on any change to the signed payload
+const ATTESTATION_VERSION: u8 = 1;
+
+/// Seconds an attestation stays valid; upstreams should reject older ones
+const ATTESTATION_TTL_SECS: u64 = 60;
+
+/// Who a provider call is made for, attested to the upstream API
+#[derive(Clone, Debug)]
+pub struct CallerContext {
+    // Calling node, or the payer address for x402 calls
+    pub caller: String,
+    pub payment_tx_hash: Option<String>,
+}
+
+impl CallerContext {
+    /// Calls the provider makes on its own behalf, e.g. validation runs
+    pub fn own_node() -> Self {
+        CallerContext {
+            caller: our().node.to_string(),
+            payment_tx_hash: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AttestationPayload<'a> {
+    v: u8,
+    iss: String, // Address of the signing provider process, e.g. "node.os@provider:hypergrid:ware.hypr"
+    provider_name: &'a str,
+    caller: &'a str,
+    tx_hash: Option<&'a str>,
+    iat: u64,
+    exp: u64,
+    body_sha256: String, // Hex
+}
+
+/// Build the header value `<payload>.<signature>`, both base64url without padding.
+/// The signature comes from the node's networking key, over `iss` followed by the payload bytes.
+pub fn attestation_header(
+    provider_name: &str,
+    caller: &CallerContext,
+    body: &[u8],
+) -> Result<String, String> {
+    let iat = now_unix_secs();
+    let payload = serde_json::to_vec(&AttestationPayload {
+        v: ATTESTATION_VERSION,
+        iss: our().to_string(),
+        provider_name,
+        caller: &caller.caller,
+        tx_hash: caller.payment_tx_hash.as_deref(),
+        iat,
+        exp: iat + ATTESTATION_TTL_SECS,
+        body_sha256: hex::encode(Sha256::digest(body)),
+    })
+    .map_err(|e| format!("Failed to serialize attestation: {}", e))?;
+
+    let signature =
+        net::sign(payload.clone()).map_err(|e| format!("Failed to sign attestation: {:?}", e))?;
+
+    Ok(format!("{}.{}", base64url(&payload), base64url(&signature)))
+}
+
+fn base64url(bytes: &[u8]) -> String {
+    let mut buf = vec![0u8; Base64UrlUnpadded::encoded_len(bytes)];
+    Base64UrlUnpadded::encode(bytes, &mut buf)
+        .map(str::to_string)
+        .unwrap_or_default()
+}
@@ -0,0 +1,269 @@
+use crate::events::{publish_event, ProviderEvent, WsSubscriptions};
+use crate::util::now_unix_secs;
+use crate::CircuitBreakerConfig;
+use hyperware_process_lib::logging::{info, warn};
+use std::collections::{HashMap, VecDeque};
+
+/// Used for providers that don't set `circuit_breaker`
+pub const DEFAULT_CIRCUIT_BREAKER: CircuitBreakerConfig = CircuitBreakerConfig {
+    consecutive_failures: 5,
+    failure_rate_threshold: 0.5,
+    window_size: 20,
+    open_seconds: 60,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BreakerState {
+    Closed,
+    // Calls are refused until the deadline (unix seconds)
+    Open { until: u64 },
+    // A single probe call is running; its outcome closes or reopens the breaker
+    HalfOpen,
+}
+
+/// Per-provider breaker over upstream call outcomes
+#[derive(Clone, Debug)]
+pub struct CircuitBreaker {
+    pub state: BreakerState,
+    consecutive_failures: u32,
+    recent: VecDeque<bool>, // true for failures, newest last
+}
+
+/// Breakers keyed by provider name, kept in memory only
+pub type CircuitBreakers = HashMap<String, CircuitBreaker>;
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        CircuitBreaker {
+            state: BreakerState::Closed,
+            consecutive_failures: 0,
+            recent: VecDeque::new(),
+        }
+    }
+}
+
+impl CircuitBreaker {
+    /// Whether a new call would be let through at `now`
+    pub fn is_available(&self, now: u64) -> bool {
+        match self.state {
+            BreakerState::Closed => true,
+            BreakerState::Open { until } => now >= until,
+            BreakerState::HalfOpen => false,
+        }
+    }
+
+    /// Mark a paid call as started; past the open deadline it becomes the half-open probe
+    pub fn begin_call(&mut self, now: u64) {
+        if let BreakerState::Open { until } = self.state {
+            if now >= until {
+                self.state = BreakerState::HalfOpen;
+            }
+        }
+    }
+
+    /// Record the outcome of a started call
+    pub fn record(&mut self, success: bool, config: &CircuitBreakerConfig, now: u64) {
+        let window_size = config.window_size.max(1) as usize;
+        self.recent.push_back(!success);
+        while self.recent.len() > window_size {
+            self.recent.pop_front();
+        }
+
+        if success {
+            // Any success, the half-open probe's included, closes the breaker
+            self.consecutive_failures = 0;
+            self.state = BreakerState::Closed;
+            return;
+        }
+
+        self.consecutive_failures += 1;
+        let failures = self.recent.iter().filter(|failed| **failed).count();
+        let failure_rate = failures as f64 / self.recent.len() as f64;
+        let trip = self.consecutive_failures >= config.consecutive_failures.max(1)
+            || (self.recent.len() == window_size && failure_rate > config.failure_rate_threshold);
+
+        // A failed probe reopens straight away
+        if trip || self.state == BreakerState::HalfOpen {
+            self.state = BreakerState::Open { until: now + config.open_seconds };
+            self.recent.clear();
+        }
+    }
+
+    /// Short label for logs, events and health pings
+    pub fn label(&self) -> &'static str {
+        match self.state {
+            BreakerState::Closed => "closed",
+            BreakerState::Open { .. } => "open",
+            BreakerState::HalfOpen => "half_open",
+        }
+    }
+}
+
+/// Refuse calls while the provider's breaker is open. Checked before payment validation,
+/// so a refused call leaves its tx hash unspent.
+pub fn check_circuit(breakers: &CircuitBreakers, provider_name: &str) -> Result<(), String> {
+    let Some(breaker) = breakers.get(provider_name) else {
+        return Ok(());
+    };
+    let now = now_unix_secs();
+    if breaker.is_available(now) {
+        return Ok(());
+    }
+    let retry_after = match breaker.state {
+        BreakerState::Open { until } => until.saturating_sub(now),
+        _ => 1,
+    };
+    Err(format!(
+        "Provider '{}' is temporarily unavailable: upstream is failing, retry in {} s",
+        provider_name, retry_after
+    ))
+}
+
+pub fn begin_circuit_call(breakers: &mut CircuitBreakers, provider_name: &str) {
+    if let Some(breaker) = breakers.get_mut(provider_name) {
+        breaker.begin_call(now_unix_secs());
+    }
+}
+
+/// Feed a call outcome to the provider's breaker, announcing opens and closes
+pub fn record_circuit_outcome(
+    breakers: &mut CircuitBreakers,
+    subscriptions: &WsSubscriptions,
+    provider_name: &str,
+    config: &CircuitBreakerConfig,
+    success: bool,
+) {
+    let breaker = breakers.entry(provider_name.to_string()).or_default();
+    let previous = breaker.label();
+    breaker.record(success, config, now_unix_secs());
+    let current = breaker.label();
+    if previous == current {
+        return;
+    }
+
+    if breaker.state == BreakerState::Closed {
+        info!("circuit_breaker_closed: provider={}", provider_name);
+    } else {
+        warn!(
+            "circuit_breaker_opened: provider={}, open_seconds={}",
+            provider_name, config.open_seconds
+        );
+    }
+    publish_event(
+        subscriptions,
+        ProviderEvent::CircuitBreakerChanged {
+            provider_name: provider_name.to_string(),
+            state: current.to_string(),
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            consecutive_failures: 3,
+            failure_rate_threshold: 0.5,
+            window_size: 4,
+            open_seconds: 60,
+        }
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let mut breaker = CircuitBreaker::default();
+        breaker.record(false, &config(), 100);
+        breaker.record(false, &config(), 100);
+        assert_eq!(breaker.state, BreakerState::Closed);
+        breaker.record(false, &config(), 100);
+        assert_eq!(breaker.state, BreakerState::Open { until: 160 });
+        assert!(!breaker.is_available(159));
+        assert!(breaker.is_available(160));
+    }
+
+    #[test]
+    fn opens_on_failure_rate_once_the_window_is_full() {
+        let mut breaker = CircuitBreaker::default();
+        for success in [false, false, true] {
+            breaker.record(success, &config(), 100);
+        }
+        assert_eq!(breaker.state, BreakerState::Closed);
+        // 3 of the last 4 calls failed, but only one in a row
+        breaker.record(false, &config(), 100);
+        assert_eq!(breaker.state, BreakerState::Open { until: 160 });
+    }
+
+    #[test]
+    fn success_resets_the_consecutive_count() {
+        let wide_window = CircuitBreakerConfig { window_size: 10, ..config() };
+        let mut breaker = CircuitBreaker::default();
+        for success in [false, false, true, false, false] {
+            breaker.record(success, &wide_window, 100);
+        }
+        assert_eq!(breaker.label(), "closed");
+        breaker.record(false, &wide_window, 100);
+        assert_eq!(breaker.label(), "open");
+
+        // Exactly half the window failing stays under the threshold
+        let mut breaker = CircuitBreaker::default();
+        for success in [false, true, true, false, true, false] {
+            breaker.record(success, &config(), 100);
+        }
+        assert_eq!(breaker.label(), "closed");
+    }
+
+    #[test]
+    fn half_open_probe_closes_or_reopens() {
+        let mut breaker = CircuitBreaker::default();
+        for _ in 0..3 {
+            breaker.record(false, &config(), 100);
+        }
+        breaker.begin_call(130);
+        assert_eq!(breaker.label(), "open");
+        breaker.begin_call(160);
+        assert_eq!(breaker.state, BreakerState::HalfOpen);
+        assert!(!breaker.is_available(160));
+
+        // A failed probe reopens for another full period
+        breaker.record(false, &config(), 170);
+        assert_eq!(breaker.state, BreakerState::Open { until: 230 });
+
+        breaker.begin_call(230);
+        breaker.record(true, &config(), 231);
+        assert_eq!(breaker.state, BreakerState::Closed);
+    }
+
+    #[test]
+    fn zero_settings_are_clamped() {
+        let config = CircuitBreakerConfig {
+            consecutive_failures: 0,
+            failure_rate_threshold: 1.0,
+            window_size: 0,
+            open_seconds: 10,
+        };
+        let mut breaker = CircuitBreaker::default();
+        breaker.record(false, &config, 100);
+        assert_eq!(breaker.state, BreakerState::Open { until: 110 });
+    }
+
+    #[test]
+    fn check_circuit_refuses_only_unavailable_providers() {
+        let mut breakers = CircuitBreakers::new();
+        assert!(check_circuit(&breakers, "weather").is_ok());
+
+        let now = now_unix_secs();
+        let mut breaker = CircuitBreaker::default();
+        for _ in 0..3 {
+            breaker.record(false, &config(), now);
+        }
+        breakers.insert("weather".to_string(), breaker);
+        let refused = check_circuit(&breakers, "weather").unwrap_err();
+        assert!(refused.contains("retry in"), "{}", refused);
+        assert!(check_circuit(&breakers, "news").is_ok());
+
+        breakers.get_mut("weather").unwrap().state = BreakerState::HalfOpen;
+        assert!(check_circuit(&breakers, "weather").unwrap_err().contains("retry in 1 s"));
+    }
+}
@@ -0,0 +1,221 @@
+use crate::util::now_unix_secs;
+use crate::RegisteredProvider;
+use hyperware_process_lib::logging::debug;
+use serde_json::json;
+use std::collections::HashMap;
+
+/// How long the remaining chunks of an oversized response can be redeemed, in seconds
+pub const CONTINUATION_TTL_SECS: u64 = 600;
+
+/// Smallest `max_response_bytes` a provider may declare
+pub const MIN_RESPONSE_CHUNK_BYTES: u64 = 1024;
+
+/// Chunks of an oversized response, already paid for
+#[derive(Clone, Debug)]
+pub struct PendingResponse {
+    pub provider_name: String,
+    // Node allowed to redeem the chunks; None for x402 calls, where the token is a bearer secret
+    pub caller_node: Option<String>,
+    pub chunks: Vec<String>,
+    pub total_bytes: usize,
+    pub expires_at: u64, // Unix seconds
+}
+
+/// Oversized responses awaiting redemption, keyed by continuation token. Held in memory
+/// only: tokens stop working when the provider restarts, and callers have to call again.
+pub type ContinuationStore = HashMap<String, PendingResponse>;
+
+/// Bytes a character takes once escaped inside a JSON string
+fn escaped_len(c: char) -> usize {
+    match c {
+        '"' | '\\' | '\n' | '\r' | '\t' | '\u{8}' | '\u{c}' => 2,
+        c if (c as u32) < 0x20 => 6, // \u00XX
+        c => c.len_utf8(),
+    }
+}
+
+/// Envelope bytes around the chunk itself, with every number at its widest
+fn envelope_overhead() -> usize {
+    let widest = usize::MAX;
+    json!({
+        "chunk": "",
+        "chunk_index": widest,
+        "total_chunks": widest,
+        "total_bytes": widest,
+        "continuation_token": uuid::Uuid::nil().to_string(),
+        "next_chunk_index": widest,
+    })
+    .to_string()
+    .len()
+}
+
+/// Split `response` so each chunk's envelope, JSON escaping included, fits in `max_bytes`.
+/// Chunks never end inside a UTF-8 character.
+pub fn split_response(response: &str, max_bytes: usize) -> Vec<String> {
+    // At least one escaped character per chunk, so splitting always progresses
+    let budget = max_bytes.saturating_sub(envelope_overhead()).max(6);
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    let mut chunk_len = 0;
+    for c in response.chars() {
+        let len = escaped_len(c);
+        if chunk_len + len > budget {
+            chunks.push(std::mem::take(&mut chunk));
+            chunk_len = 0;
+        }
+        chunk.push(c);
+        chunk_len += len;
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+/// Return `response` unchanged if it fits the provider's limit, otherwise store it in chunks
+/// and return the first chunk with a continuation token for the others
+pub fn limit_response(
+    store: &mut ContinuationStore,
+    provider: &RegisteredProvider,
+    caller_node: Option<String>,
+    response: String,
+    ttl_secs: u64,
+) -> String {
+    let Some(max_bytes) = provider.max_response_bytes else {
+        return response;
+    };
+    if response.len() as u64 <= max_bytes {
+        return response;
+    }
+
+    let now = now_unix_secs();
+    store.retain(|_, pending| pending.expires_at > now);
+
+    let token = uuid::Uuid::new_v4().to_string();
+    let pending = PendingResponse {
+        provider_name: provider.provider_name.clone(),
+        caller_node,
+        chunks: split_response(&response, max_bytes as usize),
+        total_bytes: response.len(),
+        expires_at: now + ttl_secs,
+    };
+    debug!(
+        "Chunked {} byte response of '{}' into {} chunks",
+        pending.total_bytes,
+        pending.provider_name,
+        pending.chunks.len()
+    );
+    let first = chunk_envelope(&token, &pending, 0);
+    store.insert(token, pending);
+    first
+}
+
+/// Chunk `chunk_index` of a stored response. `caller_node` is None for x402 redemption.
+pub fn redeem_chunk(
+    store: &mut ContinuationStore,
+    token: &str,
+    chunk_index: u64,
+    caller_node: Option<&str>,
+) -> Result<String, String> {
+    let now = now_unix_secs();
+    store.retain(|_, pending| pending.expires_at > now);
+    let pending = store
+        .get(token)
+        .ok_or_else(|| "Continuation token not found or expired".to_string())?;
+    if pending.caller_node.as_deref() != caller_node {
+        return Err("Continuation token was issued to another caller".to_string());
+    }
+    if chunk_index as usize >= pending.chunks.len() {
+        return Err(format!(
+            "Chunk {} out of range, response has {} chunks",
+            chunk_index,
+            pending.chunks.len()
+        ));
+    }
+    Ok(chunk_envelope(token, pending, chunk_index as usize))
+}
+
+fn chunk_envelope(token: &str, pending: &PendingResponse, index: usize) -> String {
+    let next_chunk_index = (index + 1 < pending.chunks.len()).then(|| index + 1);
+    json!({
+        "chunk": pending.chunks[index],
+        "chunk_index": index,
+        "total_chunks": pending.chunks.len(),
+        "total_bytes": pending.total_bytes,
+        "continuation_token": token,
+        "next_chunk_index": next_chunk_index,
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending(response: &str, max_bytes: usize, caller_node: Option<&str>, expires_at: u64) -> PendingResponse {
+        PendingResponse {
+            provider_name: "weather".to_string(),
+            caller_node: caller_node.map(str::to_string),
+            chunks: split_response(response, max_bytes),
+            total_bytes: response.len(),
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn escaped_chunks_fit_the_limit() {
+        let max_bytes = MIN_RESPONSE_CHUNK_BYTES as usize;
+        // Quotes, control characters and multi-byte characters all grow or span bytes
+        let response = "{\"line\":\"a\\\"b\"}\n\u{1}é🌧".repeat(200);
+        let pending = pending(&response, max_bytes, None, u64::MAX);
+        assert!(pending.chunks.len() > 1);
+        assert_eq!(pending.chunks.concat(), response);
+        for index in 0..pending.chunks.len() {
+            let envelope = chunk_envelope("00000000-0000-0000-0000-000000000000", &pending, index);
+            assert!(envelope.len() <= max_bytes, "chunk {} is {} bytes", index, envelope.len());
+        }
+    }
+
+    #[test]
+    fn tiny_limits_still_progress() {
+        let chunks = split_response("\u{1}\u{1}\u{1}", 1);
+        assert_eq!(chunks, vec!["\u{1}".to_string(); 3]);
+        assert!(split_response("", 1).is_empty());
+    }
+
+    #[test]
+    fn chunks_are_redeemed_by_their_caller_until_expiry() {
+        let response = "x".repeat(3000);
+        let mut store = ContinuationStore::new();
+        store.insert("live".to_string(), pending(&response, 1024, Some("alice.os"), u64::MAX));
+        store.insert("stale".to_string(), pending(&response, 1024, Some("alice.os"), 0));
+
+        let chunk: serde_json::Value =
+            serde_json::from_str(&redeem_chunk(&mut store, "live", 1, Some("alice.os")).unwrap()).unwrap();
+        assert_eq!(chunk["chunk_index"], 1);
+        assert_eq!(chunk["total_bytes"], 3000);
+
+        assert!(redeem_chunk(&mut store, "live", 1, Some("bob.os")).is_err());
+        assert!(redeem_chunk(&mut store, "live", 1, None).is_err());
+        assert!(redeem_chunk(&mut store, "live", 99, Some("alice.os")).is_err());
+        assert!(redeem_chunk(&mut store, "stale", 0, Some("alice.os")).is_err());
+        assert!(!store.contains_key("stale"));
+    }
+
+    #[test]
+    fn envelopes_chain_to_the_last_chunk() {
+        let response = "y".repeat(2500);
+        let pending = pending(&response, 1024, None, u64::MAX);
+        let mut rebuilt = String::new();
+        let mut next = Some(0);
+        while let Some(index) = next {
+            let envelope: serde_json::Value =
+                serde_json::from_str(&chunk_envelope("token", &pending, index)).unwrap();
+            assert_eq!(envelope["chunk_index"], index);
+            assert_eq!(envelope["total_chunks"], pending.chunks.len());
+            rebuilt.push_str(envelope["chunk"].as_str().unwrap());
+            next = envelope["next_chunk_index"].as_u64().map(|index| index as usize);
+        }
+        assert_eq!(rebuilt, response);
+    }
+}
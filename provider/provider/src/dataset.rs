@@ -0,0 +1,312 @@
+use crate::{DatasetInfo, DatasetSource};
+use hyperware_process_lib::{
+    logging::debug,
+    our,
+    vfs::{create_file, open_dir, open_file, remove_file},
+};
+use serde_json::{json, Map, Number, Value};
+use std::cmp::Ordering;
+
+/// Caller arguments reserved by dataset providers
+pub const DATASET_ARGUMENTS: [(&str, &str); 5] = [
+    ("filter", "Condition like `price>=10`, `city=Paris` or `name~=ann`; repeat to AND several"),
+    ("select", "Comma-separated columns to return"),
+    ("sort", "Comma-separated columns, prefix with `-` for descending"),
+    ("limit", "Rows per page"),
+    ("offset", "Rows to skip, use `next_offset` from the previous page"),
+];
+
+type Row = Map<String, Value>;
+
+enum DatasetFormat {
+    Csv,
+    Json,
+    Jsonl,
+}
+
+/// Path of the drive created in `init_vfs_drive`
+fn providers_drive_path() -> String {
+    format!("/{}/providers", our().package_id())
+}
+
+fn datasets_dir(drive_path: &str) -> String {
+    format!("{}/datasets", drive_path)
+}
+
+fn dataset_format(file_name: &str) -> Result<DatasetFormat, String> {
+    if file_name.is_empty() || file_name.contains('/') || file_name.contains("..") {
+        return Err(format!("Invalid dataset file name: {}", file_name));
+    }
+    match file_name.rsplit_once('.').map(|(_, ext)| ext.to_lowercase()) {
+        Some(ext) if ext == "csv" => Ok(DatasetFormat::Csv),
+        Some(ext) if ext == "json" => Ok(DatasetFormat::Json),
+        Some(ext) if ext == "jsonl" => Ok(DatasetFormat::Jsonl),
+        _ => Err(format!("Dataset '{}' must be a .csv, .json or .jsonl file", file_name)),
+    }
+}
+
+/// Parse and store an uploaded dataset, replacing any file with the same name
+pub fn save_dataset(drive_path: &str, file_name: &str, content: &str) -> Result<DatasetInfo, String> {
+    let rows = parse_rows(&dataset_format(file_name)?, content.as_bytes())?;
+
+    open_dir(&datasets_dir(drive_path), true, None).map_err(|e| e.to_string())?;
+    let file = create_file(&format!("{}/{}", datasets_dir(drive_path), file_name), None)
+        .map_err(|e| e.to_string())?;
+    file.write(content.as_bytes()).map_err(|e| e.to_string())?;
+
+    debug!("Stored dataset '{}' with {} rows", file_name, rows.len());
+    Ok(dataset_info(file_name, &rows, content.len()))
+}
+
+pub fn list_datasets(drive_path: &str) -> Result<Vec<DatasetInfo>, String> {
+    let dir = open_dir(&datasets_dir(drive_path), true, None).map_err(|e| e.to_string())?;
+    let mut datasets = Vec::new();
+    for entry in dir.read().map_err(|e| e.to_string())? {
+        let file_name = entry.path.rsplit('/').next().unwrap_or(&entry.path).to_string();
+        let Ok(format) = dataset_format(&file_name) else {
+            continue;
+        };
+        let bytes = read_dataset(drive_path, &file_name)?;
+        let rows = parse_rows(&format, &bytes)?;
+        datasets.push(dataset_info(&file_name, &rows, bytes.len()));
+    }
+    Ok(datasets)
+}
+
+pub fn delete_dataset(drive_path: &str, file_name: &str) -> Result<(), String> {
+    dataset_format(file_name)?;
+    remove_file(&format!("{}/{}", datasets_dir(drive_path), file_name), None)
+        .map_err(|e| format!("Failed to delete dataset '{}': {}", file_name, e))
+}
+
+/// Parsed rows of an uploaded dataset
+pub fn load_dataset_rows(drive_path: &str, file_name: &str) -> Result<Vec<Row>, String> {
+    let format = dataset_format(file_name)?;
+    parse_rows(&format, &read_dataset(drive_path, file_name)?)
+}
+
+fn read_dataset(drive_path: &str, file_name: &str) -> Result<Vec<u8>, String> {
+    open_file(&format!("{}/{}", datasets_dir(drive_path), file_name), false, None)
+        .map_err(|_| format!("Dataset '{}' not found", file_name))?
+        .read()
+        .map_err(|e| e.to_string())
+}
+
+fn dataset_info(file_name: &str, rows: &[Row], size_bytes: usize) -> DatasetInfo {
+    let mut columns: Vec<String> = Vec::new();
+    for row in rows {
+        for key in row.keys() {
+            if !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+    }
+    DatasetInfo {
+        file_name: file_name.to_string(),
+        row_count: rows.len() as u64,
+        columns,
+        size_bytes: size_bytes as u64,
+    }
+}
+
+fn parse_rows(format: &DatasetFormat, bytes: &[u8]) -> Result<Vec<Row>, String> {
+    let as_row = |value: Value, position: usize| match value {
+        Value::Object(row) => Ok(row),
+        _ => Err(format!("Row {} is not a JSON object", position + 1)),
+    };
+
+    match format {
+        DatasetFormat::Csv => {
+            let mut reader = csv::Reader::from_reader(bytes);
+            let headers = reader.headers().map_err(|e| format!("Invalid CSV header: {}", e))?.clone();
+            reader
+                .records()
+                .enumerate()
+                .map(|(position, record)| {
+                    let record = record.map_err(|e| format!("Invalid CSV row {}: {}", position + 1, e))?;
+                    Ok(headers
+                        .iter()
+                        .zip(record.iter())
+                        .map(|(header, field)| (header.to_string(), csv_value(field)))
+                        .collect())
+                })
+                .collect()
+        }
+        DatasetFormat::Json => match serde_json::from_slice::<Value>(bytes) {
+            Ok(Value::Array(items)) => items
+                .into_iter()
+                .enumerate()
+                .map(|(position, item)| as_row(item, position))
+                .collect(),
+            Ok(_) => Err("JSON dataset must be an array of objects".to_string()),
+            Err(e) => Err(format!("Invalid JSON dataset: {}", e)),
+        },
+        DatasetFormat::Jsonl => String::from_utf8_lossy(bytes)
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(position, line)| {
+                let value = serde_json::from_str(line)
+                    .map_err(|e| format!("Invalid JSONL row {}: {}", position + 1, e))?;
+                as_row(value, position)
+            })
+            .collect(),
+    }
+}
+
+/// CSV cells are typed loosely: numbers become numbers, empty cells null
+fn csv_value(field: &str) -> Value {
+    if field.is_empty() {
+        return Value::Null;
+    }
+    if let Ok(integer) = field.parse::<i64>() {
+        return Value::from(integer);
+    }
+    field
+        .parse::<f64>()
+        .ok()
+        .and_then(Number::from_f64)
+        .map(Value::Number)
+        .unwrap_or_else(|| Value::String(field.to_string()))
+}
+
+/// Serve one page of a dataset, filtered, sorted and projected per the caller's arguments
+pub fn query_dataset(source: &DatasetSource, args: &Vec<(String, String)>) -> Result<String, String> {
+    let format = dataset_format(&source.file_name)?;
+    let bytes = read_dataset(&providers_drive_path(), &source.file_name)?;
+    let mut rows = parse_rows(&format, &bytes)?;
+
+    let arg = |name: &str| args.iter().find(|(k, _)| k == name).map(|(_, v)| v.trim());
+
+    for (_, condition) in args.iter().filter(|(k, _)| k == "filter") {
+        let filter = Filter::parse(condition)?;
+        rows.retain(|row| filter.matches(row));
+    }
+
+    if let Some(sort) = arg("sort").filter(|s| !s.is_empty()) {
+        let keys: Vec<(&str, bool)> = sort
+            .split(',')
+            .map(str::trim)
+            .map(|key| match key.strip_prefix('-') {
+                Some(column) => (column, true),
+                None => (key, false),
+            })
+            .collect();
+        rows.sort_by(|a, b| {
+            keys.iter()
+                .map(|(column, descending)| {
+                    let ordering = compare_values(a.get(*column), b.get(*column));
+                    if *descending {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                })
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+    }
+
+    let limit = match arg("limit") {
+        Some(limit) => limit.parse::<u64>().map_err(|_| format!("Invalid limit: {}", limit))?,
+        None => source.default_limit,
+    }
+    .clamp(1, source.max_limit.max(1)) as usize;
+    let offset = match arg("offset") {
+        Some(offset) => offset.parse::<usize>().map_err(|_| format!("Invalid offset: {}", offset))?,
+        None => 0,
+    };
+
+    let total_matched = rows.len();
+    let selected: Option<Vec<&str>> = arg("select")
+        .filter(|s| !s.is_empty())
+        .map(|s| s.split(',').map(str::trim).collect());
+    let page: Vec<Value> = rows
+        .into_iter()
+        .skip(offset)
+        .take(limit)
+        .map(|row| match &selected {
+            Some(columns) => Value::Object(
+                columns
+                    .iter()
+                    .map(|c| (c.to_string(), row.get(*c).cloned().unwrap_or(Value::Null)))
+                    .collect(),
+            ),
+            None => Value::Object(row),
+        })
+        .collect();
+
+    let next_offset = (offset + page.len() < total_matched).then(|| offset + page.len());
+    Ok(json!({
+        "rows": page,
+        "total_matched": total_matched,
+        "offset": offset,
+        "limit": limit,
+        "next_offset": next_offset,
+    })
+    .to_string())
+}
+
+struct Filter {
+    column: String,
+    op: &'static str,
+    value: String,
+}
+
+impl Filter {
+    // Longest operators first so ">=" is not read as ">"
+    const OPERATORS: [&'static str; 7] = [">=", "<=", "!=", "~=", "=", ">", "<"];
+
+    fn parse(condition: &str) -> Result<Self, String> {
+        Self::OPERATORS
+            .iter()
+            .filter_map(|op| condition.find(op).map(|index| (index, *op)))
+            .min_by_key(|(index, op)| (*index, std::cmp::Reverse(op.len())))
+            .map(|(index, op)| Filter {
+                column: condition[..index].trim().to_string(),
+                op,
+                value: condition[index + op.len()..].trim().to_string(),
+            })
+            .filter(|f| !f.column.is_empty())
+            .ok_or_else(|| format!("Invalid filter: {}", condition))
+    }
+
+    fn matches(&self, row: &Row) -> bool {
+        let cell = row.get(&self.column);
+        if self.op == "~=" {
+            return cell
+                .map(|v| value_text(v).to_lowercase().contains(&self.value.to_lowercase()))
+                .unwrap_or(false);
+        }
+        let ordering = compare_values(cell, Some(&csv_value(&self.value)));
+        match self.op {
+            "=" => ordering == Ordering::Equal,
+            "!=" => ordering != Ordering::Equal,
+            ">" => ordering == Ordering::Greater,
+            ">=" => ordering != Ordering::Less,
+            "<" => ordering == Ordering::Less,
+            "<=" => ordering != Ordering::Greater,
+            _ => false,
+        }
+    }
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Numbers compare numerically, everything else as text; missing and null sort first
+fn compare_values(a: Option<&Value>, b: Option<&Value>) -> Ordering {
+    match (a.filter(|v| !v.is_null()), b.filter(|v| !v.is_null())) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Less,
+        (Some(_), None) => Ordering::Greater,
+        (Some(a), Some(b)) => match (a.as_f64(), b.as_f64()) {
+            (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+            _ => value_text(a).cmp(&value_text(b)),
+        },
+    }
+}
@@ -0,0 +1,151 @@
+use crate::util::now_unix_secs;
+use hyperware_process_lib::{
+    http::server::WsMessageType,
+    hyperapp::get_server,
+    logging::{debug, warn},
+    LazyLoadBlob,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Structured events pushed to dashboard clients over `/ws`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ProviderEvent {
+    CallStarted {
+        provider_name: String,
+        source_node: String,
+        has_tx_hash: bool,
+    },
+    CallSucceeded {
+        provider_name: String,
+        source_node: String,
+        attempt: usize,
+        duration_ms: u128,
+        response_size_bytes: usize,
+    },
+    CallFailed {
+        provider_name: String,
+        source_node: String,
+        error: String,
+    },
+    PaymentValidated {
+        provider_name: String,
+        source_node: String,
+        payment_method: String, // "tx_hash", "access_pass" or "x402"
+        tx_hash: Option<String>,
+    },
+    PaymentRejected {
+        provider_name: String,
+        source_node: String,
+        payment_method: String,
+        reason: String,
+    },
+    X402Settled {
+        provider_name: String,
+        payer: String,
+        success: bool,
+        transaction: Option<String>,
+        error_reason: Option<String>,
+    },
+    ProviderRegistered {
+        provider_name: String,
+    },
+    ProviderUpdated {
+        previous_name: String,
+        provider_name: String,
+    },
+    CircuitBreakerChanged {
+        provider_name: String,
+        state: String, // "open", "half_open" or "closed"
+    },
+    SyncDrift {
+        missing_from_index: Vec<String>,
+        mismatched: Vec<String>,
+    },
+}
+
+impl ProviderEvent {
+    /// Provider the event is about; None for events that concern every provider
+    pub fn provider_name(&self) -> Option<&str> {
+        match self {
+            ProviderEvent::CallStarted { provider_name, .. }
+            | ProviderEvent::CallSucceeded { provider_name, .. }
+            | ProviderEvent::CallFailed { provider_name, .. }
+            | ProviderEvent::PaymentValidated { provider_name, .. }
+            | ProviderEvent::PaymentRejected { provider_name, .. }
+            | ProviderEvent::X402Settled { provider_name, .. }
+            | ProviderEvent::CircuitBreakerChanged { provider_name, .. }
+            | ProviderEvent::ProviderRegistered { provider_name }
+            | ProviderEvent::ProviderUpdated { provider_name, .. } => Some(provider_name),
+            ProviderEvent::SyncDrift { .. } => None,
+        }
+    }
+}
+
+/// Envelope sent on the wire
+#[derive(Serialize)]
+struct EventMessage<'a> {
+    timestamp: u64,
+    event: &'a ProviderEvent,
+}
+
+/// Messages clients send on `/ws` to manage their subscription
+#[derive(Clone, Debug, Deserialize)]
+pub enum WsClientMessage {
+    // Empty list subscribes to every provider
+    Subscribe { provider_names: Vec<String> },
+    Unsubscribe,
+}
+
+/// Provider-name filter for an open `/ws` channel; empty means all providers
+pub type WsSubscriptions = HashMap<u32, Vec<String>>;
+
+/// Apply a client message received on `channel_id`
+pub fn handle_ws_client_message(subscriptions: &mut WsSubscriptions, channel_id: u32, bytes: &[u8]) {
+    match serde_json::from_slice::<WsClientMessage>(bytes) {
+        Ok(WsClientMessage::Subscribe { provider_names }) => {
+            debug!("ws channel {} subscribed to {:?}", channel_id, provider_names);
+            subscriptions.insert(channel_id, provider_names);
+        }
+        Ok(WsClientMessage::Unsubscribe) => {
+            debug!("ws channel {} unsubscribed", channel_id);
+            subscriptions.remove(&channel_id);
+        }
+        Err(e) => warn!("Ignoring malformed ws message on channel {}: {}", channel_id, e),
+    }
+}
+
+/// Push `event` to every subscribed channel whose filter matches
+pub fn publish_event(subscriptions: &WsSubscriptions, event: ProviderEvent) {
+    if subscriptions.is_empty() {
+        return;
+    }
+    let Some(server) = get_server() else {
+        return;
+    };
+    let bytes = match serde_json::to_vec(&EventMessage {
+        timestamp: now_unix_secs(),
+        event: &event,
+    }) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Failed to serialize provider event: {}", e);
+            return;
+        }
+    };
+
+    for (channel_id, filter) in subscriptions {
+        let matches = match event.provider_name() {
+            Some(name) => filter.is_empty() || filter.iter().any(|f| f == name),
+            None => true,
+        };
+        if matches {
+            server.send_ws_push(
+                *channel_id,
+                WsMessageType::Text,
+                LazyLoadBlob::new(None::<String>, bytes.clone()),
+            );
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{GraphQlOperation, GraphQlVariable};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Build the `{query, operationName, variables}` request body, mapping call arguments onto the
+/// operation's declared variables. The query document itself is never touched by arguments.
+pub fn build_graphql_body(
+    operation: &GraphQlOperation,
+    args: &HashMap<String, String>,
+) -> Result<Value, String> {
+    let mut variables = Map::new();
+
+    for variable in &operation.variables {
+        let raw = match args.get(&variable.name).or(variable.default_value.as_ref()) {
+            Some(raw) => raw,
+            None if is_non_null(&variable.graphql_type) => {
+                return Err(format!(
+                    "Missing argument for required GraphQL variable: {}",
+                    variable.name
+                ));
+            }
+            None => continue,
+        };
+        variables.insert(variable.name.clone(), coerce_variable(variable, raw)?);
+    }
+
+    let mut body = Map::new();
+    body.insert("query".to_string(), Value::String(operation.query.clone()));
+    if let Some(name) = &operation.operation_name {
+        body.insert("operationName".to_string(), Value::String(name.clone()));
+    }
+    body.insert("variables".to_string(), Value::Object(variables));
+    Ok(Value::Object(body))
+}
+
+/// Convert a string argument into the JSON value expected by the variable's GraphQL type
+fn coerce_variable(variable: &GraphQlVariable, raw: &str) -> Result<Value, String> {
+    let type_name = variable.graphql_type.trim().trim_end_matches('!');
+    let invalid = |expected: &str| {
+        format!(
+            "Invalid value for GraphQL variable '{}': expected {}, got '{}'",
+            variable.name, expected, raw
+        )
+    };
+
+    if raw == "null" && !is_non_null(&variable.graphql_type) {
+        return Ok(Value::Null);
+    }
+
+    match type_name {
+        "Int" => raw
+            .trim()
+            .parse::<i64>()
+            .map(Value::from)
+            .map_err(|_| invalid("Int")),
+        "Float" => raw
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number)
+            .ok_or_else(|| invalid("Float")),
+        "Boolean" => raw
+            .trim()
+            .parse::<bool>()
+            .map(Value::Bool)
+            .map_err(|_| invalid("Boolean")),
+        "String" | "ID" => Ok(Value::String(raw.to_string())),
+        list if list.starts_with('[') => match serde_json::from_str::<Value>(raw) {
+            Ok(Value::Array(items)) => Ok(Value::Array(items)),
+            _ => Err(invalid("a JSON array")),
+        },
+        // Enums, custom scalars and input objects: accept JSON, fall back to a plain string
+        _ => Ok(serde_json::from_str::<Value>(raw).unwrap_or_else(|_| Value::String(raw.to_string()))),
+    }
+}
+
+fn is_non_null(graphql_type: &str) -> bool {
+    graphql_type.trim().ends_with('!')
+}
+
+/// Extract the messages of a non-empty GraphQL `errors` array, if the response has one
+pub fn graphql_errors(response: &str) -> Option<String> {
+    let json = serde_json::from_str::<Value>(response).ok()?;
+    let errors = json.get("errors")?.as_array()?;
+    if errors.is_empty() {
+        return None;
+    }
+    let messages: Vec<String> = errors
+        .iter()
+        .map(|e| {
+            e.get("message")
+                .and_then(Value::as_str)
+                .map(String::from)
+                .unwrap_or_else(|| e.to_string())
+        })
+        .collect();
+    Some(messages.join("; "))
+}
+
+/// x402 field type for a GraphQL variable type
+pub fn graphql_type_to_field_type(graphql_type: &str) -> &'static str {
+    match graphql_type.trim().trim_end_matches('!') {
+        "Int" => "integer",
+        "Float" => "number",
+        "Boolean" => "boolean",
+        "String" | "ID" => "string",
+        list if list.starts_with('[') => "array",
+        _ => "object",
+    }
+}
@@ -0,0 +1,95 @@
+use crate::circuit_breaker::{check_circuit, CircuitBreakers};
+use crate::protocol::SUPPORTED_PROTOCOL_VERSIONS;
+use crate::{HealthStatus, JobStatus, ProviderJob, RegisteredProvider};
+use std::collections::{HashMap, VecDeque};
+
+/// Calls kept per provider for the latency percentiles
+const LATENCY_WINDOW: usize = 100;
+
+/// Recent load of one provider, kept in memory only
+#[derive(Clone, Debug, Default)]
+pub struct CallLoad {
+    in_flight: u32,
+    recent_ms: VecDeque<u64>, // Newest last
+}
+
+/// Loads keyed by provider name
+pub type CallLoads = HashMap<String, CallLoad>;
+
+pub fn begin_call_load(loads: &mut CallLoads, provider_name: &str) {
+    loads.entry(provider_name.to_string()).or_default().in_flight += 1;
+}
+
+/// Mark a started call as done, successful or not, after `duration_ms`
+pub fn finish_call_load(loads: &mut CallLoads, provider_name: &str, duration_ms: u64) {
+    let load = loads.entry(provider_name.to_string()).or_default();
+    load.in_flight = load.in_flight.saturating_sub(1);
+    load.recent_ms.push_back(duration_ms);
+    while load.recent_ms.len() > LATENCY_WINDOW {
+        load.recent_ms.pop_front();
+    }
+}
+
+/// Nearest-rank percentile of the recent call durations
+fn percentile(recent_ms: &VecDeque<u64>, percent: usize) -> Option<u64> {
+    if recent_ms.is_empty() {
+        return None;
+    }
+    let mut sorted: Vec<u64> = recent_ms.iter().copied().collect();
+    sorted.sort_unstable();
+    let rank = (percent * sorted.len()).div_ceil(100).max(1);
+    sorted.get(rank - 1).copied()
+}
+
+/// What an operator needs to know before paying `provider`, in the negotiated `protocol_version`
+pub fn health_status(
+    provider: &RegisteredProvider,
+    protocol_version: u32,
+    breakers: &CircuitBreakers,
+    loads: &CallLoads,
+    jobs: &[ProviderJob],
+) -> HealthStatus {
+    let load = loads.get(&provider.provider_name);
+    let in_flight = load.map_or(0, |l| l.in_flight);
+    // Running jobs are already counted as in flight
+    let pending_jobs = jobs
+        .iter()
+        .filter(|j| j.provider_name == provider.provider_name && j.status == JobStatus::Pending)
+        .count() as u32;
+
+    let mut payment_modes = vec!["tx_hash".to_string()];
+    if provider.access_pass.is_some() {
+        payment_modes.push("access_pass".to_string());
+    }
+    payment_modes.push("x402".to_string());
+
+    let configured = !provider.endpoint.is_empty();
+    let unavailable_reason = if !configured {
+        Some(format!(
+            "Provider '{}' exists but needs endpoint configuration",
+            provider.provider_name
+        ))
+    } else {
+        check_circuit(breakers, &provider.provider_name).err()
+    };
+
+    HealthStatus {
+        provider_name: provider.provider_name.clone(),
+        provider_version: env!("CARGO_PKG_VERSION").to_string(),
+        protocol_version,
+        supported_protocol_versions: SUPPORTED_PROTOCOL_VERSIONS.to_vec(),
+        price: provider.price,
+        wallet: provider.registered_provider_wallet.clone(),
+        available: unavailable_reason.is_none(),
+        unavailable_reason,
+        configured,
+        circuit_state: breakers
+            .get(&provider.provider_name)
+            .map_or("closed", |b| b.label())
+            .to_string(),
+        latency_p50_ms: load.and_then(|l| percentile(&l.recent_ms, 50)),
+        latency_p95_ms: load.and_then(|l| percentile(&l.recent_ms, 95)),
+        queue_depth: in_flight + pending_jobs,
+        payment_modes,
+    }
+}
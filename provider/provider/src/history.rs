@@ -0,0 +1,129 @@
+use crate::util::now_unix_secs;
+use crate::{FieldChange, ProviderRevision, RegisteredProvider, RevisionAction};
+use hyperware_process_lib::{
+    logging::debug,
+    vfs::{create_file, open_dir, open_file, remove_file},
+};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+fn history_dir(drive_path: &str) -> String {
+    format!("{}/history", drive_path)
+}
+
+/// Names are encoded so one like `../x` stays inside the history directory
+fn history_file(drive_path: &str, provider_name: &str) -> String {
+    format!("{}/{}.json", history_dir(drive_path), urlencoding::encode(provider_name))
+}
+
+/// Load the revision history of a provider. A provider without history has no revisions.
+pub fn load_revisions(drive_path: &str, provider_name: &str) -> Result<Vec<ProviderRevision>, String> {
+    let file = match open_file(&history_file(drive_path, provider_name), false, None) {
+        Ok(file) => file,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let bytes = file.read().map_err(|e| e.to_string())?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("Failed to parse revision history: {}", e))
+}
+
+fn save_revisions(drive_path: &str, provider_name: &str, revisions: &[ProviderRevision]) -> Result<(), String> {
+    open_dir(&history_dir(drive_path), true, None).map_err(|e| e.to_string())?;
+    let json_data = serde_json::to_string_pretty(revisions).map_err(|e| e.to_string())?;
+    let file = create_file(&history_file(drive_path, provider_name), None).map_err(|e| e.to_string())?;
+    file.write(json_data.as_bytes()).map_err(|e| e.to_string())
+}
+
+/// Forget the history of an unregistered provider
+pub fn delete_revisions(drive_path: &str, provider_name: &str) {
+    if let Err(e) = remove_file(&history_file(drive_path, provider_name), None) {
+        debug!("No history file to remove for '{}': {:?}", provider_name, e);
+    }
+}
+
+/// Append an immutable revision for `provider`. `previous_name` is the name the provider had
+/// before this change; on rename its history moves along with the provider.
+pub fn append_revision(
+    drive_path: &str,
+    previous_name: &str,
+    provider: &RegisteredProvider,
+    action: RevisionAction,
+    source_revision: Option<u64>,
+) -> Result<ProviderRevision, String> {
+    let mut revisions = load_revisions(drive_path, previous_name)?;
+    let previous = revisions.last().map(|r| &r.provider);
+
+    let revision = ProviderRevision {
+        revision: revisions.last().map(|r| r.revision + 1).unwrap_or(1),
+        timestamp: now_unix_secs(),
+        action,
+        source_revision,
+        diff: diff_providers(previous, provider),
+        provider: provider.clone(),
+    };
+    revisions.push(revision.clone());
+
+    save_revisions(drive_path, &provider.provider_name, &revisions)?;
+    if previous_name != provider.provider_name {
+        if let Err(e) = remove_file(&history_file(drive_path, previous_name), None) {
+            debug!("No history file to remove for '{}': {:?}", previous_name, e);
+        }
+    }
+
+    debug!(
+        "Recorded revision {} for provider '{}'",
+        revision.revision, provider.provider_name
+    );
+    Ok(revision)
+}
+
+/// Field-level diff between two provider definitions, keyed by JSON pointer.
+/// Secrets are redacted on both sides so they never show up in diffs.
+pub fn diff_providers(before: Option<&RegisteredProvider>, after: &RegisteredProvider) -> Vec<FieldChange> {
+    let mut before_fields = BTreeMap::new();
+    if let Some(before) = before {
+        flatten(&redacted_json(before), String::new(), &mut before_fields);
+    }
+    let mut after_fields = BTreeMap::new();
+    flatten(&redacted_json(after), String::new(), &mut after_fields);
+
+    let mut pointers: Vec<&String> = before_fields.keys().chain(after_fields.keys()).collect();
+    pointers.sort();
+    pointers.dedup();
+
+    pointers
+        .into_iter()
+        .filter_map(|pointer| {
+            let old = before_fields.get(pointer).cloned();
+            let new = after_fields.get(pointer).cloned();
+            (old != new).then(|| FieldChange {
+                field: pointer.clone(),
+                before: old,
+                after: new,
+            })
+        })
+        .collect()
+}
+
+fn redacted_json(provider: &RegisteredProvider) -> Value {
+    serde_json::to_value(provider.redacted()).unwrap_or(Value::Null)
+}
+
+/// Flatten a JSON value into pointer -> scalar (as JSON text) pairs
+fn flatten(value: &Value, pointer: String, out: &mut BTreeMap<String, String>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, child) in map {
+                let escaped = key.replace('~', "~0").replace('/', "~1");
+                flatten(child, format!("{}/{}", pointer, escaped), out);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (index, child) in items.iter().enumerate() {
+                flatten(child, format!("{}/{}", pointer, index), out);
+            }
+        }
+        scalar => {
+            out.insert(pointer, scalar.to_string());
+        }
+    }
+}
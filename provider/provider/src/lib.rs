@@ -5,30 +5,31 @@ use hyperware_process_lib::{
     eth::{Provider, Address as EthAddress},
     get_state,
     http::{
+        server::WsMessageType,
         StatusCode,
-        Method as HyperwareHttpMethod,
     },
     hypermap,
     logging::{debug, error, info, warn, init_logging, Level},
     our,
     vfs::{create_drive, create_file, open_file},
     Address,
+    LazyLoadBlob,
+    Request,
     hyperapp::{source, SaveOptions, sleep, get_server, set_response_status, add_response_header, get_request_header, get_request_url, get_query_params},
 };
-use crate::constants::{
-    HYPR_SUFFIX,
-    X402_FACILITATOR_BASE_URL,
-};
+use crate::constants::HYPR_SUFFIX;
 use crate::util::{parse_x_payment_header, build_payment_requirements};
-use base64ct::{Base64, Encoding};
 use rmp_serde;
 use serde::{Deserialize, Serialize};
 use serde_json;
 use std::str::FromStr; // Needed for EthAddress::from_str
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub const CHAIN_ID: u64 = hypermap::HYPERMAP_CHAIN_ID;
 
+/// How often the registry is compared with Hypermap for `SyncDrift` events
+const SYNC_CHECK_INTERVAL_MS: u64 = 5 * 60 * 1000;
+
 mod util; // Declare the util module
 use util::*; // Use its public items
 pub use util::call_provider;
@@ -36,17 +37,127 @@ pub use util::call_provider;
 mod db; // Declare the db module
 use db::*; // Use its public items
 
+mod schema; // Output schema inference and validation
+use schema::infer_schema_from_response;
+
+mod upstream_auth; // OAuth2 token handling for upstream APIs
+use upstream_auth::CachedAccessToken;
+
+mod signing; // Upstream request signers (HMAC, AWS SigV4)
+
+mod graphql; // GraphQL endpoint kind
+
+mod events; // Live event stream over /ws
+
+mod history; // Provider definition revisions in VFS
+use history::{append_revision, delete_revisions, diff_providers, load_revisions};
+
+mod process_backend; // Endpoints served by local Hyperware processes
+
+mod dataset; // Static datasets served from VFS
+
+mod sql; // Read-only sqlite query providers
+
+mod attestation; // Signed caller identity for upstream APIs
+use attestation::CallerContext;
+
+mod circuit_breaker; // Stops paid calls while an upstream is failing
+use circuit_breaker::{
+    begin_circuit_call, check_circuit, record_circuit_outcome, CircuitBreakers,
+    DEFAULT_CIRCUIT_BREAKER,
+};
+
+mod protocol; // Operator <-> provider wire-protocol versions
+use protocol::{check_protocol_version, negotiate_protocol_version};
+
+mod health; // Health ping status and recent call load
+use health::{begin_call_load, finish_call_load, health_status, CallLoads};
+
+mod x402; // x402 payment options, verification and settlement
+use x402::{
+    discovered_resource, encode_settle_response, load_relayer_wallet, match_payment_method,
+    nonce_key, save_relayer_wallet, settle_payment, settlement_margin_secs, verify_exact_payment,
+    RelayerWallet, X402_DISCOVERY_PATH,
+};
+
+mod replay; // Settled x402 payments, for replay refusal and idempotent retries
+use replay::{
+    check_replay, load_retry_responses, paid_request_key, record_served, remove_retry_response,
+    save_retry_response, RetryResponses, ServedPayment, ServedPayments,
+};
+
+mod url_safety; // Upstream host allowlists and private-network deny
+use url_safety::pin_upstream_hosts;
+
+mod sandbox; // Recorded upstream responses for offline development and tests
+
+mod continuation; // Chunked delivery of oversized responses
+use continuation::{limit_response, redeem_chunk, ContinuationStore, CONTINUATION_TTL_SECS};
+
+mod pipeline; // Composite providers chaining endpoints and Hypergrid providers
+use pipeline::{has_remote_steps, RemoteSteps};
+use events::{handle_ws_client_message, publish_event, ProviderEvent, WsSubscriptions};
+
 pub mod constants; // Declare the constants module
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ProviderCall {
     pub provider_name: String,
     pub arguments: Vec<(String, String)>,
     pub payment_tx_hash: Option<String>,
+    pub protocol_version: Option<u32>, // None for callers predating versioning
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct HealthCheckCall {
     pub provider_name: String, // Provider name for availability checking
+    #[serde(default)]
+    pub protocol_versions: Vec<u32>, // Versions the operator speaks, empty before versioning
+}
+
+// Answer to a health ping, checked by the operator before it pays
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HealthStatus {
+    pub provider_name: String,
+    pub provider_version: String, // Version of this provider process
+    pub protocol_version: u32, // Negotiated, to be carried in the operator's following messages
+    pub supported_protocol_versions: Vec<u32>,
+    pub price: f64, // USDC per call
+    pub wallet: String, // Address payments must go to
+    pub available: bool, // False while calls would be refused
+    pub unavailable_reason: Option<String>,
+    pub configured: bool,
+    pub circuit_state: String, // "closed", "open" or "half_open"
+    pub latency_p50_ms: Option<u64>, // Over recent calls, None before the first one
+    pub latency_p95_ms: Option<u64>,
+    pub queue_depth: u32, // Calls in flight plus queued jobs
+    pub payment_modes: Vec<String>, // "tx_hash", "access_pass", "x402"
+}
+
+// Access pass purchase: one USDC payment of the provider's configured pass price
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AccessPassPurchase {
+    pub provider_name: String,
+    pub payment_tx_hash: String,
+    pub protocol_version: Option<u32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AccessPassQuery {
+    pub provider_name: String,
+    pub protocol_version: Option<u32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JobQuery {
+    pub job_id: String,
+    pub protocol_version: Option<u32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChunkQuery {
+    pub continuation_token: String,
+    pub chunk_index: u64,
+    pub protocol_version: Option<u32>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -151,6 +262,36 @@ pub struct OutputSchema {
     pub output: Option<serde_json::Value>,  // Flexible JSON for response format
 }
 
+// x402 discovery list, in the shape of the x402 Bazaar's discovery/resources
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveryResponse {
+    #[serde(rename = "x402Version")]
+    pub protocol_version: u8,
+    pub items: Vec<DiscoveredResource>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveredResource {
+    pub resource: String,  // The provider's /xfour URL
+    pub r#type: String,    // Always "http"
+    #[serde(rename = "x402Version")]
+    pub protocol_version: u8,
+    pub accepts: Vec<AcceptedPayment>,
+    pub metadata: DiscoveryMetadata,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveryMetadata {
+    pub provider_name: String,
+    pub description: String,
+    pub instructions: String,
+    pub price: f64,  // USDC per call
+    pub input_schema: Option<InputSchema>,
+}
+
 // X-PAYMENT header payload structures (from x402 client)
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -231,6 +372,7 @@ pub enum TerminalCommand {
     RegisterProvider(RegisteredProvider),
     UnregisterProvider(String),
     TestProvider(ProviderCall),
+    SetSandboxMode(SandboxMode),
     ExportProviders,
     ViewDatabase,
 }
@@ -266,6 +408,19 @@ pub struct EndpointDefinition {
     // Parameter definitions for substitution
     pub parameters: Vec<ParameterDefinition>,
     pub parameter_names: Vec<String>,
+
+    // Credentials used to obtain short-lived bearer tokens for the upstream API
+    pub upstream_auth: Option<UpstreamAuth>,
+    // Signers that add computed headers after parameter substitution, applied in order
+    pub request_signers: Vec<RequestSigner>,
+
+    // How the call is executed; curl templates unless stated otherwise
+    pub kind: EndpointKind,
+
+    // Hosts requests may go to, pinned from the URL template at registration
+    pub allowed_hosts: Vec<String>,
+    // Private, loopback and link-local addresses are refused unless this is set
+    pub allow_private_network: bool,
 }
 
 // Custom Deserialize implementation for EndpointDefinition to handle migration
@@ -293,6 +448,11 @@ impl<'de> Deserialize<'de> for EndpointDefinition {
                     original_body: new_endpoint.original_body,
                     parameters: new_endpoint.parameters,
                     parameter_names: new_endpoint.parameter_names,
+                    upstream_auth: new_endpoint.upstream_auth,
+                    request_signers: new_endpoint.request_signers,
+                    kind: new_endpoint.kind,
+                    allowed_hosts: new_endpoint.allowed_hosts,
+                    allow_private_network: new_endpoint.allow_private_network,
                 })
             },
             Ok(EndpointDefinitionVariant::Old(_old_endpoint)) => {
@@ -320,6 +480,16 @@ struct NewEndpointDefinition {
     original_body: Option<String>,
     parameters: Vec<ParameterDefinition>,
     parameter_names: Vec<String>,
+    #[serde(default)]
+    upstream_auth: Option<UpstreamAuth>,
+    #[serde(default)]
+    request_signers: Vec<RequestSigner>,
+    #[serde(default)]
+    kind: EndpointKind,
+    #[serde(default)]
+    allowed_hosts: Vec<String>,
+    #[serde(default)]
+    allow_private_network: bool,
 }
 
 #[derive(Deserialize)]
@@ -338,6 +508,164 @@ struct OldEndpointDefinition {
     api_key_header_name: Option<String>,
 }
 
+// Upstream authentication schemes that cannot be expressed as static curl headers
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub enum UpstreamAuth {
+    OAuth2ClientCredentials(OAuth2ClientCredentials),
+    OAuth2RefreshToken(OAuth2RefreshToken),
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct OAuth2ClientCredentials {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String, // Secret, redacted from exports
+    pub scope: Option<String>,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct OAuth2RefreshToken {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: Option<String>, // Secret, redacted from exports
+    pub refresh_token: String,         // Secret, redacted from exports
+}
+
+#[derive(PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum EndpointKind {
+    // Plain HTTP request reconstructed from the curl template
+    #[default]
+    Curl,
+    // GraphQL operation sent to url_template; arguments map to typed variables
+    GraphQl(GraphQlOperation),
+    // Request to a process on this node instead of an HTTP call
+    Process(ProcessBackend),
+    // Uploaded file in the provider's VFS drive, queried without any upstream call
+    Dataset(DatasetSource),
+    // Read-only queries over a sqlite database owned by the provider process
+    Sql(SqlSource),
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct SqlSource {
+    pub database: String, // sqlite database name in the provider's package
+    // Only these tables and columns are visible to queries
+    pub allowed_tables: Vec<SqlTable>,
+    pub queries: Vec<SqlQueryTemplate>,
+    // None disables free-form SELECT
+    pub free_form: Option<FreeFormSql>,
+    pub row_limit: u64, // Max rows returned per call
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct SqlTable {
+    pub name: String,
+    pub columns: Vec<String>,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct SqlQueryTemplate {
+    pub name: String, // Selected by the caller's "query" argument
+    pub description: String,
+    pub sql: String, // SELECT with "?" placeholders
+    pub parameters: Vec<String>, // Argument names bound to the placeholders, in order
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct FreeFormSql {
+    pub timeout_seconds: u64,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct DatasetSource {
+    pub file_name: String, // .csv, .json (array of objects) or .jsonl, under datasets/ in the drive
+    pub default_limit: u64, // Rows per page when the caller sets no limit
+    pub max_limit: u64,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct DatasetInfo {
+    pub file_name: String,
+    pub row_count: u64,
+    pub columns: Vec<String>,
+    pub size_bytes: u64,
+}
+
+/// Whether upstream HTTP calls go out live, are recorded to VFS fixtures, or are answered from them
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub enum SandboxMode {
+    #[default]
+    Off,
+    Record,
+    Replay,
+}
+
+/// Who submits x402 settlements: the facilitator, or the provider's own relayer wallet
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub enum X402Settlement {
+    #[default]
+    Facilitator,
+    SelfSettle,
+}
+
+/// Upstream response recorded in sandbox mode, stored under sandbox/ in the VFS drive
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct SandboxFixture {
+    pub request_key: String, // Hex SHA-256 over method, full URL and body
+    pub method: String,
+    pub url: String, // Without the query string
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    pub recorded_at: u64, // Unix seconds
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct ProcessBackend {
+    pub process_id: String, // e.g. "my-process:my-package:publisher.os", always on our node
+    // JSON request body; "body" parameters are substituted by JSON pointer
+    pub body_template: String,
+    pub timeout_seconds: u64,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct GraphQlOperation {
+    pub query: String, // Full query document, never substituted into
+    pub operation_name: Option<String>,
+    pub variables: Vec<GraphQlVariable>,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct GraphQlVariable {
+    pub name: String,         // Without the leading "$"; also the ProviderCall argument name
+    pub graphql_type: String, // e.g. "Int!", "[String]", "ID"
+    pub default_value: Option<String>,
+}
+
+// Request signers compute headers over the final request (method, path, timestamp, body)
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub enum RequestSigner {
+    HmacSha256(HmacSha256Signer),
+    AwsSigV4(AwsSigV4Signer),
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct HmacSha256Signer {
+    pub secret: String, // Secret, redacted from exports
+    pub signature_header: String, // e.g. "X-Signature"
+    pub timestamp_header: String, // e.g. "X-Timestamp"
+    pub signature_prefix: Option<String>, // e.g. "sha256="
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct AwsSigV4Signer {
+    pub access_key_id: String,
+    pub secret_access_key: String, // Secret, redacted from exports
+    pub session_token: Option<String>, // Secret, redacted from exports
+    pub region: String,  // e.g. "us-east-1"
+    pub service: String, // e.g. "execute-api"
+}
+
 #[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct ParameterDefinition {
     pub parameter_name: String,
@@ -361,18 +689,230 @@ pub struct RegisteredProvider {
     // Price per call in USDC, should be clear in HNS entry
     pub price: f64,
     pub endpoint: EndpointDefinition,
+    // Optional time-based access pass offered alongside per-call pricing
+    #[serde(default)]
+    pub access_pass: Option<AccessPassConfig>,
+    // JSON Schema of the response body (serialized JSON), inferred during validation and editable
+    #[serde(default)]
+    pub output_schema: Option<String>,
+    // Composite providers run this pipeline instead of `endpoint`
+    #[serde(default)]
+    pub pipeline: Option<Pipeline>,
+    // Calls return a job ID right after payment and run in the background
+    #[serde(default)]
+    pub async_job: Option<AsyncJobConfig>,
+    // Larger responses are delivered in chunks of this size behind a continuation token
+    #[serde(default)]
+    pub max_response_bytes: Option<u64>,
+    // Thresholds for refusing calls while the upstream fails; None uses the defaults
+    #[serde(default)]
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    // Ways to pay over x402, all advertised in `accepts`; empty offers USDC on the build's network
+    #[serde(default)]
+    pub x402_accepts: Vec<X402PaymentOption>,
+}
+
+// Ordered steps of a composite provider; the last step's output is the call result
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub steps: Vec<PipelineStep>,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct PipelineStep {
+    pub name: String, // Unique within the pipeline, referenced by later steps
+    pub target: PipelineTarget,
+    // Empty means the step receives the original call arguments unchanged
+    pub bindings: Vec<ArgumentBinding>,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub enum PipelineTarget {
+    // Upstream API called directly by this provider
+    Local(EndpointDefinition),
+    // Another Hypergrid provider, called through this node's operator and paid from this provider's wallet
+    Remote(RemoteProviderStep),
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct RemoteProviderStep {
+    pub provider_node: String, // Node serving the provider, e.g. "weather.os"
+    pub provider_name: String,
+    pub price: f64, // USDC paid per call
+    pub wallet_address: String, // Downstream provider's payment wallet
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct ArgumentBinding {
+    pub argument_name: String, // Argument passed to the step
+    pub source: ArgumentSource,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub enum ArgumentSource {
+    // Argument of the incoming call, by name
+    CallArgument(String),
+    // Value at a JSON pointer in an earlier step's output
+    StepOutput(StepOutputRef),
+    Literal(String),
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct StepOutputRef {
+    pub step_name: String,
+    pub json_pointer: String, // e.g. "/results/0/latitude"; "" selects the whole output
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct AccessPassConfig {
+    // Price of a single pass in USDC
+    pub price: f64,
+    pub duration_seconds: u64,
+    // None means unlimited calls until expiry
+    pub max_calls: Option<u64>,
+}
+
+// A pass bought by a node for a single provider
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct AccessPass {
+    pub provider_name: String,
+    pub node_id: String,
+    pub payment_tx_hash: String,
+    pub purchased_at: u64, // Unix seconds
+    pub expires_at: u64,   // Unix seconds
+    pub max_calls: Option<u64>,
+    pub calls_used: u64,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct AsyncJobConfig {
+    pub upstream_timeout_seconds: u64,
+    // How long a finished job's result stays available for polling
+    pub result_ttl_seconds: u64,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct X402PaymentOption {
+    pub network: String, // x402 network name, e.g. "base" or "base-sepolia"
+    pub asset: String, // EIP-3009 token contract, e.g. USDC or EURC
+    // EIP-712 domain of the token, which payers sign against
+    pub asset_name: String,
+    pub asset_version: String,
+    pub decimals: u8,
+    pub pay_to: Option<String>, // None pays the provider wallet
+    pub price: Option<f64>, // In the asset's units per call, None uses `price`
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    // Opens after this many failed calls in a row
+    pub consecutive_failures: u32,
+    // ...or when more than this share (0-1) of the last `window_size` calls failed
+    pub failure_rate_threshold: f64,
+    pub window_size: u32,
+    // How long calls are refused before a probe call is let through
+    pub open_seconds: u64,
+}
+
+// A paid call running, or finished, in the background
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct ProviderJob {
+    pub job_id: String,
+    pub provider_name: String,
+    pub node_id: String, // Only this node may fetch the result
+    pub arguments: Vec<(String, String)>,
+    pub payment_tx_hash: Option<String>,
+    pub status: JobStatus,
+    pub created_at: u64, // Unix seconds
+    pub completed_at: Option<u64>,
+    pub result: Option<String>,
+    pub error: Option<String>,
+    // Tx hash of the access pass covering the job, refunded if it fails
+    #[serde(default)]
+    pub access_pass: Option<String>,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+// Immutable snapshot of a provider definition, appended on every register/update/rollback
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct ProviderRevision {
+    pub revision: u64, // Starts at 1, increases per provider
+    pub timestamp: u64, // Unix seconds
+    pub action: RevisionAction,
+    // Revision that was restored, for rollbacks
+    pub source_revision: Option<u64>,
+    // Changes relative to the previous revision
+    pub diff: Vec<FieldChange>,
+    pub provider: RegisteredProvider,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub enum RevisionAction {
+    Register,
+    Update,
+    Rollback,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String, // JSON pointer into RegisteredProvider, e.g. "/endpoint/url_template"
+    pub before: Option<String>, // JSON-encoded value, None if the field was added
+    pub after: Option<String>,  // JSON-encoded value, None if the field was removed
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct HypergridProviderState {
     pub registered_providers: Vec<RegisteredProvider>,
     pub spent_tx_hashes: Vec<String>,
+    #[serde(default)]
+    pub access_passes: Vec<AccessPass>,
+    // Upstream OAuth2 access tokens, keyed by provider name
+    #[serde(default)]
+    pub upstream_tokens: HashMap<String, CachedAccessToken>,
+    // Background calls of async providers
+    #[serde(default)]
+    pub jobs: Vec<ProviderJob>,
+    // Record or replay upstream HTTP calls instead of only sending them
+    #[serde(default)]
+    pub sandbox_mode: SandboxMode,
+    #[serde(default)]
+    pub x402_settlement: X402Settlement,
+    // Hot wallet paying the gas of self-settled x402 payments, loaded from VFS
+    #[serde(skip)]
+    pub x402_relayer: Option<RelayerWallet>,
+    // Settled x402 payments by authorization nonce
+    #[serde(default)]
+    pub x402_served: ServedPayments,
     #[serde(skip, default = "util::default_provider")]
     pub rpc_provider: Provider,
     #[serde(skip, default = "util::default_hypermap")]
     pub hypermap: hypermap::Hypermap,
     #[serde(skip)]
     pub vfs_drive_path: Option<String>,
+    // Open /ws channels and their provider-name filters
+    #[serde(skip)]
+    pub ws_subscriptions: WsSubscriptions,
+    // Remaining chunks of oversized responses, not persisted across restarts
+    #[serde(skip)]
+    pub response_continuations: ContinuationStore,
+    #[serde(skip)]
+    pub circuit_breakers: CircuitBreakers,
+    // In-flight calls and recent latencies, reported by health pings
+    #[serde(skip)]
+    pub call_loads: CallLoads,
+    // x402 authorizations verified but not settled yet
+    #[serde(skip)]
+    pub x402_pending_nonces: HashSet<String>,
+    // Responses of settled x402 calls, for retries; stored on the drive, not in state
+    #[serde(skip)]
+    pub x402_retry_responses: RetryResponses,
 }
 
 impl HypergridProviderState {
@@ -393,9 +933,22 @@ impl HypergridProviderState {
         Self {
             registered_providers: Vec::new(),
             spent_tx_hashes: Vec::new(),
+            access_passes: Vec::new(),
+            upstream_tokens: HashMap::new(),
+            jobs: Vec::new(),
+            sandbox_mode: SandboxMode::Off,
+            x402_settlement: X402Settlement::Facilitator,
+            x402_relayer: None,
+            x402_served: HashMap::new(),
             rpc_provider: provider.clone(),
             hypermap: hypermap::Hypermap::new(provider.clone(), hypermap_contract_address),
             vfs_drive_path: None,
+            ws_subscriptions: HashMap::new(),
+            response_continuations: HashMap::new(),
+            circuit_breakers: HashMap::new(),
+            call_loads: HashMap::new(),
+            x402_pending_nonces: HashSet::new(),
+            x402_retry_responses: HashMap::new(),
         }
     }
 
@@ -468,9 +1021,14 @@ impl HypergridProviderState {
     }
 
     /// Export providers as JSON string (for easy export functionality)
+    /// Upstream auth secrets are redacted and must be re-entered after import.
     pub fn export_providers_json(&self) -> Result<String, String> {
-        let json_data =
-            serde_json::to_string_pretty(&self.registered_providers).map_err(Self::to_err)?;
+        let redacted: Vec<RegisteredProvider> = self
+            .registered_providers
+            .iter()
+            .map(RegisteredProvider::redacted)
+            .collect();
+        let json_data = serde_json::to_string_pretty(&redacted).map_err(Self::to_err)?;
 
         debug!(
             "Exported {} providers as JSON",
@@ -479,47 +1037,259 @@ impl HypergridProviderState {
         Ok(json_data)
     }
 
-    /// Loads old state from disk, falls back to new() if none exists
-    pub fn load() -> Self {
-        match get_state() {
-            Some(bytes) => match rmp_serde::from_slice::<Self>(&bytes) {
-                Ok(state) => {
-                    debug!("Successfully loaded HypergridProviderState from checkpoint.");
-                    state
-                }
-                Err(e) => {
-                    error!("Failed to deserialize HypergridProviderState with rmp_serde: {}, creating new state", e);
-                    Self::new()
+    /// Replace a registered provider in place and record the change in its revision history.
+    /// Shared by `update_provider` and `rollback_provider`.
+    fn apply_provider_update(
+        &mut self,
+        provider_name: String,
+        mut updated_provider: RegisteredProvider,
+        action: RevisionAction,
+        source_revision: Option<u64>,
+    ) -> Result<RegisteredProvider, String> {
+        // Find the provider to update
+        let provider_index = self
+            .registered_providers
+            .iter()
+            .position(|p| p.provider_name == provider_name);
+
+        match provider_index {
+            Some(index) => {
+                // Check if the provider name is changing
+                let name_changed = provider_name != updated_provider.provider_name;
+
+                // If name changed, check if new name already exists
+                if name_changed {
+                    if self
+                        .registered_providers
+                        .iter()
+                        .any(|p| p.provider_name == updated_provider.provider_name)
+                    {
+                        let error_msg = format!(
+                            "A provider with name '{}' already exists. Please choose a different name.",
+                            updated_provider.provider_name
+                        );
+                        debug!("{}", error_msg);
+                        return Err(error_msg);
+                    }
                 }
-            },
-            None => {
-                debug!("No saved state found. Creating new state.");
-                Self::new()
-            }
-        }
-    }
-}
 
-impl Default for HypergridProviderState {
-    fn default() -> Self {
-        Self::new()
-    }
-}
+                // Definitions edited in the UI come back redacted; keep the stored secrets
+                updated_provider = updated_provider.with_stored_secrets(&self.registered_providers[index]);
+                pin_upstream_hosts(&mut updated_provider)?;
+                validate_provider_definition(&updated_provider)?;
 
-// --- Hyperware Process ---
-#[hyperprocess(
-    name = "provider",
-    ui = None,
-    endpoints = vec![
-        Binding::Http {
-            path: "/api",
-            config: HttpBindingConfig::new(false, false, false, None),
-        },
-        Binding::Http {
-            path: "/xfour",
-            config: HttpBindingConfig::new(false, false, false, None),
-        },
-        Binding::Ws {
+                // Always use node identity as provider_id
+                let updated_provider_with_id = RegisteredProvider {
+                    provider_id: our().node.to_string(),
+                    ..updated_provider
+                };
+
+                // Update the provider
+                self.registered_providers[index] = updated_provider_with_id.clone();
+                self.record_revision(&provider_name, &updated_provider_with_id, action, source_revision);
+                // Auth config may have changed, drop any cached upstream token
+                self.clear_upstream_tokens(&provider_name);
+                // A fixed endpoint should not stay behind an open breaker
+                self.circuit_breakers.remove(&provider_name);
+
+                debug!(
+                    "Successfully updated provider: {} -> {}",
+                    provider_name, updated_provider_with_id.provider_name
+                );
+                publish_event(
+                    &self.ws_subscriptions,
+                    ProviderEvent::ProviderUpdated {
+                        previous_name: provider_name.clone(),
+                        provider_name: updated_provider_with_id.provider_name.clone(),
+                    },
+                );
+
+                // Save to VFS
+                if let Err(e) = self.save_providers_to_vfs() {
+                    error!("Failed to save updated providers to VFS: {}", e);
+                }
+
+                // Manual save for diagnostics
+                match rmp_serde::to_vec(self) {
+                    Ok(bytes) => {
+                        hyperware_process_lib::set_state(&bytes);
+                        debug!(
+                            "Manually called set_state with {} bytes after update.",
+                            bytes.len()
+                        );
+                    }
+                    Err(e) => {
+                        error!("Manual save after update: Failed to serialize HypergridProviderState: {}", e);
+                    }
+                }
+
+                Ok(updated_provider_with_id)
+            }
+            None => Err(format!(
+                "Provider with name '{}' not found for update.",
+                provider_name
+            )),
+        }
+    }
+
+    /// Drop cached upstream tokens of a provider, including those of its pipeline steps
+    fn clear_upstream_tokens(&mut self, provider_name: &str) {
+        let step_prefix = format!("{}/", provider_name);
+        self.upstream_tokens
+            .retain(|key, _| key != provider_name && !key.starts_with(&step_prefix));
+    }
+
+    /// Append a revision to the provider's VFS history; failures are logged, not fatal
+    fn record_revision(
+        &self,
+        previous_name: &str,
+        provider: &RegisteredProvider,
+        action: RevisionAction,
+        source_revision: Option<u64>,
+    ) {
+        let Some(drive_path) = self.vfs_drive_path.as_ref() else {
+            error!("VFS drive not initialized, revision for '{}' not recorded", provider.provider_name);
+            return;
+        };
+        if let Err(e) = append_revision(drive_path, previous_name, provider, action, source_revision) {
+            error!("Failed to record revision for provider '{}': {}", provider.provider_name, e);
+        }
+    }
+
+    /// Drop finished jobs whose result has outlived the provider's TTL
+    fn prune_finished_jobs(&mut self, now: u64) {
+        let providers = &self.registered_providers;
+        self.jobs.retain(|job| {
+            let Some(completed_at) = job.completed_at else {
+                return true;
+            };
+            let ttl = providers
+                .iter()
+                .find(|p| p.provider_name == job.provider_name)
+                .and_then(|p| p.async_job.as_ref())
+                .map_or(0, |config| config.result_ttl_seconds);
+            now < completed_at + ttl
+        });
+    }
+
+    /// Store the outcome of a background call on its job
+    fn finish_job(&mut self, job_id: &str, outcome: Result<String, String>) {
+        let Some(job) = self.jobs.iter_mut().find(|j| j.job_id == job_id) else {
+            return;
+        };
+        job.completed_at = Some(now_unix_secs());
+        match outcome {
+            Ok(response) => {
+                job.status = JobStatus::Succeeded;
+                job.result = Some(response);
+            }
+            Err(e) => {
+                job.status = JobStatus::Failed;
+                job.error = Some(e);
+                if let Some(pass_tx_hash) = &job.access_pass {
+                    refund_access_pass(&mut self.access_passes, pass_tx_hash);
+                }
+            }
+        }
+    }
+
+    /// Pick jobs back up after a restart. Queued jobs are started again; jobs that were running
+    /// may already have reached the upstream, so they fail rather than run twice.
+    fn recover_jobs(&mut self) {
+        let mut interrupted = Vec::new();
+        for job in &self.jobs {
+            match job.status {
+                JobStatus::Pending => {
+                    let body = serde_json::json!({ "RunJob": job.job_id });
+                    match serde_json::to_vec(&body) {
+                        Ok(body) => match Request::to(our()).body(body).send() {
+                            Ok(()) => info!(
+                                "provider_job_requeued: provider={}, job_id={}",
+                                job.provider_name, job.job_id
+                            ),
+                            Err(e) => error!("provider_job_requeue_failed: job_id={}, error={:?}", job.job_id, e),
+                        },
+                        Err(e) => error!("provider_job_requeue_failed: job_id={}, error={}", job.job_id, e),
+                    }
+                }
+                JobStatus::Running => {
+                    warn!(
+                        "provider_job_interrupted: provider={}, source_node={}, job_id={}, tx_hash={}",
+                        job.provider_name,
+                        job.node_id,
+                        job.job_id,
+                        job.payment_tx_hash.as_deref().unwrap_or("none")
+                    );
+                    interrupted.push(job.job_id.clone());
+                }
+                JobStatus::Succeeded | JobStatus::Failed => {}
+            }
+        }
+        for job_id in interrupted {
+            self.finish_job(
+                &job_id,
+                Err("Interrupted by a provider restart; not retried since the upstream may already have been called"
+                    .to_string()),
+            );
+        }
+    }
+
+    /// Schedule the next background comparison with Hypermap
+    fn queue_sync_check() {
+        let body = serde_json::json!({ "CheckSyncDrift": null });
+        let sent = serde_json::to_vec(&body)
+            .map_err(|e| e.to_string())
+            .and_then(|body| Request::to(our()).body(body).send().map_err(|e| format!("{:?}", e)));
+        if let Err(e) = sent {
+            error!("provider_sync_check_not_queued: error={}", e);
+        }
+    }
+
+    /// Loads old state from disk, falls back to new() if none exists
+    pub fn load() -> Self {
+        match get_state() {
+            Some(bytes) => match rmp_serde::from_slice::<Self>(&bytes) {
+                Ok(state) => {
+                    debug!("Successfully loaded HypergridProviderState from checkpoint.");
+                    state
+                }
+                Err(e) => {
+                    error!("Failed to deserialize HypergridProviderState with rmp_serde: {}, creating new state", e);
+                    Self::new()
+                }
+            },
+            None => {
+                debug!("No saved state found. Creating new state.");
+                Self::new()
+            }
+        }
+    }
+}
+
+impl Default for HypergridProviderState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// --- Hyperware Process ---
+#[hyperprocess(
+    name = "provider",
+    ui = None,
+    endpoints = vec![
+        Binding::Http {
+            path: "/api",
+            config: HttpBindingConfig::new(false, false, false, None),
+        },
+        Binding::Http {
+            path: "/xfour",
+            config: HttpBindingConfig::new(false, false, false, None),
+        },
+        Binding::Http {
+            path: "/.well-known/x402",
+            config: HttpBindingConfig::new(false, false, false, None),
+        },
+        Binding::Ws {
             path: "/ws",
             config: WsBindingConfig::new(false, false, false),
         }
@@ -548,53 +1318,86 @@ impl HypergridProviderState {
         if let Err(e) = self.init_vfs_drive() {
             error!("Failed to initialize VFS drive: {}", e);
         }
+        if let Some(drive_path) = &self.vfs_drive_path {
+            match load_relayer_wallet(drive_path) {
+                Ok(relayer) => self.x402_relayer = relayer,
+                Err(e) => error!("x402_relayer_unreadable: error={}", e),
+            }
+            match load_retry_responses(drive_path, &self.x402_served, now_unix_secs()) {
+                Ok(responses) => self.x402_retry_responses = responses,
+                Err(e) => error!("x402_retry_responses_unreadable: error={}", e),
+            }
+        }
+        self.recover_jobs();
+        Self::queue_sync_check();
+        if self.sandbox_mode != SandboxMode::Off {
+            warn!("sandbox_mode_active: mode={:?}, upstream calls are not live", self.sandbox_mode);
+        }
         // add_to_homepage("Hypergrid Provider Dashboard", Some(ICON), Some("/"), None);
     }
 
     #[local]
     #[remote]
-    async fn health_ping(&self, request: HealthCheckCall) -> Result<String, String> {
+    async fn health_ping(&self, request: HealthCheckCall) -> Result<HealthStatus, String> {
         info!("Health ping received: {:?}", request);
 
-        info!("Checking availability for provider: {}", request.provider_name);
-
-        // Check if provider exists in registry
-        let provider_exists = self
+        let Some(provider) = self
             .registered_providers
             .iter()
-            .find(|p| p.provider_name == request.provider_name);
+            .find(|p| p.provider_name == request.provider_name)
+        else {
+            let error_msg = format!("Provider '{}' not found in registry", request.provider_name);
+            warn!("{}", error_msg);
+            return Err(error_msg);
+        };
+        let protocol_version = negotiate_protocol_version(&request.protocol_versions).map_err(|e| {
+            warn!("protocol_version_mismatch: provider={}, error={}", request.provider_name, e);
+            e
+        })?;
 
-        match provider_exists {
-            Some(provider) => {
-                // Check if provider has a valid endpoint configuration
-                if provider.endpoint.is_empty() {
-                    let error_msg = format!(
-                        "Provider '{}' exists but needs endpoint configuration",
-                        request.provider_name
-                    );
-                    warn!("{}", error_msg);
-                    return Err(error_msg);
-                }
+        // Unconfigured providers and open circuits are reported, the operator refuses to pay them
+        let status = health_status(
+            provider,
+            protocol_version,
+            &self.circuit_breakers,
+            &self.call_loads,
+            &self.jobs,
+        );
+        if let Some(reason) = &status.unavailable_reason {
+            warn!("{}", reason);
+        }
+        debug!(
+            "health_ping: provider={}, protocol_version={}, available={}, circuit={}, queue_depth={}, price_usdc={}",
+            status.provider_name,
+            status.protocol_version,
+            status.available,
+            status.circuit_state,
+            status.queue_depth,
+            status.price
+        );
+        Ok(status)
+    }
 
-                debug!(
-                    "Provider '{}' is available and configured (price: {} USDC)",
-                    request.provider_name,
-                    provider.price
-                );
-                Ok("Ack".to_string())
+    /// Dashboard clients manage their event subscription over /ws:
+    /// `{"Subscribe": {"provider_names": [...]}}` (empty list for all) or `"Unsubscribe"`
+    #[ws]
+    fn websocket(&mut self, channel_id: u32, message_type: WsMessageType, blob: LazyLoadBlob) {
+        match message_type {
+            WsMessageType::Text | WsMessageType::Binary => {
+                handle_ws_client_message(&mut self.ws_subscriptions, channel_id, &blob.bytes);
             }
-            None => {
-                let error_msg = format!("Provider '{}' not found in registry", request.provider_name);
-                warn!("{}", error_msg);
-                Err(error_msg)
+            WsMessageType::Close => {
+                debug!("ws channel {} closed", channel_id);
+                self.ws_subscriptions.remove(&channel_id);
             }
+            _ => {}
         }
     }
 
     #[http]
     async fn register_provider(
         &mut self,
-        provider: RegisteredProvider,
+        mut provider: RegisteredProvider,
     ) -> Result<RegisteredProvider, String> {
         // Usage tracking log - registration started
         debug!(
@@ -616,9 +1419,12 @@ impl HypergridProviderState {
             warn!("{}", error_msg);
             return Err(error_msg);
         }
+        pin_upstream_hosts(&mut provider)?;
+        validate_provider_definition(&provider)?;
 
         // Provider ID is set by frontend to match node identity
         self.registered_providers.push(provider.clone());
+        self.record_revision(&provider.provider_name, &provider, RevisionAction::Register, None);
 
         // Success tracking log
         debug!(
@@ -626,6 +1432,12 @@ impl HypergridProviderState {
             provider.provider_name,
             self.registered_providers.len()
         );
+        publish_event(
+            &self.ws_subscriptions,
+            ProviderEvent::ProviderRegistered {
+                provider_name: provider.provider_name.clone(),
+            },
+        );
 
         // Save to VFS
         if let Err(e) = self.save_providers_to_vfs() {
@@ -649,7 +1461,7 @@ impl HypergridProviderState {
     #[http]
     async fn validate_provider(
         &mut self,
-        provider: RegisteredProvider,
+        mut provider: RegisteredProvider,
         arguments: Vec<(String, String)>,
     ) -> Result<String, String> {
         // Usage tracking log - validation started
@@ -672,18 +1484,32 @@ impl HypergridProviderState {
             return Err(error_msg);
         }
 
+        // Reject malformed definitions before the test run calls any upstream
+        pin_upstream_hosts(&mut provider)?;
+        validate_provider_definition(&provider)?;
+
         // Use the new curl-based validation
-        let validation_result = call_provider(
-            provider.provider_name.clone(),
-            provider.endpoint.clone(),
+        let validation_result = invoke_provider(
+            &provider,
             &arguments,
-            our().node.to_string(),
+            &CallerContext::own_node(),
+            &mut self.upstream_tokens,
+            RemoteSteps::DryRun,
+            provider.upstream_timeout_seconds(),
+            self.sandbox_mode,
         )
         .await?;
         debug!("Validation result: {}", validation_result);
         validate_response_status(&validation_result)
             .map_err(|e| format!("Validation failed: {}", e))?;
 
+        // Capture the output schema from the sample response; it can be edited before registering.
+        // A dry run stops before remote steps, so its output isn't the pipeline's.
+        let mut provider = provider;
+        if provider.output_schema.is_none() && !has_remote_steps(&provider) {
+            provider.output_schema = Some(infer_schema_from_response(&validation_result).to_string());
+        }
+
         let validation_start = std::time::Instant::now();
         // Success tracking log
         debug!(
@@ -708,23 +1534,25 @@ impl HypergridProviderState {
     async fn validate_provider_update(
         &mut self,
         provider_name: String,
-        updated_provider: RegisteredProvider,
+        mut updated_provider: RegisteredProvider,
         arguments: Vec<(String, String)>,
     ) -> Result<String, String> {
         debug!("Validating provider update: {}", provider_name);
         // Check if the original provider exists
-        if !self
+        let Some(stored) = self
             .registered_providers
             .iter()
-            .any(|p| p.provider_name == provider_name)
-        {
+            .find(|p| p.provider_name == provider_name)
+        else {
             let error_msg = format!(
                 "Provider with name '{}' not found for update.",
                 provider_name
             );
             warn!("{}", error_msg);
             return Err(error_msg);
-        }
+        };
+        // Definitions edited in the UI come back redacted; test with the stored secrets
+        updated_provider = updated_provider.with_stored_secrets(stored);
 
         // If the name is changing, check if new name already exists
         if provider_name != updated_provider.provider_name {
@@ -742,23 +1570,43 @@ impl HypergridProviderState {
             }
         }
 
+        // Reject malformed definitions before the test run calls any upstream
+        pin_upstream_hosts(&mut updated_provider)?;
+        validate_provider_definition(&updated_provider)?;
+
         // Use the new curl-based validation
-        let validation_result = call_provider(
-            updated_provider.provider_name.clone(),
-            updated_provider.endpoint.clone(),
+        let validation_result = invoke_provider(
+            &updated_provider,
             &arguments,
-            our().node.to_string(),
+            &CallerContext::own_node(),
+            &mut self.upstream_tokens,
+            RemoteSteps::DryRun,
+            updated_provider.upstream_timeout_seconds(),
+            self.sandbox_mode,
         )
         .await?;
         debug!("Validation result: {}", validation_result);
         validate_response_status(&validation_result)
             .map_err(|e| format!("Validation failed: {}", e))?;
 
+        // Check the sample against the declared schema, or infer one if none is declared yet
+        let mut updated_provider = updated_provider;
+        let schema_violations = match &updated_provider.output_schema {
+            _ if has_remote_steps(&updated_provider) => Vec::new(),
+            Some(_) => output_schema_violations(&updated_provider, &validation_result),
+            None => {
+                updated_provider.output_schema =
+                    Some(infer_schema_from_response(&validation_result).to_string());
+                Vec::new()
+            }
+        };
+
         debug!("Provider update validation successful: {}", updated_provider.provider_name);
         // Return the validated provider object as JSON for frontend consistency
         let response = serde_json::json!({
             "validation_result": validation_result,
-            "provider": updated_provider
+            "provider": updated_provider.redacted(),
+            "schema_violations": schema_violations
         });
 
         serde_json::to_string(&response)
@@ -772,74 +1620,81 @@ impl HypergridProviderState {
         updated_provider: RegisteredProvider,
     ) -> Result<RegisteredProvider, String> {
         debug!("Provider update request received: {}", provider_name);
+        self.apply_provider_update(provider_name, updated_provider, RevisionAction::Update, None)
+            .map(|provider| provider.redacted())
+    }
 
-        // Find the provider to update
-        let provider_index = self
-            .registered_providers
-            .iter()
-            .position(|p| p.provider_name == provider_name);
-
-        match provider_index {
-            Some(index) => {
-                // Check if the provider name is changing
-                let name_changed = provider_name != updated_provider.provider_name;
-
-                // If name changed, check if new name already exists
-                if name_changed {
-                    if self
-                        .registered_providers
-                        .iter()
-                        .any(|p| p.provider_name == updated_provider.provider_name)
-                    {
-                        let error_msg = format!(
-                            "A provider with name '{}' already exists. Please choose a different name.",
-                            updated_provider.provider_name
-                        );
-                        debug!("{}", error_msg);
-                        return Err(error_msg);
-                    }
-                }
-
-                // Always use node identity as provider_id
-                let updated_provider_with_id = RegisteredProvider {
-                    provider_id: our().node.to_string(),
-                    ..updated_provider
-                };
-
-                // Update the provider
-                self.registered_providers[index] = updated_provider_with_id.clone();
+    #[http]
+    async fn get_provider_revisions(&self, provider_name: String) -> Result<Vec<ProviderRevision>, String> {
+        debug!("Fetching revision history for provider: {}", provider_name);
+        let drive_path = self.vfs_drive_path.as_ref().ok_or("VFS drive not initialized")?;
+        let revisions = load_revisions(drive_path, &provider_name)?;
 
-                debug!(
-                    "Successfully updated provider: {} -> {}",
-                    provider_name, updated_provider_with_id.provider_name
-                );
+        // Secrets stay in VFS; only redacted definitions leave the process
+        Ok(revisions
+            .into_iter()
+            .map(|r| ProviderRevision {
+                provider: r.provider.redacted(),
+                ..r
+            })
+            .collect())
+    }
 
-                // Save to VFS
-                if let Err(e) = self.save_providers_to_vfs() {
-                    error!("Failed to save updated providers to VFS: {}", e);
-                }
+    #[http]
+    async fn diff_provider_revisions(
+        &self,
+        provider_name: String,
+        from_revision: u64,
+        to_revision: u64,
+    ) -> Result<Vec<FieldChange>, String> {
+        debug!(
+            "Diffing revisions {} -> {} for provider: {}",
+            from_revision, to_revision, provider_name
+        );
+        let drive_path = self.vfs_drive_path.as_ref().ok_or("VFS drive not initialized")?;
+        let revisions = load_revisions(drive_path, &provider_name)?;
+        let find = |revision: u64| {
+            revisions
+                .iter()
+                .find(|r| r.revision == revision)
+                .ok_or_else(|| format!("Revision {} not found for provider '{}'", revision, provider_name))
+        };
 
-                // Manual save for diagnostics
-                match rmp_serde::to_vec(self) {
-                    Ok(bytes) => {
-                        hyperware_process_lib::set_state(&bytes);
-                        debug!(
-                            "Manually called set_state with {} bytes after update.",
-                            bytes.len()
-                        );
-                    }
-                    Err(e) => {
-                        error!("Manual save after update: Failed to serialize HypergridProviderState: {}", e);
-                    }
-                }
+        let from = find(from_revision)?;
+        let to = find(to_revision)?;
+        Ok(diff_providers(Some(&from.provider), &to.provider))
+    }
 
-                Ok(updated_provider_with_id)
-            }
-            None => Err(format!(
-                "Provider with name '{}' not found for update.",
-                provider_name
-            )),
-        }
+    /// Restore an earlier revision. The restored definition is validated against the live
+    /// upstream exactly like a regular update before it replaces the current one.
+    #[http]
+    async fn rollback_provider(
+        &mut self,
+        provider_name: String,
+        revision: u64,
+        arguments: Vec<(String, String)>,
+    ) -> Result<RegisteredProvider, String> {
+        debug!("Rolling back provider '{}' to revision {}", provider_name, revision);
+        let drive_path = self.vfs_drive_path.as_ref().ok_or("VFS drive not initialized")?;
+        let target = load_revisions(drive_path, &provider_name)?
+            .into_iter()
+            .find(|r| r.revision == revision)
+            .ok_or_else(|| format!("Revision {} not found for provider '{}'", revision, provider_name))?;
+
+        let validation = self
+            .validate_provider_update(provider_name.clone(), target.provider.clone(), arguments)
+            .await
+            .map_err(|e| format!("Rollback validation failed: {}", e))?;
+        // Apply the definition as validated, with its upstream hosts pinned and the revision's
+        // own secrets, which the validation response leaves out
+        let validated: serde_json::Value = serde_json::from_str(&validation)
+            .map_err(|e| format!("Failed to parse rollback validation: {}", e))?;
+        let restored: RegisteredProvider = serde_json::from_value(validated["provider"].clone())
+            .map_err(|e| format!("Failed to read the validated definition: {}", e))?;
+        let restored = restored.with_stored_secrets(&target.provider);
+
+        self.apply_provider_update(provider_name, restored, RevisionAction::Rollback, Some(revision))
+            .map(|provider| provider.redacted())
     }
 
     #[local]
@@ -863,6 +1718,15 @@ impl HypergridProviderState {
             mcp_request.arguments.len()
         );
 
+        // Checked first: a caller on an unknown version may mean something else by its fields
+        if let Err(e) = check_protocol_version(mcp_request.protocol_version) {
+            error!(
+                "provider_call_failed: provider={}, source_node={}, error_type=protocol_version_mismatch, protocol_version={:?}",
+                mcp_request.provider_name, source_node_id, mcp_request.protocol_version
+            );
+            return Err(e);
+        }
+
         // --- 0. Check if provider exists at all ---
         // First validate the payment before accessing registered_provider
         if !self
@@ -884,106 +1748,642 @@ impl HypergridProviderState {
             return Err(error_msg);
         }
 
-        // Get the source node ID ---
-        let source_address = source();
-        // goobersync.os
-        let source_node_id = source_address.node().to_string();
+        // Get the source node ID ---
+        let source_address = source();
+        // goobersync.os
+        let source_node_id = source_address.node().to_string();
+
+        // Refuse before the payment is validated, so the tx hash stays unspent for a later retry
+        if let Err(unavailable) = check_circuit(&self.circuit_breakers, &mcp_request.provider_name) {
+            error!(
+                "provider_call_failed: provider={}, source_node={}, error_type=circuit_open",
+                mcp_request.provider_name, source_node_id
+            );
+            return Err(unavailable);
+        }
+
+        publish_event(
+            &self.ws_subscriptions,
+            ProviderEvent::CallStarted {
+                provider_name: mcp_request.provider_name.clone(),
+                source_node: source_node_id.clone(),
+                has_tx_hash: mcp_request.payment_tx_hash.is_some(),
+            },
+        );
+
+        // --- 1. Validate the payment ---
+        // Calls without a tx hash are covered by an active access pass, if the node holds one
+        // The call is given back to the pass if the provider fails to answer
+        let access_pass = match mcp_request.payment_tx_hash {
+            None => consume_access_pass(
+                &mut self.access_passes,
+                &mcp_request.provider_name,
+                &source_node_id,
+            ),
+            Some(_) => None,
+        };
+        if access_pass.is_some() {
+            debug!(
+                "Call to '{}' from {} covered by access pass",
+                mcp_request.provider_name, source_node_id
+            );
+            publish_event(
+                &self.ws_subscriptions,
+                ProviderEvent::PaymentValidated {
+                    provider_name: mcp_request.provider_name.clone(),
+                    source_node: source_node_id.clone(),
+                    payment_method: "access_pass".to_string(),
+                    tx_hash: None,
+                },
+            );
+        } else if let Err(validation_err) =
+            validate_transaction_payment(&mcp_request, self, source_node_id.clone()).await
+        {
+            // Error tracking log - payment validation failed
+            error!(
+                "provider_call_failed: provider={}, source_node={}, error_type=payment_validation_failed, validation_error={}",
+                mcp_request.provider_name,
+                source_node_id,
+                validation_err
+            );
+            publish_event(
+                &self.ws_subscriptions,
+                ProviderEvent::PaymentRejected {
+                    provider_name: mcp_request.provider_name.clone(),
+                    source_node: source_node_id.clone(),
+                    payment_method: "tx_hash".to_string(),
+                    reason: validation_err.clone(),
+                },
+            );
+            return Err(validation_err);
+        } else {
+            publish_event(
+                &self.ws_subscriptions,
+                ProviderEvent::PaymentValidated {
+                    provider_name: mcp_request.provider_name.clone(),
+                    source_node: source_node_id.clone(),
+                    payment_method: "tx_hash".to_string(),
+                    tx_hash: mcp_request.payment_tx_hash.clone(),
+                },
+            );
+        }
+        // We can safely unwrap here since validate_transaction_payment already checked
+        // that the provider exists in the registered_providers list
+        let registered_provider = self
+            .registered_providers
+            .iter()
+            .find(|p| p.provider_name == mcp_request.provider_name)
+            .expect(&format!(
+                "Provider '{}' not found - this should never happen as it was validated in `validate_transaction_payment`",
+                mcp_request.provider_name
+            ));
+
+        // Async providers answer with a job ticket and run the call in the background
+        if registered_provider.async_job.is_some() {
+            let now = now_unix_secs();
+            let job = ProviderJob {
+                job_id: uuid::Uuid::new_v4().to_string(),
+                provider_name: registered_provider.provider_name.clone(),
+                node_id: source_node_id.clone(),
+                arguments: mcp_request.arguments.clone(),
+                payment_tx_hash: mcp_request.payment_tx_hash.clone(),
+                status: JobStatus::Pending,
+                created_at: now,
+                completed_at: None,
+                result: None,
+                error: None,
+                access_pass: access_pass.clone(),
+            };
+            let ticket = serde_json::json!({
+                "job_id": job.job_id,
+                "provider_name": job.provider_name,
+                "status": job.status,
+            });
+
+            self.prune_finished_jobs(now);
+            self.jobs.push(job.clone());
+            let queued = serde_json::to_vec(&serde_json::json!({ "RunJob": job.job_id }))
+                .map_err(|e| e.to_string())
+                .and_then(|body| {
+                    Request::to(our())
+                        .body(body)
+                        .send()
+                        .map_err(|e| format!("Failed to queue job: {:?}", e))
+                });
+            if let Err(e) = queued {
+                self.finish_job(&job.job_id, Err(e.clone()));
+                return Err(e);
+            }
+
+            info!(
+                "provider_job_queued: provider={}, source_node={}, job_id={}, tx_hash={}",
+                job.provider_name,
+                source_node_id,
+                job.job_id,
+                job.payment_tx_hash.as_deref().unwrap_or("none")
+            );
+            return Ok(ticket.to_string());
+        }
+
+        // --- 2. Call the provider with retry mechanism ---
+        const MAX_RETRIES: usize = 3;
+        // Pipelines may have paid downstream providers before failing, so they run once
+        let max_attempts = if registered_provider.pipeline.is_some() { 1 } else { MAX_RETRIES };
+        let mut last_error = String::new();
+        let call_start_time = std::time::Instant::now();
+        let breaker_config = registered_provider.circuit_breaker_config();
+        begin_circuit_call(&mut self.circuit_breakers, &registered_provider.provider_name);
+        begin_call_load(&mut self.call_loads, &registered_provider.provider_name);
+        for attempt in 1..=max_attempts {
+            debug!("Attempting provider call {} of {}", attempt, max_attempts);
+
+            let api_call_result = invoke_provider(
+                registered_provider,
+                &mcp_request.arguments,
+                &CallerContext {
+                    caller: source_node_id.clone(),
+                    payment_tx_hash: mcp_request.payment_tx_hash.clone(),
+                },
+                &mut self.upstream_tokens,
+                RemoteSteps::Pay,
+                registered_provider.upstream_timeout_seconds(),
+                // Paid calls always go upstream, whatever the sandbox mode
+                SandboxMode::Off,
+            )
+            .await;
+
+            match api_call_result {
+                Ok(response) => {
+                    let call_duration = call_start_time.elapsed();
+
+                    // Success tracking log - no sensitive data
+                    info!(
+                        "provider_call_success: provider={}, provider_node={}, source_node={}, tx_hash={}, price_usdc={}, attempt={}, duration_ms={}, response_size_bytes={}",
+                        registered_provider.provider_name,
+                        our().node,
+                        source_node_id,
+                        mcp_request.payment_tx_hash.as_deref().unwrap_or("none"),
+                        registered_provider.price,
+                        attempt,
+                        call_duration.as_millis(),
+                        response.len()
+                    );
+
+                    if attempt > 1 {
+                        debug!("Provider call succeeded on attempt {} of {} after {:?}", attempt, max_attempts, call_duration);
+                    }
+                    report_output_schema_drift(registered_provider, &response, &source_node_id);
+                    finish_call_load(
+                        &mut self.call_loads,
+                        &registered_provider.provider_name,
+                        call_duration.as_millis() as u64,
+                    );
+                    record_circuit_outcome(
+                        &mut self.circuit_breakers,
+                        &self.ws_subscriptions,
+                        &registered_provider.provider_name,
+                        &breaker_config,
+                        true,
+                    );
+                    publish_event(
+                        &self.ws_subscriptions,
+                        ProviderEvent::CallSucceeded {
+                            provider_name: registered_provider.provider_name.clone(),
+                            source_node: source_node_id.clone(),
+                            attempt,
+                            duration_ms: call_duration.as_millis(),
+                            response_size_bytes: response.len(),
+                        },
+                    );
+                    return Ok(limit_response(
+                        &mut self.response_continuations,
+                        registered_provider,
+                        Some(source_node_id),
+                        response,
+                        CONTINUATION_TTL_SECS,
+                    ));
+                },
+                Err(e) => {
+                    last_error = e.clone();
+                    error!(
+                        "provider_call_attempt_failed: provider={}, source_node={}, attempt={}, error_type=api_call_failed",
+                        registered_provider.provider_name,
+                        source_node_id,
+                        attempt
+                    );
+                    // Don't sleep after the last attempt
+                    if attempt < max_attempts {
+                        // Add a small delay between retries to handle rate limiting and temporary issues
+                        let _ = sleep(500).await;
+                    }
+                }
+            }
+        }
+
+        // If we get here, all retries failed
+        if let Some(pass_tx_hash) = &access_pass {
+            refund_access_pass(&mut self.access_passes, pass_tx_hash);
+        }
+        let total_duration = call_start_time.elapsed();
+        error!(
+            "provider_call_failed: provider={}, source_node={}, error_type=all_retries_failed, attempts={}, total_duration_ms={}",
+            registered_provider.provider_name,
+            source_node_id,
+            max_attempts,
+            total_duration.as_millis()
+        );
+        finish_call_load(
+            &mut self.call_loads,
+            &registered_provider.provider_name,
+            total_duration.as_millis() as u64,
+        );
+        record_circuit_outcome(
+            &mut self.circuit_breakers,
+            &self.ws_subscriptions,
+            &registered_provider.provider_name,
+            &breaker_config,
+            false,
+        );
+        publish_event(
+            &self.ws_subscriptions,
+            ProviderEvent::CallFailed {
+                provider_name: registered_provider.provider_name.clone(),
+                source_node: source_node_id.clone(),
+                error: last_error.clone(),
+            },
+        );
+        Err(last_error)
+    }
+
+    /// Background half of an async call, queued by `call_provider` right after payment
+    #[local]
+    async fn run_job(&mut self, job_id: String) -> Result<(), String> {
+        if source() != our() {
+            return Err("Jobs can only be started by this process".to_string());
+        }
+        let job = match self.jobs.iter_mut().find(|j| j.job_id == job_id) {
+            Some(job) if job.status == JobStatus::Pending => {
+                job.status = JobStatus::Running;
+                job.clone()
+            }
+            Some(_) => return Err(format!("Job '{}' has already started", job_id)),
+            None => return Err(format!("Job '{}' not found", job_id)),
+        };
+        let Some(provider) = self
+            .registered_providers
+            .iter()
+            .find(|p| p.provider_name == job.provider_name)
+            .cloned()
+        else {
+            let error_msg = format!("Provider '{}' is no longer registered", job.provider_name);
+            self.finish_job(&job_id, Err(error_msg.clone()));
+            return Err(error_msg);
+        };
+
+        let call_start_time = std::time::Instant::now();
+        begin_circuit_call(&mut self.circuit_breakers, &provider.provider_name);
+        begin_call_load(&mut self.call_loads, &provider.provider_name);
+        let outcome = invoke_provider(
+            &provider,
+            &job.arguments,
+            &CallerContext {
+                caller: job.node_id.clone(),
+                payment_tx_hash: job.payment_tx_hash.clone(),
+            },
+            &mut self.upstream_tokens,
+            RemoteSteps::Pay,
+            provider.upstream_timeout_seconds(),
+            SandboxMode::Off,
+        )
+        .await;
+        let call_duration = call_start_time.elapsed();
+        finish_call_load(&mut self.call_loads, &provider.provider_name, call_duration.as_millis() as u64);
+        record_circuit_outcome(
+            &mut self.circuit_breakers,
+            &self.ws_subscriptions,
+            &provider.provider_name,
+            &provider.circuit_breaker_config(),
+            outcome.is_ok(),
+        );
+
+        match &outcome {
+            Ok(response) => {
+                info!(
+                    "provider_job_success: provider={}, provider_node={}, source_node={}, job_id={}, tx_hash={}, price_usdc={}, duration_ms={}, response_size_bytes={}",
+                    provider.provider_name,
+                    our().node,
+                    job.node_id,
+                    job_id,
+                    job.payment_tx_hash.as_deref().unwrap_or("none"),
+                    provider.price,
+                    call_duration.as_millis(),
+                    response.len()
+                );
+                report_output_schema_drift(&provider, response, &job.node_id);
+                publish_event(
+                    &self.ws_subscriptions,
+                    ProviderEvent::CallSucceeded {
+                        provider_name: provider.provider_name.clone(),
+                        source_node: job.node_id.clone(),
+                        attempt: 1,
+                        duration_ms: call_duration.as_millis(),
+                        response_size_bytes: response.len(),
+                    },
+                );
+            }
+            Err(e) => {
+                error!(
+                    "provider_job_failed: provider={}, source_node={}, job_id={}, duration_ms={}",
+                    provider.provider_name,
+                    job.node_id,
+                    job_id,
+                    call_duration.as_millis()
+                );
+                publish_event(
+                    &self.ws_subscriptions,
+                    ProviderEvent::CallFailed {
+                        provider_name: provider.provider_name.clone(),
+                        source_node: job.node_id.clone(),
+                        error: e.clone(),
+                    },
+                );
+            }
+        }
+
+        // Results stay chunked for as long as the job itself can be fetched
+        let ttl_secs = provider
+            .async_job
+            .as_ref()
+            .map_or(CONTINUATION_TTL_SECS, |config| config.result_ttl_seconds.max(CONTINUATION_TTL_SECS));
+        let outcome = outcome.map(|response| {
+            limit_response(
+                &mut self.response_continuations,
+                &provider,
+                Some(job.node_id.clone()),
+                response,
+                ttl_secs,
+            )
+        });
+        self.finish_job(&job_id, outcome);
+        Ok(())
+    }
+
+    /// Further chunks of an oversized response, redeemable without paying again
+    #[local]
+    #[remote]
+    async fn get_response_chunk(&mut self, request: ChunkQuery) -> Result<String, String> {
+        check_protocol_version(request.protocol_version)?;
+        let source_node_id = source().node().to_string();
+        redeem_chunk(
+            &mut self.response_continuations,
+            &request.continuation_token,
+            request.chunk_index,
+            Some(&source_node_id),
+        )
+    }
+
+    /// Status, and once finished the result, of a job started by the requesting node
+    #[local]
+    #[remote]
+    async fn get_job_result(&mut self, request: JobQuery) -> Result<ProviderJob, String> {
+        check_protocol_version(request.protocol_version)?;
+        let source_node_id = source().node().to_string();
+        self.prune_finished_jobs(now_unix_secs());
+
+        self.jobs
+            .iter()
+            .find(|j| j.job_id == request.job_id && j.node_id == source_node_id)
+            .cloned()
+            .ok_or_else(|| format!("Job '{}' not found or expired", request.job_id))
+    }
+
+    #[local]
+    #[remote]
+    async fn purchase_access_pass(&mut self, request: AccessPassPurchase) -> Result<AccessPass, String> {
+        check_protocol_version(request.protocol_version)?;
+        let source_node_id = source().node().to_string();
+
+        info!(
+            "access_pass_purchase_started: provider={}, source_node={}, tx_hash={}",
+            request.provider_name, source_node_id, request.payment_tx_hash
+        );
+
+        let pass_config = self
+            .registered_providers
+            .iter()
+            .find(|p| p.provider_name == request.provider_name)
+            .ok_or_else(|| format!("Provider '{}' not found in registry", request.provider_name))?
+            .access_pass
+            .clone()
+            .ok_or_else(|| format!("Provider '{}' does not offer access passes", request.provider_name))?;
+
+        if let Err(validation_err) = validate_usdc_payment(
+            &request.provider_name,
+            Some(&request.payment_tx_hash),
+            pass_config.price,
+            self,
+            source_node_id.clone(),
+        )
+        .await
+        {
+            error!(
+                "access_pass_purchase_failed: provider={}, source_node={}, error_type=payment_validation_failed, validation_error={}",
+                request.provider_name, source_node_id, validation_err
+            );
+            publish_event(
+                &self.ws_subscriptions,
+                ProviderEvent::PaymentRejected {
+                    provider_name: request.provider_name.clone(),
+                    source_node: source_node_id.clone(),
+                    payment_method: "access_pass_purchase".to_string(),
+                    reason: validation_err.clone(),
+                },
+            );
+            return Err(validation_err);
+        }
+        publish_event(
+            &self.ws_subscriptions,
+            ProviderEvent::PaymentValidated {
+                provider_name: request.provider_name.clone(),
+                source_node: source_node_id.clone(),
+                payment_method: "access_pass_purchase".to_string(),
+                tx_hash: Some(request.payment_tx_hash.clone()),
+            },
+        );
+
+        let now = now_unix_secs();
+        let pass = AccessPass {
+            provider_name: request.provider_name.clone(),
+            node_id: source_node_id.clone(),
+            payment_tx_hash: request.payment_tx_hash,
+            purchased_at: now,
+            expires_at: now + pass_config.duration_seconds,
+            max_calls: pass_config.max_calls,
+            calls_used: 0,
+        };
+
+        // Drop passes that can no longer be used before storing the new one
+        self.access_passes.retain(|p| p.is_active(now));
+        self.access_passes.push(pass.clone());
+
+        info!(
+            "access_pass_purchase_success: provider={}, source_node={}, price_usdc={}, expires_at={}",
+            pass.provider_name, source_node_id, pass_config.price, pass.expires_at
+        );
+
+        Ok(pass)
+    }
+
+    /// Active passes held by the requesting node for the given provider
+    #[local]
+    #[remote]
+    async fn get_access_passes(&self, request: AccessPassQuery) -> Result<Vec<AccessPass>, String> {
+        check_protocol_version(request.protocol_version)?;
+        let source_node_id = source().node().to_string();
+        let now = now_unix_secs();
+
+        Ok(self
+            .access_passes
+            .iter()
+            .filter(|p| {
+                p.provider_name == request.provider_name
+                    && p.node_id == source_node_id
+                    && p.is_active(now)
+            })
+            .cloned()
+            .collect())
+    }
+
+    #[http]
+    async fn get_registered_providers(&self) -> Result<Vec<RegisteredProvider>, String> {
+        debug!("Fetching registered providers");
+        // /api is unauthenticated, so secrets never leave through it
+        Ok(self
+            .registered_providers
+            .iter()
+            .map(RegisteredProvider::redacted)
+            .collect())
+    }
+
+    /// Upload a CSV, JSON or JSONL dataset into the VFS drive, replacing a file of the same name
+    #[http]
+    async fn upload_dataset(&mut self, file_name: String, content: String) -> Result<DatasetInfo, String> {
+        let drive_path = self.vfs_drive_path.as_ref().ok_or("VFS drive not initialized")?;
+        let info = dataset::save_dataset(drive_path, &file_name, &content)?;
+        info!(
+            "dataset_uploaded: file={}, rows={}, size_bytes={}",
+            info.file_name, info.row_count, info.size_bytes
+        );
+        Ok(info)
+    }
 
-        // --- 1. Validate the payment ---
-        if let Err(validation_err) =
-            validate_transaction_payment(&mcp_request, self, source_node_id.clone()).await
-        {
-            // Error tracking log - payment validation failed
-            error!(
-                "provider_call_failed: provider={}, source_node={}, error_type=payment_validation_failed, validation_error={}",
-                mcp_request.provider_name,
-                source_node_id,
-                validation_err
-            );
-            return Err(validation_err);
-        }
-        // We can safely unwrap here since validate_transaction_payment already checked
-        // that the provider exists in the registered_providers list
-        let registered_provider = self
+    #[http]
+    async fn list_datasets(&self) -> Result<Vec<DatasetInfo>, String> {
+        let drive_path = self.vfs_drive_path.as_ref().ok_or("VFS drive not initialized")?;
+        dataset::list_datasets(drive_path)
+    }
+
+    /// Load an uploaded dataset into a table of one of the provider's sqlite databases,
+    /// replacing the table if it exists. Returns the number of rows imported.
+    #[http]
+    async fn import_dataset_to_sqlite(
+        &mut self,
+        file_name: String,
+        database: String,
+        table: String,
+    ) -> Result<u64, String> {
+        let drive_path = self.vfs_drive_path.as_ref().ok_or("VFS drive not initialized")?;
+        let rows = dataset::load_dataset_rows(drive_path, &file_name)?;
+        let row_count = sql::import_rows(&database, &table, rows).await?;
+        info!(
+            "dataset_imported_to_sqlite: file={}, database={}, table={}, rows={}",
+            file_name, database, table, row_count
+        );
+        Ok(row_count)
+    }
+
+    #[http]
+    async fn delete_dataset(&mut self, file_name: String) -> Result<(), String> {
+        let in_use: Vec<&str> = self
             .registered_providers
             .iter()
-            .find(|p| p.provider_name == mcp_request.provider_name)
-            .expect(&format!(
-                "Provider '{}' not found - this should never happen as it was validated in `validate_transaction_payment`",
-                mcp_request.provider_name
+            .filter(|p| matches!(&p.endpoint.kind, EndpointKind::Dataset(d) if d.file_name == file_name))
+            .map(|p| p.provider_name.as_str())
+            .collect();
+        if !in_use.is_empty() {
+            return Err(format!(
+                "Dataset '{}' is served by providers: {}",
+                file_name,
+                in_use.join(", ")
             ));
+        }
+        let drive_path = self.vfs_drive_path.as_ref().ok_or("VFS drive not initialized")?;
+        dataset::delete_dataset(drive_path, &file_name)
+    }
 
-        // --- 2. Call the provider with retry mechanism ---
-        const MAX_RETRIES: usize = 3;
-        let mut last_error = String::new();
-        let call_start_time = std::time::Instant::now();
-        for attempt in 1..=MAX_RETRIES {
-            debug!("Attempting provider call {} of {}", attempt, MAX_RETRIES);
+    /// Set the wallet that submits and pays gas for self-settled x402 payments; returns its address.
+    /// Local only, so the key never crosses the unauthenticated `/api` binding.
+    #[local]
+    async fn set_x402_relayer(&mut self, private_key: String) -> Result<String, String> {
+        let drive_path = self.vfs_drive_path.as_ref().ok_or("VFS drive not initialized")?;
+        let wallet = save_relayer_wallet(drive_path, &private_key)?;
+        let address = wallet.address.clone();
+        self.x402_relayer = Some(wallet);
+        info!("x402_relayer_configured: address={}", address);
+        Ok(address)
+    }
 
-            let api_call_result = call_provider(
-                // This is the HTTP call_provider
-                registered_provider.provider_name.clone(),
-                registered_provider.endpoint.clone(),
-                &mcp_request.arguments,
-                source_node_id.clone(), // this makes sure User-Agent is node ID
-            )
-            .await;
+    #[http]
+    async fn get_x402_relayer_address(&self) -> Result<Option<String>, String> {
+        Ok(self.x402_relayer.as_ref().map(|w| w.address.clone()))
+    }
 
-            match api_call_result {
-                Ok(response) => {
-                    let call_duration = call_start_time.elapsed();
+    /// Settle x402 payments through the facilitator or from the relayer wallet
+    #[http]
+    async fn set_x402_settlement(&mut self, mode: X402Settlement) -> Result<X402Settlement, String> {
+        if mode == X402Settlement::SelfSettle && self.x402_relayer.is_none() {
+            warn!("x402_settlement_changed: mode=SelfSettle without a relayer wallet, the facilitator settles until one is set");
+        }
+        info!("x402_settlement_changed: from={:?}, to={:?}", self.x402_settlement, mode);
+        self.x402_settlement = mode;
+        Ok(mode)
+    }
 
-                    // Success tracking log - no sensitive data
-                    info!(
-                        "provider_call_success: provider={}, provider_node={}, source_node={}, tx_hash={}, price_usdc={}, attempt={}, duration_ms={}, response_size_bytes={}",
-                        registered_provider.provider_name,
-                        our().node,
-                        source_node_id,
-                        mcp_request.payment_tx_hash.as_deref().unwrap_or("none"),
-                        registered_provider.price,
-                        attempt,
-                        call_duration.as_millis(),
-                        response.len()
-                    );
+    #[http]
+    async fn get_x402_settlement(&self) -> Result<X402Settlement, String> {
+        Ok(self.x402_settlement)
+    }
 
-                    if attempt > 1 {
-                        debug!("Provider call succeeded on attempt {} of {} after {:?}", attempt, MAX_RETRIES, call_duration);
-                    }
-                    return Ok(response);
-                },
-                Err(e) => {
-                    last_error = e.clone();
-                    error!(
-                        "provider_call_attempt_failed: provider={}, source_node={}, attempt={}, error_type=api_call_failed",
-                        registered_provider.provider_name,
-                        source_node_id,
-                        attempt
-                    );
-                    // Don't sleep after the last attempt
-                    if attempt < MAX_RETRIES {
-                        // Add a small delay between retries to handle rate limiting and temporary issues
-                        let _ = sleep(500).await;
-                    }
-                }
-            }
-        }
+    /// Switch validation and test calls between live, record and replay; local only, like
+    /// the terminal command
+    #[local]
+    async fn set_sandbox_mode(&mut self, mode: SandboxMode) -> Result<SandboxMode, String> {
+        info!("sandbox_mode_changed: from={:?}, to={:?}", self.sandbox_mode, mode);
+        self.sandbox_mode = mode;
+        Ok(mode)
+    }
 
-        // If we get here, all retries failed
-        let total_duration = call_start_time.elapsed();
-        error!(
-            "provider_call_failed: provider={}, source_node={}, error_type=all_retries_failed, attempts={}, total_duration_ms={}",
-            registered_provider.provider_name,
-            source_node_id,
-            MAX_RETRIES,
-            total_duration.as_millis()
-        );
-        Err(last_error)
+    #[http]
+    async fn get_sandbox_mode(&self) -> Result<SandboxMode, String> {
+        Ok(self.sandbox_mode)
     }
 
     #[http]
-    async fn get_registered_providers(&self) -> Result<Vec<RegisteredProvider>, String> {
-        debug!("Fetching registered providers");
-        Ok(self.registered_providers.clone())
+    async fn get_sandbox_fixtures(&self, provider_name: String) -> Result<Vec<SandboxFixture>, String> {
+        sandbox::load_fixtures(&provider_name)
+    }
+
+    /// Replace a provider's fixtures, e.g. with hand-edited error responses; an empty list deletes them
+    #[local]
+    async fn set_sandbox_fixtures(
+        &mut self,
+        provider_name: String,
+        fixtures: Vec<SandboxFixture>,
+    ) -> Result<(), String> {
+        if fixtures.is_empty() {
+            return sandbox::delete_fixtures(&provider_name);
+        }
+        sandbox::save_fixtures(&provider_name, &fixtures)
     }
 
     #[http]
@@ -993,7 +2393,7 @@ impl HypergridProviderState {
             .registered_providers
             .iter()
             .filter(|provider| provider.endpoint.is_empty())
-            .cloned()
+            .map(RegisteredProvider::redacted)
             .collect();
         debug!("Found {} providers needing endpoint configuration", providers_needing_config.len());
         Ok(providers_needing_config)
@@ -1005,6 +2405,33 @@ impl HypergridProviderState {
         self.export_providers_json()
     }
 
+    /// x402 discovery: every configured provider with its payment requirements and input
+    /// schema, so x402 clients can find providers without knowing their names
+    #[http(path = "/.well-known/x402")]
+    async fn handle_x402_discovery(&self) -> Vec<u8> {
+        add_response_header("Content-Type".to_string(), "application/json".to_string());
+
+        // Same fallback as /xfour; get_request_url() should always succeed in HTTP context
+        let request_url = get_request_url()
+            .unwrap_or_else(|| format!("http://unknown/provider:hypergrid:test.hypr{}", X402_DISCOVERY_PATH));
+        let base_url = request_url.split(X402_DISCOVERY_PATH).next().unwrap_or_default();
+
+        let items: Vec<DiscoveredResource> = self
+            .registered_providers
+            .iter()
+            .filter(|provider| !provider.endpoint.is_empty())
+            .map(|provider| discovered_resource(provider, base_url))
+            .collect();
+        debug!("x402_discovery: providers={}", items.len());
+
+        set_response_status(StatusCode::OK);
+        serde_json::to_vec(&DiscoveryResponse {
+            protocol_version: 1,
+            items,
+        })
+        .unwrap()
+    }
+
     /// HTTP 402 Payment Required endpoint for x402 micropayment protocol
     ///
     /// This endpoint implements the x402 payment flow:
@@ -1034,6 +2461,27 @@ impl HypergridProviderState {
             }
         };
 
+        // ===== CONTINUATION: REMAINING CHUNKS OF A PAID RESPONSE =====
+        if let Some(token) = params.get("continuation_token") {
+            let chunk_index = match params.get("chunk_index").map(|i| i.parse::<u64>()) {
+                Some(Ok(index)) => index,
+                _ => {
+                    let error_json = serde_json::json!({"error": "Missing or invalid parameter: chunk_index"});
+                    return serde_json::to_vec(&error_json).unwrap();
+                }
+            };
+            return match redeem_chunk(&mut self.response_continuations, token, chunk_index, None) {
+                Ok(chunk) => {
+                    set_response_status(StatusCode::OK);
+                    chunk.into_bytes()
+                }
+                Err(e) => {
+                    set_response_status(StatusCode::NOT_FOUND);
+                    serde_json::to_vec(&serde_json::json!({"error": e})).unwrap()
+                }
+            };
+        }
+
         // ===== SHARED: PROVIDER NAME EXTRACTION =====
         let provider_name = match params.get("providername") {
             Some(name) => name,
@@ -1091,78 +2539,135 @@ impl HypergridProviderState {
             // Rebuild PaymentRequirements for verification
             let payment_requirements = build_payment_requirements(&provider, &resource_url);
 
-            // Find the matching payment method based on scheme and network
+            // Find the advertised option the payment was made for
             let payment_method = payment_requirements.accepts
-                .as_ref()
-                .and_then(|accepts| {
-                    accepts.iter()
-                        .find(|method| {
-                            method.scheme == payment_payload.scheme &&
-                            method.network == payment_payload.network
-                        })
-                        .cloned()
-                });
+                .as_deref()
+                .and_then(|accepts| match_payment_method(accepts, &payment_payload))
+                .cloned();
 
             let payment_method = match payment_method {
                 Some(method) => {
-                    info!("Found matching payment method for scheme: {}, network: {}",
-                        payment_payload.scheme, payment_payload.network);
+                    info!("Found matching payment method for scheme: {}, network: {}, asset: {}",
+                        payment_payload.scheme, payment_payload.network, method.asset);
                     method
                 },
                 None => {
                     error!("No matching payment method found for scheme: {}, network: {}",
                         payment_payload.scheme, payment_payload.network);
                     let error_json = serde_json::json!({
-                        "error": format!("No matching payment method for scheme: {}, network: {}",
-                            payment_payload.scheme, payment_payload.network)
+                        "error": format!("No matching payment method for scheme: {}, network: {}, pay to: {}, amount: {}",
+                            payment_payload.scheme, payment_payload.network,
+                            payment_payload.payload.authorization.to, payment_payload.payload.authorization.value)
                     });
                     let error_bytes = serde_json::to_vec(&error_json).unwrap();
                     return error_bytes;
                 }
             };
 
-            // Build facilitator verify request with the matched payment method
-            let verify_request = FacilitatorVerifyRequest {
-                protocol_version: 1,
-                payment_payload: payment_payload.clone(),
-                payment_requirements: payment_method,
-            };
-
-            let verify_body = serde_json::to_vec(&verify_request)
-                .expect("Failed to serialize verify request");
+            let args_vec: Vec<(String, String)> = params.iter()
+                .filter(|(k, _)| k != &"providername")
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
 
-            // Call facilitator /verify
-            let verify_url = url::Url::parse(&format!("{}/verify", X402_FACILITATOR_BASE_URL))
-                .expect("Invalid facilitator URL");
+            // A settled payment is never taken twice; retrying the same call gets its response again
+            let nonce_key = nonce_key(&payment_method, &payment_payload);
+            let request_key = paid_request_key(&provider.provider_name, &args_vec);
+            match check_replay(
+                &self.x402_served,
+                &self.x402_retry_responses,
+                &nonce_key,
+                &request_key,
+                &payment_payload.payload.signature,
+                now_unix_secs(),
+            ) {
+                Ok(None) => {}
+                Ok(Some((cached_response, settle_result))) => {
+                    info!(
+                        "x402_retry_served_from_cache: provider={}, payer={}, tx_hash={:?}",
+                        provider.provider_name, settle_result.payer, settle_result.transaction
+                    );
+                    set_response_status(StatusCode::OK);
+                    add_response_header("X-PAYMENT-RESPONSE".to_string(), encode_settle_response(&settle_result));
+                    return limit_response(
+                        &mut self.response_continuations,
+                        &provider,
+                        None,
+                        cached_response,
+                        CONTINUATION_TTL_SECS,
+                    )
+                    .into_bytes();
+                }
+                Err(reason) => {
+                    warn!(
+                        "x402_replay_refused: provider={}, payer={}, reason={}",
+                        provider.provider_name, payment_payload.payload.authorization.from, reason
+                    );
+                    publish_event(
+                        &self.ws_subscriptions,
+                        ProviderEvent::PaymentRejected {
+                            provider_name: provider.provider_name.clone(),
+                            source_node: payment_payload.payload.authorization.from.clone(),
+                            payment_method: "x402".to_string(),
+                            reason: reason.clone(),
+                        },
+                    );
+                    let mut error_payment_reqs = payment_requirements.clone();
+                    error_payment_reqs.error = Some(reason);
+                    set_response_status(StatusCode::PAYMENT_REQUIRED);
+                    return serde_json::to_vec(&error_payment_reqs).unwrap();
+                }
+            }
 
-            let mut verify_headers = HashMap::new();
-            verify_headers.insert("Content-Type".to_string(), "application/json".to_string());
+            // ===== CIRCUIT BREAKER =====
+            // Retries of settled calls were answered above; take no new payment while the
+            // upstream is failing
+            if let Err(unavailable) = check_circuit(&self.circuit_breakers, &provider.provider_name) {
+                warn!("{}", unavailable);
+                set_response_status(StatusCode::SERVICE_UNAVAILABLE);
+                return serde_json::to_vec(&serde_json::json!({"error": unavailable})).unwrap();
+            }
 
-            let verify_response = match send_async_http_request(
-                HyperwareHttpMethod::POST,
-                verify_url,
-                Some(verify_headers),
-                30,
-                verify_body,
+            // Verified here; the facilitator or the relayer only settles
+            let mut verify_result = match verify_exact_payment(
+                &payment_payload,
+                &payment_method,
+                &self.x402_pending_nonces,
+                settlement_margin_secs(&provider),
+                now_unix_secs(),
             ).await {
-                Ok(resp) => resp,
+                Ok(result) => result,
                 Err(e) => {
-                    error!("Facilitator /verify request failed: {:?}", e);
-                    let error_json = serde_json::json!({"error": "Payment verification service unavailable"});
-                    let error_bytes = serde_json::to_vec(&error_json).unwrap();
+                    error!("x402_verification_unavailable: provider={}, error={}", provider.provider_name, e);
+                    let error_json = serde_json::json!({"error": "Payment verification unavailable, please retry"});
                     set_response_status(StatusCode::SERVICE_UNAVAILABLE);
-                    return error_bytes;
+                    return serde_json::to_vec(&error_json).unwrap();
                 }
             };
+            // Held until settlement, so the same authorization can't pay for a concurrent call.
+            // Verification awaits the chain, so another call may have taken the nonce meanwhile.
+            if verify_result.is_valid && !self.x402_pending_nonces.insert(nonce_key.clone()) {
+                verify_result.is_valid = false;
+                verify_result.invalid_reason =
+                    Some("invalid_exact_evm_payload_authorization_nonce_in_use".to_string());
+            }
 
-            info!("Facilitator /verify response: {:?}", verify_response.status());
-
-            // Parse verify response
-            let verify_result: VerifyResponse = serde_json::from_slice(verify_response.body())
-                .expect("Failed to parse verify response");
+            let verify_request = FacilitatorVerifyRequest {
+                protocol_version: 1,
+                payment_payload: payment_payload.clone(),
+                payment_requirements: payment_method,
+            };
 
             if !verify_result.is_valid {
                 warn!("Payment verification failed: {:?}", verify_result.invalid_reason);
+                publish_event(
+                    &self.ws_subscriptions,
+                    ProviderEvent::PaymentRejected {
+                        provider_name: provider.provider_name.clone(),
+                        source_node: verify_result.payer.clone(),
+                        payment_method: "x402".to_string(),
+                        reason: verify_result.invalid_reason.clone().unwrap_or_default(),
+                    },
+                );
                 let mut error_payment_reqs = payment_requirements.clone();
                 error_payment_reqs.error = Some(verify_result.invalid_reason.unwrap_or_else(|| "Payment verification failed".to_string()));
                 let error_bytes = serde_json::to_vec(&error_payment_reqs).unwrap();
@@ -1171,22 +2676,58 @@ impl HypergridProviderState {
             }
 
             info!("Payment verified for payer: {}", verify_result.payer);
+            publish_event(
+                &self.ws_subscriptions,
+                ProviderEvent::PaymentValidated {
+                    provider_name: provider.provider_name.clone(),
+                    source_node: verify_result.payer.clone(),
+                    payment_method: "x402".to_string(),
+                    tx_hash: None,
+                },
+            );
 
             // Call upstream provider API
-            let args_vec: Vec<(String, String)> = params.iter()
-                .filter(|(k, _)| k != &"providername")
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .collect();
-
-            let upstream_response = match call_provider(
-                provider.provider_name.clone(),
-                provider.endpoint.clone(),
+            let call_start_time = std::time::Instant::now();
+            begin_circuit_call(&mut self.circuit_breakers, &provider.provider_name);
+            begin_call_load(&mut self.call_loads, &provider.provider_name);
+            let upstream_response = invoke_provider(
+                &provider,
                 &args_vec,
-                our().node.to_string(),
-            ).await {
+                // Settlement happens after the call, so there is no tx hash yet
+                &CallerContext {
+                    caller: verify_result.payer.clone(),
+                    payment_tx_hash: None,
+                },
+                &mut self.upstream_tokens,
+                RemoteSteps::Pay,
+                provider.upstream_timeout_seconds(),
+                SandboxMode::Off,
+            ).await;
+            finish_call_load(
+                &mut self.call_loads,
+                &provider.provider_name,
+                call_start_time.elapsed().as_millis() as u64,
+            );
+            record_circuit_outcome(
+                &mut self.circuit_breakers,
+                &self.ws_subscriptions,
+                &provider.provider_name,
+                &provider.circuit_breaker_config(),
+                upstream_response.is_ok(),
+            );
+            let upstream_response = match upstream_response {
                 Ok(resp) => resp,
                 Err(e) => {
                     error!("Upstream API call failed: {}", e);
+                    publish_event(
+                        &self.ws_subscriptions,
+                        ProviderEvent::CallFailed {
+                            provider_name: provider.provider_name.clone(),
+                            source_node: verify_result.payer.clone(),
+                            error: e.clone(),
+                        },
+                    );
+                    self.x402_pending_nonces.remove(&nonce_key);
                     let error_json = serde_json::json!({"error": format!("Provider API call failed: {}", e)});
                     let error_bytes = serde_json::to_vec(&error_json).unwrap();
                     set_response_status(StatusCode::BAD_GATEWAY);
@@ -1194,54 +2735,31 @@ impl HypergridProviderState {
                 }
             };
 
-            info!("Upstream API call successful, settling payment");
-
-            // Call facilitator /settle
-            let settle_body = serde_json::to_vec(&verify_request)
-                .expect("Failed to serialize settle request");
+            report_output_schema_drift(&provider, &upstream_response, &verify_result.payer);
 
-            let settle_url = url::Url::parse(&format!("{}/settle", X402_FACILITATOR_BASE_URL))
-                .expect("Invalid facilitator URL");
+            info!("Upstream API call successful, settling payment");
 
-            let mut settle_headers = HashMap::new();
-            settle_headers.insert("Content-Type".to_string(), "application/json".to_string());
+            let settle_result = settle_payment(
+                self.x402_settlement,
+                self.x402_relayer.as_ref(),
+                &verify_request,
+                &verify_result.payer,
+            )
+            .await;
 
-            // Call facilitator /settle and parse response
-            let settle_result: SettleResponse = match send_async_http_request(
-                HyperwareHttpMethod::POST,
-                settle_url,
-                Some(settle_headers),
-                30,
-                settle_body,
-            ).await {
-                Ok(http_response) => {
-                    info!("Facilitator /settle response: {:?}", http_response.status());
-
-                    // Parse the HTTP response body into SettleResponse
-                    serde_json::from_slice(http_response.body())
-                        .unwrap_or_else(|e| {
-                            error!("Failed to parse settlement response: {}", e);
-                            SettleResponse {
-                                success: false,
-                                payer: verify_result.payer.clone(),
-                                transaction: None,
-                                network: payment_payload.network.clone(),
-                                error_reason: Some(format!("Failed to parse settlement response: {}", e)),
-                            }
-                        })
-                }
-                Err(e) => {
-                    // HTTP request failed - settlement service unavailable
-                    error!("Facilitator /settle request failed but continuing: {:?}", e);
-                    SettleResponse {
-                        success: false,
-                        payer: verify_result.payer.clone(),
-                        transaction: None,
-                        network: payment_payload.network.clone(),
-                        error_reason: Some(format!("Settlement service error: {:?}", e)),
-                    }
-                }
-            };
+            // Settled authorizations are marked used on chain from here on
+            self.x402_pending_nonces.remove(&nonce_key);
+
+            publish_event(
+                &self.ws_subscriptions,
+                ProviderEvent::X402Settled {
+                    provider_name: provider.provider_name.clone(),
+                    payer: settle_result.payer.clone(),
+                    success: settle_result.success,
+                    transaction: settle_result.transaction.clone(),
+                    error_reason: settle_result.error_reason.clone(),
+                },
+            );
 
             // Reject request if settlement fails - provider does not get paid
             if !settle_result.success {
@@ -1255,26 +2773,61 @@ impl HypergridProviderState {
                 return error_bytes;
             }
 
-            // Encode settle response for X-PAYMENT-RESPONSE header
-            let settle_json = serde_json::to_vec(&settle_result)
-                .expect("Failed to serialize settle response");
-
-            // Base64 encode for header
-            let encoded_len = Base64::encoded_len(&settle_json);
-            let mut buf = vec![0u8; encoded_len];
-            let settle_b64 = Base64::encode(&settle_json, &mut buf)
-                .expect("Failed to base64 encode settlement response")
-                .to_string();
+            let now = now_unix_secs();
+            if let Some(drive_path) = &self.vfs_drive_path {
+                if let Err(e) = save_retry_response(drive_path, &nonce_key, &upstream_response) {
+                    error!("x402_retry_response_unsaved: provider={}, error={}", provider_name, e);
+                }
+            }
+            let expired = record_served(
+                &mut self.x402_served,
+                &mut self.x402_retry_responses,
+                nonce_key,
+                ServedPayment {
+                    request_key,
+                    signature: payment_payload.payload.signature.clone(),
+                    payer: settle_result.payer.clone(),
+                    network: settle_result.network.clone(),
+                    transaction: settle_result.transaction.clone(),
+                    settled_at: now,
+                    valid_before: payment_payload
+                        .payload
+                        .authorization
+                        .valid_before
+                        .parse()
+                        .unwrap_or(u64::MAX),
+                },
+                upstream_response.clone(),
+                now,
+            );
+            if let Some(drive_path) = &self.vfs_drive_path {
+                for key in expired {
+                    remove_retry_response(drive_path, &key);
+                }
+            }
 
             // Return upstream response with X-PAYMENT-RESPONSE header
             set_response_status(StatusCode::OK);
-            add_response_header("X-PAYMENT-RESPONSE".to_string(), settle_b64);
+            add_response_header("X-PAYMENT-RESPONSE".to_string(), encode_settle_response(&settle_result));
 
             info!("Payment flow completed successfully for provider '{}'", provider_name);
-            return upstream_response.into_bytes();
+            return limit_response(
+                &mut self.response_continuations,
+                &provider,
+                None,
+                upstream_response,
+                CONTINUATION_TTL_SECS,
+            )
+            .into_bytes();
         }
 
         // ===== BRANCH: 402 PAYMENT REQUIRED FLOW =====
+        // Don't ask for a payment while the upstream is failing
+        if let Err(unavailable) = check_circuit(&self.circuit_breakers, &provider.provider_name) {
+            warn!("{}", unavailable);
+            set_response_status(StatusCode::SERVICE_UNAVAILABLE);
+            return serde_json::to_vec(&serde_json::json!({"error": unavailable})).unwrap();
+        }
         info!("No X-PAYMENT header, returning 402 Payment Required");
 
         let payment_reqs = build_payment_requirements(&provider, &resource_url);
@@ -1383,6 +2936,40 @@ impl HypergridProviderState {
         })
     }
 
+    /// Compare the registry with Hypermap every `SYNC_CHECK_INTERVAL_MS` and push `SyncDrift`
+    /// while they disagree. Started at init and re-queued by itself after each check.
+    #[local]
+    async fn check_sync_drift(&mut self) -> Result<(), String> {
+        if source() != our() {
+            return Err("Sync checks can only be started by this process".to_string());
+        }
+        let _ = sleep(SYNC_CHECK_INTERVAL_MS).await;
+
+        let outcome = match load_provider_db().await {
+            Ok(db) => compare_with_indexed_state(&self.registered_providers, &db)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        match outcome {
+            Ok(comparison) if !comparison.is_synchronized() => {
+                warn!("provider_sync_drift: {}", comparison.summary());
+                publish_event(
+                    &self.ws_subscriptions,
+                    ProviderEvent::SyncDrift {
+                        missing_from_index: comparison.missing_from_index.clone(),
+                        mismatched: comparison.mismatched.clone(),
+                    },
+                );
+            }
+            Ok(_) => debug!("Provider registry matches Hypermap"),
+            Err(e) => warn!("provider_sync_check_failed: error={}", e),
+        }
+
+        Self::queue_sync_check();
+        Ok(())
+    }
+
     /// Get provider state synchronization status
     #[http]
     async fn get_provider_sync_status(&self) -> Result<String, String> {
@@ -1420,7 +3007,7 @@ impl HypergridProviderState {
                     self.registered_providers
                 ))
             }
-            TerminalCommand::RegisterProvider(provider) => {
+            TerminalCommand::RegisterProvider(mut provider) => {
                 debug!("Registering provider: {:?}", provider);
                 if self
                     .registered_providers
@@ -1434,11 +3021,20 @@ impl HypergridProviderState {
                     warn!("{}", error_msg);
                     return Err(error_msg);
                 }
+                pin_upstream_hosts(&mut provider)?;
+                validate_provider_definition(&provider)?;
                 self.registered_providers.push(provider.clone());
+                self.record_revision(&provider.provider_name, &provider, RevisionAction::Register, None);
                 debug!(
                     "Successfully registered provider: {}",
                     provider.provider_name
                 );
+                publish_event(
+                    &self.ws_subscriptions,
+                    ProviderEvent::ProviderRegistered {
+                        provider_name: provider.provider_name.clone(),
+                    },
+                );
 
                 // Save to VFS
                 if let Err(e) = self.save_providers_to_vfs() {
@@ -1454,6 +3050,10 @@ impl HypergridProviderState {
                 debug!("Unregistering provider: {}", provider_name);
                 self.registered_providers
                     .retain(|p| p.provider_name != provider_name);
+                self.clear_upstream_tokens(&provider_name);
+                if let Some(drive_path) = &self.vfs_drive_path {
+                    delete_revisions(drive_path, &provider_name);
+                }
 
                 // Save to VFS
                 if let Err(e) = self.save_providers_to_vfs() {
@@ -1497,11 +3097,18 @@ impl HypergridProviderState {
 
                 debug!("Registered provider: {:?}", registered_provider);
 
-                let result = call_provider(
-                    registered_provider.provider_name.clone(),
-                    registered_provider.endpoint.clone(),
+                let result = invoke_provider(
+                    registered_provider,
                     &provider_request.arguments,
-                    source_node_id,
+                    &CallerContext {
+                        caller: source_node_id,
+                        payment_tx_hash: provider_request.payment_tx_hash.clone(),
+                    },
+                    &mut self.upstream_tokens,
+                    // A test call is not paid for, so it must not pay downstream providers either
+                    RemoteSteps::DryRun,
+                    registered_provider.upstream_timeout_seconds(),
+                    self.sandbox_mode,
                 )
                 .await;
 
@@ -1512,6 +3119,11 @@ impl HypergridProviderState {
                     Err(e) => Err(e),
                 }
             }
+            TerminalCommand::SetSandboxMode(mode) => {
+                info!("sandbox_mode_changed: from={:?}, to={:?}", self.sandbox_mode, mode);
+                self.sandbox_mode = mode;
+                Ok(format!("Sandbox mode set to {:?}", mode))
+            }
             TerminalCommand::ExportProviders => {
                 debug!("Exporting providers as JSON");
                 match self.export_providers_json() {
@@ -1567,7 +3179,98 @@ impl EndpointDefinition {
             original_body: None,
             parameters: Vec::new(),
             parameter_names: Vec::new(),
+            upstream_auth: None,
+            request_signers: Vec::new(),
+            kind: EndpointKind::Curl,
+            allowed_hosts: Vec::new(),
+            allow_private_network: false,
+        }
+    }
+
+    /// Copy of this endpoint with upstream auth and signer secrets blanked out, for exports
+    pub fn redacted(&self) -> Self {
+        let upstream_auth = self.upstream_auth.clone().map(|auth| match auth {
+            UpstreamAuth::OAuth2ClientCredentials(config) => {
+                UpstreamAuth::OAuth2ClientCredentials(OAuth2ClientCredentials {
+                    client_secret: String::new(),
+                    ..config
+                })
+            }
+            UpstreamAuth::OAuth2RefreshToken(config) => {
+                UpstreamAuth::OAuth2RefreshToken(OAuth2RefreshToken {
+                    client_secret: None,
+                    refresh_token: String::new(),
+                    ..config
+                })
+            }
+        });
+        let request_signers = self
+            .request_signers
+            .iter()
+            .cloned()
+            .map(|signer| match signer {
+                RequestSigner::HmacSha256(config) => RequestSigner::HmacSha256(HmacSha256Signer {
+                    secret: String::new(),
+                    ..config
+                }),
+                RequestSigner::AwsSigV4(config) => RequestSigner::AwsSigV4(AwsSigV4Signer {
+                    secret_access_key: String::new(),
+                    session_token: None,
+                    ..config
+                }),
+            })
+            .collect();
+        Self {
+            upstream_auth,
+            request_signers,
+            ..self.clone()
+        }
+    }
+
+    /// Fill secrets left blank (as `redacted` leaves them) from the stored definition, so an
+    /// edited copy that went through the UI keeps its credentials
+    pub fn with_stored_secrets(mut self, stored: &EndpointDefinition) -> Self {
+        match (self.upstream_auth.as_mut(), stored.upstream_auth.as_ref()) {
+            (
+                Some(UpstreamAuth::OAuth2ClientCredentials(config)),
+                Some(UpstreamAuth::OAuth2ClientCredentials(stored)),
+            ) => {
+                if config.client_secret.is_empty() {
+                    config.client_secret = stored.client_secret.clone();
+                }
+            }
+            (
+                Some(UpstreamAuth::OAuth2RefreshToken(config)),
+                Some(UpstreamAuth::OAuth2RefreshToken(stored)),
+            ) => {
+                if config.refresh_token.is_empty() {
+                    config.refresh_token = stored.refresh_token.clone();
+                }
+                if config.client_secret.as_deref().unwrap_or_default().is_empty() {
+                    config.client_secret = stored.client_secret.clone();
+                }
+            }
+            _ => {}
+        }
+        for (signer, stored) in self.request_signers.iter_mut().zip(&stored.request_signers) {
+            match (signer, stored) {
+                (RequestSigner::HmacSha256(config), RequestSigner::HmacSha256(stored)) => {
+                    if config.secret.is_empty() {
+                        config.secret = stored.secret.clone();
+                    }
+                }
+                (RequestSigner::AwsSigV4(config), RequestSigner::AwsSigV4(stored)) => {
+                    if config.secret_access_key.is_empty() {
+                        config.secret_access_key = stored.secret_access_key.clone();
+                    }
+                    if config.session_token.as_deref().unwrap_or_default().is_empty() {
+                        config.session_token = stored.session_token.clone();
+                    }
+                }
+                _ => {}
+            }
         }
+        self
     }
 
     /// Check if this endpoint definition is empty (needs configuration)
@@ -1591,3 +3294,59 @@ impl EndpointDefinition {
     }
 }
 
+impl RegisteredProvider {
+    /// Copy with secrets blanked out on the endpoint and on every local pipeline step
+    pub fn redacted(&self) -> Self {
+        let pipeline = self.pipeline.as_ref().map(|pipeline| Pipeline {
+            steps: pipeline
+                .steps
+                .iter()
+                .map(|step| PipelineStep {
+                    target: match &step.target {
+                        PipelineTarget::Local(endpoint) => PipelineTarget::Local(endpoint.redacted()),
+                        remote => remote.clone(),
+                    },
+                    ..step.clone()
+                })
+                .collect(),
+        });
+        Self {
+            endpoint: self.endpoint.redacted(),
+            pipeline,
+            ..self.clone()
+        }
+    }
+
+    /// Fill blank secrets on the endpoint and on local pipeline steps from the stored provider
+    pub fn with_stored_secrets(mut self, stored: &RegisteredProvider) -> Self {
+        self.endpoint = self.endpoint.with_stored_secrets(&stored.endpoint);
+        if let (Some(pipeline), Some(stored)) = (self.pipeline.as_mut(), stored.pipeline.as_ref()) {
+            for (step, stored) in pipeline.steps.iter_mut().zip(&stored.steps) {
+                if let (PipelineTarget::Local(endpoint), PipelineTarget::Local(stored)) =
+                    (&mut step.target, &stored.target)
+                {
+                    *endpoint = endpoint.clone().with_stored_secrets(stored);
+                }
+            }
+        }
+        self
+    }
+
+    pub fn circuit_breaker_config(&self) -> CircuitBreakerConfig {
+        self.circuit_breaker.clone().unwrap_or(DEFAULT_CIRCUIT_BREAKER)
+    }
+
+    /// Upstream timeout: the async job's when configured, otherwise the synchronous default
+    pub fn upstream_timeout_seconds(&self) -> u64 {
+        self.async_job
+            .as_ref()
+            .map_or(DEFAULT_UPSTREAM_TIMEOUT_SECS, |job| job.upstream_timeout_seconds)
+    }
+}
+
+impl AccessPass {
+    /// A pass is usable until it expires or runs out of calls
+    pub fn is_active(&self, now: u64) -> bool {
+        now < self.expires_at && self.max_calls.map_or(true, |max| self.calls_used < max)
+    }
+}
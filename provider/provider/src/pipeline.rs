@@ -0,0 +1,476 @@
+use crate::constants::PUBLISHER;
+use crate::attestation::CallerContext;
+use crate::protocol::{PROTOCOL_VERSION, SUPPORTED_PROTOCOL_VERSIONS};
+use crate::upstream_auth::CachedAccessToken;
+use crate::util::{call_provider, now_unix_secs};
+use crate::{
+    ArgumentSource, ChunkQuery, HealthCheckCall, HealthStatus, JobQuery, JobStatus, Pipeline,
+    PipelineStep, PipelineTarget, ProviderJob, RegisteredProvider, RemoteProviderStep, SandboxMode,
+};
+use hyperware_process_lib::{
+    eth::Address as EthAddress,
+    get_blob,
+    hyperapp::{send, sleep},
+    logging::{debug, info, warn},
+    our, Address, Request,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+/// Timeout for the operator to pay and call a downstream Hypergrid provider, in seconds
+const REMOTE_STEP_TIMEOUT_SECS: u64 = 120;
+
+/// Timeout for a downstream provider to answer a health ping, job poll or chunk request, in seconds
+const REMOTE_PING_TIMEOUT_SECS: u64 = 10;
+
+/// How often a pipeline polls a downstream async job, in milliseconds
+const REMOTE_JOB_POLL_INTERVAL_MS: u64 = 2000;
+
+/// How a pipeline run treats its remote steps
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RemoteSteps {
+    /// Pay and call them through this node's operator
+    Pay,
+    /// Health-ping them without paying and stop before the first one (validation)
+    DryRun,
+}
+
+/// Status part of the operator's HTTP-shaped answer; the JSON body comes in the blob
+#[derive(Deserialize)]
+struct OperatorResponse {
+    status: u16,
+}
+
+/// Ticket a downstream async provider answers with instead of its result
+#[derive(Deserialize)]
+struct JobTicket {
+    job_id: String,
+    provider_name: String,
+    status: JobStatus,
+}
+
+/// One chunk of a downstream response that exceeded the provider's size limit
+#[derive(Deserialize)]
+struct ChunkEnvelope {
+    chunk: String,
+    total_chunks: u64,
+    continuation_token: String,
+}
+
+/// Structural checks run before a composite provider is registered or updated
+pub fn validate_pipeline(provider: &RegisteredProvider) -> Result<(), String> {
+    let Some(pipeline) = &provider.pipeline else {
+        return Ok(());
+    };
+    if pipeline.steps.is_empty() {
+        return Err("Pipeline must have at least one step".to_string());
+    }
+
+    let mut earlier_steps = HashSet::new();
+    let mut downstream_cost = 0.0;
+    for step in &pipeline.steps {
+        if step.name.trim().is_empty() {
+            return Err("Pipeline step names cannot be empty".to_string());
+        }
+        for binding in &step.bindings {
+            if let ArgumentSource::StepOutput(output) = &binding.source {
+                if !earlier_steps.contains(output.step_name.as_str()) {
+                    return Err(format!(
+                        "Step '{}' reads '{}' from step '{}', which does not run before it",
+                        step.name, binding.argument_name, output.step_name
+                    ));
+                }
+            }
+        }
+        if let PipelineTarget::Remote(remote) = &step.target {
+            if !remote.price.is_finite() || remote.price < 0.0 {
+                return Err(format!("Step '{}' has an invalid price: {}", step.name, remote.price));
+            }
+            if remote.price > 0.0 && EthAddress::from_str(&remote.wallet_address).is_err() {
+                return Err(format!(
+                    "Step '{}' has an invalid wallet address: {}",
+                    step.name, remote.wallet_address
+                ));
+            }
+            downstream_cost += remote.price;
+        }
+        if !earlier_steps.insert(step.name.as_str()) {
+            return Err(format!("Duplicate pipeline step name: {}", step.name));
+        }
+    }
+
+    if downstream_cost > provider.price {
+        return Err(format!(
+            "Pipeline pays {} USDC downstream per call, more than the provider price of {} USDC",
+            downstream_cost, provider.price
+        ));
+    }
+    Ok(())
+}
+
+/// Names of the incoming call arguments the pipeline reads
+pub fn pipeline_call_arguments(pipeline: &Pipeline) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for step in &pipeline.steps {
+        let step_names: Vec<String> = match (&step.target, step.bindings.is_empty()) {
+            // Unbound local steps read their own parameters straight from the call
+            (PipelineTarget::Local(endpoint), true) => endpoint
+                .parameters
+                .iter()
+                .map(|p| p.parameter_name.clone())
+                .collect(),
+            _ => step
+                .bindings
+                .iter()
+                .filter_map(|b| match &b.source {
+                    ArgumentSource::CallArgument(name) => Some(name.clone()),
+                    _ => None,
+                })
+                .collect(),
+        };
+        for name in step_names {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+/// Run every step in order and return the output of the last one.
+/// Steps are not retried: a failed step after a downstream payment must not pay twice.
+/// A dry run returns the output of the last step before the first remote one, or `{}`.
+pub async fn run_pipeline(
+    provider: &RegisteredProvider,
+    pipeline: &Pipeline,
+    call_args: &Vec<(String, String)>,
+    caller: &CallerContext,
+    token_cache: &mut HashMap<String, CachedAccessToken>,
+    remote_steps: RemoteSteps,
+    timeout_seconds: u64,
+    sandbox: SandboxMode,
+) -> Result<String, String> {
+    let provider_name = provider.provider_name.as_str();
+    let mut outputs: HashMap<String, String> = HashMap::new();
+    let mut last_output = String::new();
+
+    for (index, step) in pipeline.steps.iter().enumerate() {
+        debug!(
+            "Running pipeline step {} of {} for '{}': {}",
+            index + 1,
+            pipeline.steps.len(),
+            provider_name,
+            step.name
+        );
+        let args = resolve_arguments(step, call_args, &outputs)?;
+
+        let output = match &step.target {
+            PipelineTarget::Local(endpoint) => {
+                // Each local step caches its upstream token under its own key
+                call_provider(
+                    format!("{}/{}", provider_name, step.name),
+                    endpoint.clone(),
+                    &args,
+                    caller,
+                    token_cache,
+                    timeout_seconds,
+                    sandbox,
+                )
+                .await
+            }
+            PipelineTarget::Remote(_) if remote_steps == RemoteSteps::DryRun => {
+                ping_remote_steps(&pipeline.steps[index..]).await?;
+                info!(
+                    "pipeline_dry_run_stopped: provider={}, step={}, local_steps_run={}",
+                    provider_name, step.name, index
+                );
+                return Ok(if last_output.is_empty() { "{}".to_string() } else { last_output });
+            }
+            PipelineTarget::Remote(remote) => {
+                // A replay must not spend anything
+                if sandbox == SandboxMode::Replay {
+                    return Err(format!(
+                        "Pipeline step '{}' calls a remote provider, which sandbox replay can't do",
+                        step.name
+                    ));
+                }
+                call_remote_provider(provider, &step.name, remote, args, timeout_seconds).await
+            }
+        }
+        .map_err(|e| format!("Pipeline step '{}' failed: {}", step.name, e))?;
+
+        outputs.insert(step.name.clone(), output.clone());
+        last_output = output;
+    }
+
+    Ok(last_output)
+}
+
+/// Whether running the provider calls other Hypergrid providers
+pub fn has_remote_steps(provider: &RegisteredProvider) -> bool {
+    provider.pipeline.as_ref().is_some_and(|pipeline| {
+        pipeline
+            .steps
+            .iter()
+            .any(|step| matches!(step.target, PipelineTarget::Remote(_)))
+    })
+}
+
+/// Build the argument list for `step` from its bindings
+fn resolve_arguments(
+    step: &PipelineStep,
+    call_args: &Vec<(String, String)>,
+    outputs: &HashMap<String, String>,
+) -> Result<Vec<(String, String)>, String> {
+    if step.bindings.is_empty() {
+        return Ok(call_args.clone());
+    }
+
+    step.bindings
+        .iter()
+        .map(|binding| {
+            let value = match &binding.source {
+                ArgumentSource::CallArgument(name) => call_args
+                    .iter()
+                    .find(|(k, _)| k == name)
+                    .map(|(_, v)| v.clone())
+                    .ok_or_else(|| format!("Missing call argument '{}'", name))?,
+                ArgumentSource::StepOutput(output_ref) => {
+                    let output = outputs
+                        .get(&output_ref.step_name)
+                        .ok_or_else(|| format!("No output from step '{}'", output_ref.step_name))?;
+                    let json: Value = serde_json::from_str(output).map_err(|e| {
+                        format!("Output of step '{}' is not JSON: {}", output_ref.step_name, e)
+                    })?;
+                    match json.pointer(&output_ref.json_pointer) {
+                        Some(Value::String(s)) => s.clone(),
+                        Some(other) => other.to_string(),
+                        None => {
+                            return Err(format!(
+                                "Output of step '{}' has nothing at '{}'",
+                                output_ref.step_name, output_ref.json_pointer
+                            ))
+                        }
+                    }
+                }
+                ArgumentSource::Literal(value) => value.clone(),
+            };
+            Ok((binding.argument_name.clone(), value))
+        })
+        .collect()
+}
+
+/// Check the remote steps are reachable and charge what the pipeline expects, without paying
+async fn ping_remote_steps(steps: &[PipelineStep]) -> Result<(), String> {
+    for step in steps {
+        let PipelineTarget::Remote(remote) = &step.target else {
+            continue;
+        };
+        let target = Address::new(&remote.provider_node, ("provider", "hypergrid", PUBLISHER));
+        let body = serde_json::json!({
+            "HealthPing": HealthCheckCall {
+                provider_name: remote.provider_name.clone(),
+                protocol_versions: SUPPORTED_PROTOCOL_VERSIONS.to_vec(),
+            }
+        });
+        let request = Request::to(target)
+            .expects_response(REMOTE_PING_TIMEOUT_SECS)
+            .body(serde_json::to_vec(&body).map_err(|e| e.to_string())?);
+
+        let status = match send::<Result<HealthStatus, String>>(request).await {
+            Ok(Ok(status)) => status,
+            Ok(Err(e)) => return Err(format!("Pipeline step '{}': {}", step.name, e)),
+            Err(e) => {
+                return Err(format!(
+                    "Pipeline step '{}': no health ping answer from '{}' on {}: {:?}",
+                    step.name, remote.provider_name, remote.provider_node, e
+                ))
+            }
+        };
+        if !status.available {
+            return Err(format!(
+                "Pipeline step '{}': '{}' is unavailable: {}",
+                step.name,
+                remote.provider_name,
+                status.unavailable_reason.as_deref().unwrap_or("no reason given")
+            ));
+        }
+        // Compared in USDC units, like the operator does before paying
+        let units = |price: f64| (price * 1_000_000.0).round() as u64;
+        if units(status.price) != units(remote.price) {
+            return Err(format!(
+                "Pipeline step '{}': '{}' charges {} USDC, the step expects {} USDC",
+                step.name, remote.provider_name, status.price, remote.price
+            ));
+        }
+        if remote.price > 0.0 && !status.wallet.eq_ignore_ascii_case(&remote.wallet_address) {
+            warn!(
+                "pipeline_remote_wallet_mismatch: step={}, downstream_provider={}, expected={}, live={}",
+                step.name, remote.provider_name, remote.wallet_address, status.wallet
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Call another Hypergrid provider through this node's operator, which health-checks it and
+/// pays it from the provider's registered wallet. The operator can only sign for its own TBA,
+/// so it refuses unless that is the wallet the provider registered. Job tickets and chunked
+/// answers are followed up until the step has the downstream provider's whole result.
+async fn call_remote_provider(
+    provider: &RegisteredProvider,
+    step_name: &str,
+    remote: &RemoteProviderStep,
+    arguments: Vec<(String, String)>,
+    timeout_seconds: u64,
+) -> Result<String, String> {
+    let provider_name = provider.provider_name.as_str();
+    let operator = Address::new(&our().node, ("operator", "hypergrid", PUBLISHER));
+    let body = serde_json::json!({
+        "payer_wallet": provider.registered_provider_wallet,
+        "request": {
+            "CallProvider": {
+                "provider_id": remote.provider_node,
+                "provider_name": remote.provider_name,
+                "arguments": arguments,
+            }
+        }
+    });
+    let request = Request::to(operator)
+        .expects_response(REMOTE_STEP_TIMEOUT_SECS)
+        .body(serde_json::to_vec(&body).map_err(|e| e.to_string())?);
+
+    let status = match send::<OperatorResponse>(request).await {
+        Ok(response) => response.status,
+        Err(e) => {
+            return Err(format!(
+                "No response from the operator calling '{}': {:?}",
+                remote.provider_name, e
+            ))
+        }
+    };
+    let answer = get_blob().unwrap_or_default().bytes;
+    if status != 200 {
+        warn!(
+            "pipeline_remote_step_failed: provider={}, step={}, downstream_provider={}, status={}",
+            provider_name, step_name, remote.provider_name, status
+        );
+        return Err(format!(
+            "Operator answered {} calling '{}': {}",
+            status,
+            remote.provider_name,
+            String::from_utf8_lossy(&answer)
+        ));
+    }
+    info!(
+        "pipeline_remote_step: provider={}, step={}, downstream_provider={}, downstream_node={}, price_usdc={}",
+        provider_name, step_name, remote.provider_name, remote.provider_node, remote.price
+    );
+    let output = serde_json::from_slice::<Result<String, String>>(&answer)
+        .map_err(|e| format!("Unreadable answer from '{}': {}", remote.provider_name, e))??;
+
+    let output = match serde_json::from_str::<JobTicket>(&output) {
+        Ok(ticket) => {
+            debug!(
+                "pipeline_remote_job: provider={}, step={}, downstream_provider={}, job_id={}, status={:?}",
+                provider_name, step_name, ticket.provider_name, ticket.job_id, ticket.status
+            );
+            await_remote_job(remote, &ticket.job_id, timeout_seconds).await?
+        }
+        Err(_) => output,
+    };
+    match serde_json::from_str::<ChunkEnvelope>(&output) {
+        Ok(first) => collect_remote_chunks(remote, first).await,
+        Err(_) => Ok(output),
+    }
+}
+
+/// Poll a downstream async job until it finishes or `timeout_seconds` have passed. The job
+/// belongs to this node, which the operator paid from, so it is polled directly.
+async fn await_remote_job(
+    remote: &RemoteProviderStep,
+    job_id: &str,
+    timeout_seconds: u64,
+) -> Result<String, String> {
+    let target = Address::new(&remote.provider_node, ("provider", "hypergrid", PUBLISHER));
+    let deadline = now_unix_secs() + timeout_seconds;
+    loop {
+        let body = serde_json::json!({
+            "GetJobResult": JobQuery {
+                job_id: job_id.to_string(),
+                protocol_version: Some(PROTOCOL_VERSION),
+            }
+        });
+        let request = Request::to(target.clone())
+            .expects_response(REMOTE_PING_TIMEOUT_SECS)
+            .body(serde_json::to_vec(&body).map_err(|e| e.to_string())?);
+        let job = match send::<Result<ProviderJob, String>>(request).await {
+            Ok(Ok(job)) => job,
+            Ok(Err(e)) => return Err(format!("Job {} of '{}': {}", job_id, remote.provider_name, e)),
+            Err(e) => {
+                return Err(format!(
+                    "No answer polling job {} of '{}': {:?}",
+                    job_id, remote.provider_name, e
+                ))
+            }
+        };
+        match job.status {
+            JobStatus::Succeeded => {
+                return job
+                    .result
+                    .ok_or_else(|| format!("Job {} of '{}' finished without a result", job_id, remote.provider_name))
+            }
+            JobStatus::Failed => {
+                return Err(format!(
+                    "Job {} of '{}' failed: {}",
+                    job_id,
+                    remote.provider_name,
+                    job.error.unwrap_or_default()
+                ))
+            }
+            JobStatus::Pending | JobStatus::Running => {}
+        }
+        if now_unix_secs() >= deadline {
+            return Err(format!(
+                "Job {} of '{}' did not finish within {}s",
+                job_id, remote.provider_name, timeout_seconds
+            ));
+        }
+        let _ = sleep(REMOTE_JOB_POLL_INTERVAL_MS).await;
+    }
+}
+
+/// Redeem the remaining chunks of a downstream response and join them. Chunks are already
+/// paid for and issued to this node.
+async fn collect_remote_chunks(remote: &RemoteProviderStep, first: ChunkEnvelope) -> Result<String, String> {
+    let target = Address::new(&remote.provider_node, ("provider", "hypergrid", PUBLISHER));
+    let mut response = first.chunk;
+    for chunk_index in 1..first.total_chunks {
+        let body = serde_json::json!({
+            "GetResponseChunk": ChunkQuery {
+                continuation_token: first.continuation_token.clone(),
+                chunk_index,
+                protocol_version: Some(PROTOCOL_VERSION),
+            }
+        });
+        let request = Request::to(target.clone())
+            .expects_response(REMOTE_PING_TIMEOUT_SECS)
+            .body(serde_json::to_vec(&body).map_err(|e| e.to_string())?);
+        let envelope = match send::<Result<String, String>>(request).await {
+            Ok(Ok(envelope)) => envelope,
+            Ok(Err(e)) => return Err(format!("Chunk {} of '{}': {}", chunk_index, remote.provider_name, e)),
+            Err(e) => {
+                return Err(format!(
+                    "No answer fetching chunk {} of '{}': {:?}",
+                    chunk_index, remote.provider_name, e
+                ))
+            }
+        };
+        let chunk: ChunkEnvelope = serde_json::from_str(&envelope)
+            .map_err(|e| format!("Unreadable chunk {} of '{}': {}", chunk_index, remote.provider_name, e))?;
+        response.push_str(&chunk.chunk);
+    }
+    Ok(response)
+}
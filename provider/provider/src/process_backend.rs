@@ -0,0 +1,93 @@
+use crate::util::{parse_parameter_value, update_json_value_by_pointer};
+use crate::{EndpointDefinition, ProcessBackend};
+use hyperware_process_lib::{
+    hyperapp::send,
+    logging::{debug, error, warn},
+    our, Address, ProcessId, Request,
+};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Forward a call to a process on this node. The request body is the backend's JSON template
+/// with `body` parameters substituted by JSON pointer, exactly like HTTP request bodies.
+pub async fn call_process_backend(
+    provider_id_for_log: &str,
+    endpoint_def: &EndpointDefinition,
+    backend: &ProcessBackend,
+    args_map: &HashMap<String, String>,
+) -> Result<String, String> {
+    let process_id = ProcessId::from_str(&backend.process_id)
+        .map_err(|e| format!("Invalid process id '{}': {}", backend.process_id, e))?;
+    let target = Address::new(our().node(), process_id);
+
+    let mut body: Value = serde_json::from_str(&backend.body_template)
+        .map_err(|e| format!("Process body template is not valid JSON: {}", e))?;
+
+    for param_def in &endpoint_def.parameters {
+        let value = args_map
+            .get(&param_def.parameter_name)
+            .ok_or_else(|| format!("Missing argument for parameter: {}", param_def.parameter_name))?;
+        if param_def.location != "body" {
+            warn!(
+                "Ignoring {} parameter '{}' for process backend",
+                param_def.location, param_def.parameter_name
+            );
+            continue;
+        }
+        // Same pointer convention as HTTP bodies: "/body" replaces the whole template
+        let pointer = param_def
+            .json_pointer
+            .strip_prefix("/body")
+            .unwrap_or(&param_def.json_pointer);
+        if pointer.is_empty() {
+            body = parse_parameter_value(value);
+        } else {
+            update_json_value_by_pointer(&mut body, pointer, value)?;
+        }
+    }
+
+    debug!(
+        "process_request_started: provider={}, target={}, timeout_s={}",
+        provider_id_for_log, target, backend.timeout_seconds
+    );
+    let start_time = std::time::Instant::now();
+    let request = Request::to(target.clone())
+        .expects_response(backend.timeout_seconds)
+        .body(serde_json::to_vec(&body).map_err(|e| format!("Failed to serialize body: {}", e))?);
+
+    let response = send::<Value>(request).await.map_err(|e| {
+        error!(
+            "process_request_failed: provider={}, target={}, duration_ms={}, error_type=send_error",
+            provider_id_for_log,
+            target,
+            start_time.elapsed().as_millis()
+        );
+        format!("Failed to call process {}: {:?}", target, e)
+    })?;
+
+    debug!(
+        "process_response_received: provider={}, target={}, duration_ms={}",
+        provider_id_for_log,
+        target,
+        start_time.elapsed().as_millis()
+    );
+
+    // Hyperprocess handlers answer with a serialized Result; unwrap it
+    match response {
+        Value::Object(map) if map.len() == 1 && map.contains_key("Err") => {
+            Err(format!("Process returned an error: {}", value_to_string(&map["Err"])))
+        }
+        Value::Object(map) if map.len() == 1 && map.contains_key("Ok") => Ok(value_to_string(&map["Ok"])),
+        other => Ok(other.to_string()),
+    }
+}
+
+/// Strings are returned as-is, anything else as JSON text
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
@@ -0,0 +1,38 @@
+/// Wire-protocol versions this provider speaks, oldest first. Version 1 is the format that
+/// predates versioning, so messages without a version are read as version 1.
+pub const SUPPORTED_PROTOCOL_VERSIONS: [u32; 1] = [1];
+
+/// Newest version this provider speaks, used for messages it sends itself
+pub const PROTOCOL_VERSION: u32 = SUPPORTED_PROTOCOL_VERSIONS[SUPPORTED_PROTOCOL_VERSIONS.len() - 1];
+
+/// Highest version both sides speak; operators that offer none are on version 1
+pub fn negotiate_protocol_version(offered: &[u32]) -> Result<u32, String> {
+    if offered.is_empty() {
+        return check_protocol_version(None);
+    }
+    offered
+        .iter()
+        .copied()
+        .filter(|version| SUPPORTED_PROTOCOL_VERSIONS.contains(version))
+        .max()
+        .ok_or_else(|| {
+            format!(
+                "No common Hypergrid protocol version: caller speaks {:?}, this provider speaks {:?}",
+                offered, SUPPORTED_PROTOCOL_VERSIONS
+            )
+        })
+}
+
+/// Refuse messages in a version this provider doesn't speak
+pub fn check_protocol_version(version: Option<u32>) -> Result<u32, String> {
+    let version = version.unwrap_or(1);
+    if SUPPORTED_PROTOCOL_VERSIONS.contains(&version) {
+        return Ok(version);
+    }
+    Err(format!(
+        "Unsupported Hypergrid protocol version {}: this provider speaks {:?}, update the {}",
+        version,
+        SUPPORTED_PROTOCOL_VERSIONS,
+        if version > PROTOCOL_VERSION { "provider" } else { "operator" }
+    ))
+}
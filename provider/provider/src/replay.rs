@@ -0,0 +1,240 @@
+use crate::SettleResponse;
+use hyperware_process_lib::{
+    logging::debug,
+    vfs::{create_file, open_dir, open_file, remove_file},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// How long a settled call's upstream response is kept for retries. The payment itself is
+/// remembered until its authorization expires.
+pub const RETRY_RESPONSE_TTL_SECS: u64 = 3600;
+
+/// An x402 payment that was settled for a call
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ServedPayment {
+    pub request_key: String, // Hex SHA-256 over the provider name and arguments
+    pub signature: String,
+    pub payer: String,
+    pub network: String,
+    pub transaction: Option<String>,
+    pub settled_at: u64,   // Unix seconds
+    pub valid_before: u64, // Unix seconds, from the authorization
+}
+
+/// Served payments keyed by `x402::nonce_key`
+pub type ServedPayments = HashMap<String, ServedPayment>;
+
+/// Upstream responses of served payments, keyed like `ServedPayments`. Each is also stored on
+/// the provider drive for its retry window, outside the persisted state, so retries still get
+/// it after a restart.
+pub type RetryResponses = HashMap<String, String>;
+
+fn retries_dir(drive_path: &str) -> String {
+    format!("{}/x402-retries", drive_path)
+}
+
+fn retry_file(drive_path: &str, nonce_key: &str) -> String {
+    format!("{}/{}.json", retries_dir(drive_path), urlencoding::encode(nonce_key))
+}
+
+/// Nonce key of a file written by `save_retry_response`, from its path
+fn retry_file_key(path: &str) -> Option<String> {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    file_name
+        .strip_suffix(".json")
+        .and_then(|key| urlencoding::decode(key).ok())
+        .map(|key| key.into_owned())
+}
+
+/// Key of a paid request, independent of argument order
+pub fn paid_request_key(provider_name: &str, arguments: &[(String, String)]) -> String {
+    let mut sorted: Vec<&(String, String)> = arguments.iter().collect();
+    sorted.sort();
+    let mut hasher = Sha256::new();
+    hasher.update(provider_name.as_bytes());
+    for (name, value) in sorted {
+        hasher.update([0]);
+        hasher.update(name.as_bytes());
+        hasher.update([0]);
+        hasher.update(value.as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Look up a payment before verifying it. `Ok(None)` means it was never served,
+/// `Ok(Some(..))` is a retry of a settled call with its cached response and settlement, and
+/// `Err` is the reason code to refuse a replay with.
+pub fn check_replay(
+    served: &ServedPayments,
+    responses: &RetryResponses,
+    nonce_key: &str,
+    request_key: &str,
+    signature: &str,
+    now: u64,
+) -> Result<Option<(String, SettleResponse)>, String> {
+    let Some(payment) = served.get(nonce_key) else {
+        return Ok(None);
+    };
+    let nonce_used = "invalid_exact_evm_payload_authorization_nonce_used".to_string();
+    // Only the exact same header retrying the exact same call gets the response back
+    if payment.request_key != request_key || !payment.signature.eq_ignore_ascii_case(signature) {
+        return Err(nonce_used);
+    }
+    match responses.get(nonce_key) {
+        Some(response) if now < payment.settled_at + RETRY_RESPONSE_TTL_SECS => Ok(Some((
+            response.clone(),
+            SettleResponse {
+                success: true,
+                payer: payment.payer.clone(),
+                transaction: payment.transaction.clone(),
+                network: payment.network.clone(),
+                error_reason: None,
+            },
+        ))),
+        _ => Err(nonce_used),
+    }
+}
+
+/// Remember a settled payment and its response, and forget payments whose authorization
+/// expired and responses past their retry window. Returns the keys of the forgotten responses.
+pub fn record_served(
+    served: &mut ServedPayments,
+    responses: &mut RetryResponses,
+    nonce_key: String,
+    payment: ServedPayment,
+    response: String,
+    now: u64,
+) -> Vec<String> {
+    served.retain(|_, p| p.valid_before > now);
+    let expired: Vec<String> = responses
+        .keys()
+        .filter(|key| !in_retry_window(served, key, now))
+        .cloned()
+        .collect();
+    for key in &expired {
+        responses.remove(key);
+    }
+    responses.insert(nonce_key.clone(), response);
+    served.insert(nonce_key, payment);
+    expired
+}
+
+fn in_retry_window(served: &ServedPayments, nonce_key: &str, now: u64) -> bool {
+    served
+        .get(nonce_key)
+        .is_some_and(|p| now < p.settled_at + RETRY_RESPONSE_TTL_SECS)
+}
+
+/// Store a served payment's response on the provider drive
+pub fn save_retry_response(drive_path: &str, nonce_key: &str, response: &str) -> Result<(), String> {
+    open_dir(&retries_dir(drive_path), true, None).map_err(|e| e.to_string())?;
+    let file = create_file(&retry_file(drive_path, nonce_key), None).map_err(|e| e.to_string())?;
+    file.write(response.as_bytes()).map_err(|e| e.to_string())
+}
+
+pub fn remove_retry_response(drive_path: &str, nonce_key: &str) {
+    if let Err(e) = remove_file(&retry_file(drive_path, nonce_key), None) {
+        debug!("No retry response to remove for '{}': {:?}", nonce_key, e);
+    }
+}
+
+/// Stored responses of payments still in their retry window. Files of the others are removed.
+pub fn load_retry_responses(drive_path: &str, served: &ServedPayments, now: u64) -> Result<RetryResponses, String> {
+    let dir = open_dir(&retries_dir(drive_path), true, None).map_err(|e| e.to_string())?;
+    let mut responses = RetryResponses::new();
+    for entry in dir.read().map_err(|e| e.to_string())? {
+        let Some(nonce_key) = retry_file_key(&entry.path) else {
+            continue;
+        };
+        if !in_retry_window(served, &nonce_key, now) {
+            remove_retry_response(drive_path, &nonce_key);
+            continue;
+        }
+        let bytes = open_file(&retry_file(drive_path, &nonce_key), false, None)
+            .and_then(|file| file.read())
+            .map_err(|e| e.to_string())?;
+        responses.insert(nonce_key, String::from_utf8_lossy(&bytes).into_owned());
+    }
+    Ok(responses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payment(request_key: &str, settled_at: u64, valid_before: u64) -> ServedPayment {
+        ServedPayment {
+            request_key: request_key.to_string(),
+            signature: "0xABCD".to_string(),
+            payer: "0x1111111111111111111111111111111111111111".to_string(),
+            network: "base".to_string(),
+            transaction: Some("0xfeed".to_string()),
+            settled_at,
+            valid_before,
+        }
+    }
+
+    #[test]
+    fn request_keys_ignore_argument_order() {
+        let args = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect()
+        };
+        let key = paid_request_key("weather", &args(&[("city", "Paris"), ("days", "1")]));
+        assert_eq!(key, paid_request_key("weather", &args(&[("days", "1"), ("city", "Paris")])));
+        assert_ne!(key, paid_request_key("weather", &args(&[("city", "Paris"), ("days", "2")])));
+        assert_ne!(key, paid_request_key("forecast", &args(&[("city", "Paris"), ("days", "1")])));
+        // Separators keep shifted boundaries apart
+        assert_ne!(
+            paid_request_key("weather", &args(&[("ab", "c")])),
+            paid_request_key("weather", &args(&[("a", "bc")]))
+        );
+    }
+
+    #[test]
+    fn only_the_same_call_retries() {
+        let mut served = ServedPayments::new();
+        let mut responses = RetryResponses::new();
+        assert!(matches!(check_replay(&served, &responses, "n1", "k1", "0xabcd", 100), Ok(None)));
+
+        let response = "{\"ok\":1}".to_string();
+        record_served(&mut served, &mut responses, "n1".to_string(), payment("k1", 100, 10_000), response, 100);
+        let (response, settlement) = check_replay(&served, &responses, "n1", "k1", "0xabcd", 200).unwrap().unwrap();
+        assert_eq!(response, "{\"ok\":1}");
+        assert_eq!(settlement.transaction.as_deref(), Some("0xfeed"));
+
+        assert!(check_replay(&served, &responses, "n1", "k2", "0xabcd", 200).is_err());
+        assert!(check_replay(&served, &responses, "n1", "k1", "0xother", 200).is_err());
+        // Past the retry window the nonce stays spent
+        assert!(check_replay(&served, &responses, "n1", "k1", "0xabcd", 100 + RETRY_RESPONSE_TTL_SECS).is_err());
+    }
+
+    #[test]
+    fn recording_prunes_expired_payments_and_responses() {
+        let mut served = ServedPayments::new();
+        let mut responses = RetryResponses::new();
+        record_served(&mut served, &mut responses, "old".to_string(), payment("k1", 100, 500), "a".to_string(), 100);
+        record_served(&mut served, &mut responses, "kept".to_string(), payment("k2", 100, 100_000), "b".to_string(), 100);
+
+        let now = 100 + RETRY_RESPONSE_TTL_SECS;
+        let mut expired = record_served(&mut served, &mut responses, "new".to_string(), payment("k3", now, 100_000), "c".to_string(), now);
+        expired.sort();
+        assert_eq!(expired, vec!["kept".to_string(), "old".to_string()]);
+        assert!(!served.contains_key("old"));
+        assert!(served.contains_key("kept"));
+        assert!(!responses.contains_key("old"));
+        assert!(!responses.contains_key("kept"));
+        assert_eq!(responses.get("new").map(String::as_str), Some("c"));
+    }
+
+    #[test]
+    fn retry_files_round_trip_nonce_keys() {
+        let nonce_key = "0xusdc:0xpayer:0x01/../state";
+        let path = retry_file("/provider:hypergrid:os/data", nonce_key);
+        let file_name = path.strip_prefix("/provider:hypergrid:os/data/x402-retries/").unwrap();
+        assert!(!file_name.contains('/'));
+        assert_eq!(retry_file_key(&path).as_deref(), Some(nonce_key));
+        assert_eq!(retry_file_key("/provider:hypergrid:os/data/x402-retries/notes.txt"), None);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::util::{now_unix_secs, send_async_http_request};
+use crate::{SandboxFixture, SandboxMode};
+use hyperware_process_lib::{
+    http::{
+        client::HttpClientError, HeaderName, HeaderValue, Method as HyperwareHttpMethod,
+        Response as HyperwareHttpResponse, StatusCode,
+    },
+    logging::{debug, warn},
+    our,
+    vfs::{create_file, open_dir, open_file, remove_file},
+};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::str::FromStr;
+use url::Url;
+
+/// Path of the drive created in `init_vfs_drive`
+fn providers_drive_path() -> String {
+    format!("/{}/providers", our().package_id())
+}
+
+fn sandbox_dir() -> String {
+    format!("{}/sandbox", providers_drive_path())
+}
+
+/// Fixtures of pipeline steps (`<provider>/<step>`) live in their provider's file
+fn fixture_file(provider_id: &str) -> String {
+    let provider_name = provider_id.split('/').next().unwrap_or(provider_id);
+    format!("{}/{}.json", sandbox_dir(), urlencoding::encode(provider_name))
+}
+
+/// Fixtures match on method, full URL and body. Headers are left out: the attestation,
+/// request signatures and bearer tokens change on every call.
+pub fn request_key(method: &str, url: &Url, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.to_uppercase().as_bytes());
+    hasher.update(b"\n");
+    hasher.update(url.as_str().as_bytes());
+    hasher.update(b"\n");
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+pub fn load_fixtures(provider_id: &str) -> Result<Vec<SandboxFixture>, String> {
+    let file = match open_file(&fixture_file(provider_id), false, None) {
+        Ok(file) => file,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let bytes = file.read().map_err(|e| e.to_string())?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("Failed to parse sandbox fixtures: {}", e))
+}
+
+pub fn save_fixtures(provider_id: &str, fixtures: &[SandboxFixture]) -> Result<(), String> {
+    open_dir(&sandbox_dir(), true, None).map_err(|e| e.to_string())?;
+    let json_data = serde_json::to_string_pretty(fixtures).map_err(|e| e.to_string())?;
+    let file = create_file(&fixture_file(provider_id), None).map_err(|e| e.to_string())?;
+    file.write(json_data.as_bytes()).map_err(|e| e.to_string())
+}
+
+pub fn delete_fixtures(provider_name: &str) -> Result<(), String> {
+    if let Err(e) = remove_file(&fixture_file(provider_name), None) {
+        debug!("No sandbox fixtures to remove for '{}': {:?}", provider_name, e);
+    }
+    Ok(())
+}
+
+/// Send an upstream request, or record or replay it, depending on the sandbox mode
+pub async fn send_upstream(
+    mode: SandboxMode,
+    provider_id: &str,
+    method: HyperwareHttpMethod,
+    url: Url,
+    headers: HashMap<String, String>,
+    timeout: u64,
+    body: Vec<u8>,
+) -> Result<HyperwareHttpResponse<Vec<u8>>, HttpClientError> {
+    let key = request_key(method.as_str(), &url, &body);
+    match mode {
+        SandboxMode::Off => send_async_http_request(method, url, Some(headers), timeout, body).await,
+        SandboxMode::Replay => replay(provider_id, &key, method.as_str(), &url),
+        SandboxMode::Record => {
+            let method_str = method.to_string();
+            let response = send_async_http_request(method, url.clone(), Some(headers), timeout, body).await?;
+            if let Err(e) = record(provider_id, key, &method_str, &url, &response) {
+                warn!("sandbox_record_failed: provider={}, error={}", provider_id, e);
+            }
+            Ok(response)
+        }
+    }
+}
+
+fn replay(
+    provider_id: &str,
+    key: &str,
+    method: &str,
+    url: &Url,
+) -> Result<HyperwareHttpResponse<Vec<u8>>, HttpClientError> {
+    let fixtures = load_fixtures(provider_id).map_err(HttpClientError::ExecuteRequestFailed)?;
+    let fixture = fixtures.iter().find(|f| f.request_key == key).ok_or_else(|| {
+        HttpClientError::ExecuteRequestFailed(format!(
+            "sandbox has no recorded response for {} {}{}",
+            method,
+            url.origin().ascii_serialization(),
+            url.path()
+        ))
+    })?;
+    debug!("sandbox_replay: provider={}, key={}, status={}", provider_id, key, fixture.status);
+
+    let mut response = HyperwareHttpResponse::builder()
+        .status(StatusCode::from_u16(fixture.status).unwrap_or_default());
+    if let Some(headers_map) = response.headers_mut() {
+        for (key, value) in &fixture.headers {
+            let (Ok(key), Ok(value)) = (HeaderName::from_str(key), HeaderValue::from_str(value)) else {
+                continue;
+            };
+            headers_map.insert(key, value);
+        }
+    }
+    response
+        .body(fixture.body.clone().into_bytes())
+        .map_err(|e| HttpClientError::ExecuteRequestFailed(e.to_string()))
+}
+
+fn record(
+    provider_id: &str,
+    key: String,
+    method: &str,
+    url: &Url,
+    response: &HyperwareHttpResponse<Vec<u8>>,
+) -> Result<(), String> {
+    let fixture = SandboxFixture {
+        request_key: key,
+        method: method.to_string(),
+        // The query string is dropped, it may carry API keys
+        url: format!("{}{}", url.origin().ascii_serialization(), url.path()),
+        status: response.status().as_u16(),
+        headers: response
+            .headers()
+            .iter()
+            .filter_map(|(k, v)| Some((k.to_string(), v.to_str().ok()?.to_string())))
+            .collect(),
+        body: String::from_utf8_lossy(response.body()).into_owned(),
+        recorded_at: now_unix_secs(),
+    };
+
+    let mut fixtures = load_fixtures(provider_id)?;
+    fixtures.retain(|f| f.request_key != fixture.request_key);
+    debug!(
+        "sandbox_recorded: provider={}, key={}, status={}",
+        provider_id, fixture.request_key, fixture.status
+    );
+    fixtures.push(fixture);
+    save_fixtures(provider_id, &fixtures)
+}
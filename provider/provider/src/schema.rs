@@ -0,0 +1,140 @@
+use serde_json::{Map, Value};
+
+/// Infer a JSON Schema describing `sample`.
+///
+/// Objects list every key seen as required; arrays take their item schema from the first element.
+pub fn infer_json_schema(sample: &Value) -> Value {
+    match sample {
+        Value::Null => serde_json::json!({ "type": "null" }),
+        Value::Bool(_) => serde_json::json!({ "type": "boolean" }),
+        Value::Number(n) => {
+            if n.is_i64() || n.is_u64() {
+                serde_json::json!({ "type": "integer" })
+            } else {
+                serde_json::json!({ "type": "number" })
+            }
+        }
+        Value::String(_) => serde_json::json!({ "type": "string" }),
+        Value::Array(items) => match items.first() {
+            Some(first) => serde_json::json!({ "type": "array", "items": infer_json_schema(first) }),
+            None => serde_json::json!({ "type": "array" }),
+        },
+        Value::Object(map) => {
+            let properties: Map<String, Value> = map
+                .iter()
+                .map(|(k, v)| (k.clone(), infer_json_schema(v)))
+                .collect();
+            let required: Vec<Value> = map.keys().cloned().map(Value::String).collect();
+            serde_json::json!({
+                "type": "object",
+                "properties": properties,
+                "required": required
+            })
+        }
+    }
+}
+
+/// Infer a schema from a raw response body. Non-JSON bodies are described as plain strings.
+pub fn infer_schema_from_response(response: &str) -> Value {
+    match serde_json::from_str::<Value>(response) {
+        Ok(json) => infer_json_schema(&json),
+        Err(_) => serde_json::json!({ "type": "string" }),
+    }
+}
+
+/// Validate a raw response body against a declared schema.
+///
+/// Returns the list of violations; an empty list means the response matches.
+pub fn validate_response_against_schema(response: &str, schema: &Value) -> Vec<String> {
+    let mut violations = Vec::new();
+    match serde_json::from_str::<Value>(response) {
+        Ok(json) => validate_value(&json, schema, "", &mut violations),
+        Err(_) => {
+            // Plain-text responses are only valid against a string schema
+            if !type_matches(&Value::String(response.to_string()), schema) {
+                violations.push("response is not valid JSON".to_string());
+            }
+        }
+    }
+    violations
+}
+
+/// Supports the subset of JSON Schema produced by `infer_json_schema`, plus `enum`
+fn validate_value(value: &Value, schema: &Value, path: &str, violations: &mut Vec<String>) {
+    let display_path = if path.is_empty() { "/" } else { path };
+
+    if !type_matches(value, schema) {
+        violations.push(format!(
+            "{}: expected type {}, got {}",
+            display_path,
+            schema.get("type").map(|t| t.to_string()).unwrap_or_default(),
+            json_type_name(value)
+        ));
+        return;
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            violations.push(format!("{}: value {} is not one of the allowed values", display_path, value));
+        }
+    }
+
+    match value {
+        Value::Object(map) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for key in required.iter().filter_map(Value::as_str) {
+                    if !map.contains_key(key) {
+                        violations.push(format!("{}/{}: required field is missing", path, key));
+                    }
+                }
+            }
+            if let Some(Value::Object(properties)) = schema.get("properties") {
+                for (key, property_schema) in properties {
+                    if let Some(child) = map.get(key) {
+                        validate_value(child, property_schema, &format!("{}/{}", path, key), violations);
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    validate_value(item, item_schema, &format!("{}/{}", path, index), violations);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn type_matches(value: &Value, schema: &Value) -> bool {
+    match schema.get("type") {
+        None => true,
+        Some(Value::String(expected)) => type_name_matches(value, expected),
+        Some(Value::Array(options)) => options
+            .iter()
+            .filter_map(Value::as_str)
+            .any(|expected| type_name_matches(value, expected)),
+        Some(_) => true,
+    }
+}
+
+fn type_name_matches(value: &Value, expected: &str) -> bool {
+    match expected {
+        "integer" => value.as_i64().is_some() || value.as_u64().is_some(),
+        // Integers are also valid numbers
+        "number" => value.is_number(),
+        other => json_type_name(value) == other,
+    }
+}
+
+fn json_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
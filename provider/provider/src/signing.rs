@@ -0,0 +1,167 @@
+use crate::{AwsSigV4Signer, HmacSha256Signer, RequestSigner};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use url::Url;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Run every configured signer over the final request, adding the computed headers.
+///
+/// Must be called after parameter substitution so signatures cover the exact bytes sent.
+pub fn apply_request_signers(
+    signers: &[RequestSigner],
+    method: &str,
+    url: &Url,
+    headers: &mut HashMap<String, String>,
+    body: &[u8],
+) -> Result<(), String> {
+    let now = Utc::now();
+    for signer in signers {
+        match signer {
+            RequestSigner::HmacSha256(config) => sign_hmac_sha256(config, method, url, headers, body, now)?,
+            RequestSigner::AwsSigV4(config) => sign_aws_sigv4(config, method, url, headers, body, now)?,
+        }
+    }
+    Ok(())
+}
+
+/// Signs `METHOD\nPATH?QUERY\nTIMESTAMP\nBODY` with the shared secret and sends the
+/// hex-encoded signature alongside the Unix timestamp it covers.
+fn sign_hmac_sha256(
+    config: &HmacSha256Signer,
+    method: &str,
+    url: &Url,
+    headers: &mut HashMap<String, String>,
+    body: &[u8],
+    now: DateTime<Utc>,
+) -> Result<(), String> {
+    let timestamp = now.timestamp().to_string();
+    let path_and_query = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+
+    let mut string_to_sign = format!("{}\n{}\n{}\n", method, path_and_query, timestamp).into_bytes();
+    string_to_sign.extend_from_slice(body);
+
+    let signature = hex::encode(hmac_sha256(config.secret.as_bytes(), &string_to_sign)?);
+
+    headers.insert(config.timestamp_header.clone(), timestamp);
+    headers.insert(
+        config.signature_header.clone(),
+        format!("{}{}", config.signature_prefix.as_deref().unwrap_or(""), signature),
+    );
+    Ok(())
+}
+
+/// AWS Signature Version 4, header-based
+/// https://docs.aws.amazon.com/IAM/latest/UserGuide/create-signed-request.html
+fn sign_aws_sigv4(
+    config: &AwsSigV4Signer,
+    method: &str,
+    url: &Url,
+    headers: &mut HashMap<String, String>,
+    body: &[u8],
+    now: DateTime<Utc>,
+) -> Result<(), String> {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date_stamp = now.format("%Y%m%d").to_string();
+    let payload_hash = hex::encode(Sha256::digest(body));
+
+    let host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => return Err("Cannot sign request without a host".to_string()),
+    };
+
+    headers.insert("X-Amz-Date".to_string(), amz_date.clone());
+    headers.insert("X-Amz-Content-Sha256".to_string(), payload_hash.clone());
+    if let Some(token) = &config.session_token {
+        headers.insert("X-Amz-Security-Token".to_string(), token.clone());
+    }
+
+    // Sign host, the x-amz-* headers and the content type; other headers may be rewritten in transit
+    let mut signed: Vec<(String, String)> = headers
+        .iter()
+        .map(|(k, v)| (k.to_lowercase(), v.trim().to_string()))
+        .filter(|(k, _)| k.starts_with("x-amz-") || k == "content-type")
+        .collect();
+    signed.push(("host".to_string(), host));
+    signed.sort();
+
+    let canonical_headers: String = signed
+        .iter()
+        .map(|(k, v)| format!("{}:{}\n", k, v))
+        .collect();
+    let signed_headers = signed
+        .iter()
+        .map(|(k, _)| k.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let mut query_pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| (aws_uri_encode(&k), aws_uri_encode(&v)))
+        .collect();
+    query_pairs.sort();
+    let canonical_query = query_pairs
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let canonical_uri = if url.path().is_empty() { "/" } else { url.path() };
+
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method, canonical_uri, canonical_query, canonical_headers, signed_headers, payload_hash
+    );
+
+    let credential_scope = format!(
+        "{}/{}/{}/aws4_request",
+        date_stamp, config.region, config.service
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        credential_scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let k_date = hmac_sha256(
+        format!("AWS4{}", config.secret_access_key).as_bytes(),
+        date_stamp.as_bytes(),
+    )?;
+    let k_region = hmac_sha256(&k_date, config.region.as_bytes())?;
+    let k_service = hmac_sha256(&k_region, config.service.as_bytes())?;
+    let k_signing = hmac_sha256(&k_service, b"aws4_request")?;
+    let signature = hex::encode(hmac_sha256(&k_signing, string_to_sign.as_bytes())?);
+
+    headers.insert(
+        "Authorization".to_string(),
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            config.access_key_id, credential_scope, signed_headers, signature
+        ),
+    );
+    Ok(())
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut mac = HmacSha256::new_from_slice(key).map_err(|e| format!("Invalid HMAC key: {}", e))?;
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+/// URI-encode per SigV4 rules: everything except unreserved characters
+fn aws_uri_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
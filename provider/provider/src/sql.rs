@@ -0,0 +1,526 @@
+use crate::{SqlSource, SqlTable};
+use hyperware_process_lib::{
+    logging::{debug, warn},
+    our,
+    sqlite::{self, Sqlite},
+};
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+
+/// Statements that can change data or schema, never allowed in queries
+const FORBIDDEN_KEYWORDS: [&str; 16] = [
+    "insert", "update", "delete", "into", "upsert", "drop", "alter", "create", "attach",
+    "detach", "pragma", "vacuum", "reindex", "analyze", "begin", "commit",
+];
+
+/// Caller argument selecting a query template
+pub const SQL_QUERY_ARGUMENT: &str = "query";
+/// Caller argument carrying free-form SQL, when enabled
+pub const SQL_FREE_FORM_ARGUMENT: &str = "sql";
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Identifier(String), // Lowercased; quoted identifiers included
+    Symbol(char),
+    Literal,
+}
+
+/// Split SQL into identifiers and symbols, skipping string and numeric literals
+fn tokenize(sql: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if (c == '-' && chars.get(i + 1) == Some(&'-')) || (c == '/' && chars.get(i + 1) == Some(&'*')) {
+            return Err("SQL comments are not allowed".to_string());
+        } else if c == '\'' {
+            // String literal, '' escapes a quote
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err("Unterminated string literal".to_string()),
+                    Some('\'') if chars.get(i + 1) == Some(&'\'') => i += 2,
+                    Some('\'') => break,
+                    Some(_) => i += 1,
+                }
+            }
+            i += 1;
+            tokens.push(Token::Literal);
+        } else if c == '"' || c == '`' || c == '[' {
+            let close = if c == '[' { ']' } else { c };
+            let start = i + 1;
+            let end = chars[start..]
+                .iter()
+                .position(|&ch| ch == close)
+                .map(|p| start + p)
+                .ok_or("Unterminated quoted identifier")?;
+            tokens.push(Token::Identifier(chars[start..end].iter().collect::<String>().to_lowercase()));
+            i = end + 1;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Identifier(chars[start..i].iter().collect::<String>().to_lowercase()));
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Token::Literal);
+        } else {
+            tokens.push(Token::Symbol(c));
+            i += 1;
+        }
+    }
+    Ok(tokens)
+}
+
+/// Static checks: a single SELECT (or WITH ... SELECT) statement with no write keywords
+fn check_read_only(sql: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = tokenize(sql)?;
+    if tokens.last() == Some(&Token::Symbol(';')) {
+        tokens.pop();
+    }
+    if tokens.contains(&Token::Symbol(';')) {
+        return Err("Only a single SQL statement is allowed".to_string());
+    }
+    match tokens.first() {
+        Some(Token::Identifier(first)) if first == "select" || first == "with" => {}
+        _ => return Err("Only SELECT queries are allowed".to_string()),
+    }
+    for token in &tokens {
+        if let Token::Identifier(word) = token {
+            if FORBIDDEN_KEYWORDS.contains(&word.as_str()) {
+                return Err(format!("'{}' is not allowed in queries", word.to_uppercase()));
+            }
+            if word.starts_with("sqlite_") || word.starts_with("pragma_") {
+                return Err(format!("'{}' is not accessible", word));
+            }
+        }
+    }
+    check_not_recursive(&tokens)?;
+    Ok(tokens)
+}
+
+/// Recursive CTEs can recurse without bound, and the outer LIMIT doesn't stop them. SQLite
+/// treats a CTE that names itself as recursive even without the RECURSIVE keyword.
+fn check_not_recursive(tokens: &[Token]) -> Result<(), String> {
+    let identifier = |index: usize| match tokens.get(index) {
+        Some(Token::Identifier(word)) => Some(word.as_str()),
+        _ => None,
+    };
+    // Index just past the parenthesis closing the one at `open`
+    let skip_group = |open: usize| -> Result<usize, String> {
+        let mut depth = 0;
+        for (index, token) in tokens.iter().enumerate().skip(open) {
+            match token {
+                Token::Symbol('(') => depth += 1,
+                Token::Symbol(')') if depth == 1 => return Ok(index + 1),
+                Token::Symbol(')') => depth -= 1,
+                _ => {}
+            }
+        }
+        Err("Unbalanced parentheses".to_string())
+    };
+
+    let mut index = 0;
+    while index < tokens.len() {
+        if identifier(index) != Some("with") {
+            index += 1;
+            continue;
+        }
+        index += 1;
+        if identifier(index) == Some("recursive") {
+            return Err("Recursive queries are not allowed".to_string());
+        }
+        // name [(columns)] AS [NOT] [MATERIALIZED] (body), ...
+        loop {
+            let name = identifier(index).ok_or("Malformed WITH clause")?.to_string();
+            index += 1;
+            if tokens.get(index) == Some(&Token::Symbol('(')) {
+                index = skip_group(index)?;
+            }
+            while matches!(identifier(index), Some("as" | "not" | "materialized")) {
+                index += 1;
+            }
+            if tokens.get(index) != Some(&Token::Symbol('(')) {
+                return Err("Malformed WITH clause".to_string());
+            }
+            let end = skip_group(index)?;
+            if tokens[index..end].contains(&Token::Identifier(name.clone())) {
+                return Err(format!("Recursive queries are not allowed: '{}' refers to itself", name));
+            }
+            index = end;
+            if tokens.get(index) != Some(&Token::Symbol(',')) {
+                break;
+            }
+            index += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Number of `?` placeholders, ignoring any inside string literals or quoted identifiers
+fn count_placeholders(tokens: &[Token]) -> usize {
+    tokens.iter().filter(|token| **token == Token::Symbol('?')).count()
+}
+
+/// Checks run at registration; table and column access is enforced against the live schema per call
+pub fn validate_sql_source(source: &SqlSource) -> Result<(), String> {
+    if source.queries.is_empty() && source.free_form.is_none() {
+        return Err("SQL provider needs at least one query template or free-form access".to_string());
+    }
+    let mut names = HashSet::new();
+    for template in &source.queries {
+        if !names.insert(template.name.as_str()) {
+            return Err(format!("Duplicate query template name: {}", template.name));
+        }
+        let tokens =
+            check_read_only(&template.sql).map_err(|e| format!("Query template '{}': {}", template.name, e))?;
+        let placeholders = count_placeholders(&tokens);
+        if placeholders != template.parameters.len() {
+            return Err(format!(
+                "Query template '{}' has {} placeholders but declares {} parameters",
+                template.name,
+                placeholders,
+                template.parameters.len()
+            ));
+        }
+    }
+    if source.row_limit == 0 {
+        return Err("SQL row limit must be greater than zero".to_string());
+    }
+    Ok(())
+}
+
+/// Reject queries touching undeclared tables or hidden columns of declared ones
+async fn check_access(db: &Sqlite, allowed: &[SqlTable], tokens: &[Token]) -> Result<(), String> {
+    let existing: Vec<String> = db
+        .read("SELECT name FROM sqlite_master WHERE type IN ('table', 'view')".to_string(), vec![])
+        .await
+        .map_err(|e| format!("Failed to read database schema: {}", e))?
+        .iter()
+        .filter_map(|row| row.get("name").and_then(Value::as_str).map(str::to_lowercase))
+        .collect();
+
+    let allowed_tables: HashMap<String, &SqlTable> =
+        allowed.iter().map(|t| (t.name.to_lowercase(), t)).collect();
+    let mut hidden_columns = HashSet::new();
+    for (name, table) in &allowed_tables {
+        let columns = db
+            .read("SELECT name FROM pragma_table_info(?)".to_string(), vec![json!(name)])
+            .await
+            .map_err(|e| format!("Failed to read columns of '{}': {}", name, e))?;
+        for column in columns.iter().filter_map(|row| row.get("name").and_then(Value::as_str)) {
+            if !table.columns.iter().any(|c| c.eq_ignore_ascii_case(column)) {
+                hidden_columns.insert(column.to_lowercase());
+            }
+        }
+    }
+
+    for (index, token) in tokens.iter().enumerate() {
+        let in_call = index > 0 && tokens[index - 1] == Token::Symbol('(');
+        match token {
+            Token::Identifier(word) if existing.contains(word) && !allowed_tables.contains_key(word) => {
+                return Err(format!("Table '{}' is not accessible", word));
+            }
+            Token::Identifier(word) if hidden_columns.contains(word) => {
+                return Err(format!("Column '{}' is not accessible", word));
+            }
+            // `*` would expand to hidden columns; `count(*)` is fine
+            Token::Symbol('*') if !hidden_columns.is_empty() && !in_call => {
+                return Err("SELECT * is not allowed on tables with hidden columns".to_string());
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Run a template or free-form query, returning rows plus column metadata
+pub async fn query_sql(source: &SqlSource, args: &Vec<(String, String)>) -> Result<String, String> {
+    let arg = |name: &str| args.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone());
+
+    let (sql, params) = match (arg(SQL_QUERY_ARGUMENT), arg(SQL_FREE_FORM_ARGUMENT)) {
+        (Some(name), _) => {
+            let template = source
+                .queries
+                .iter()
+                .find(|q| q.name == name)
+                .ok_or_else(|| format!("Unknown query: {}", name))?;
+            let params = template
+                .parameters
+                .iter()
+                .map(|p| {
+                    arg(p)
+                        .map(|v| crate::util::parse_parameter_value(&v))
+                        .ok_or_else(|| format!("Missing argument for parameter: {}", p))
+                })
+                .collect::<Result<Vec<Value>, String>>()?;
+            (template.sql.clone(), params)
+        }
+        (None, Some(sql)) if source.free_form.is_some() => (sql, Vec::new()),
+        (None, Some(_)) => return Err("Free-form SQL is not enabled for this provider".to_string()),
+        (None, None) => {
+            return Err(format!(
+                "Pass '{}' with a query name{}",
+                SQL_QUERY_ARGUMENT,
+                if source.free_form.is_some() { " or 'sql' with a SELECT statement" } else { "" }
+            ))
+        }
+    };
+
+    let tokens = check_read_only(&sql)?;
+    let timeout = source.free_form.as_ref().map(|f| f.timeout_seconds).unwrap_or(30);
+    let db = sqlite::open(our().package_id(), &source.database, Some(timeout))
+        .await
+        .map_err(|e| format!("Failed to open database '{}': {}", source.database, e))?;
+    check_access(&db, &source.allowed_tables, &tokens).await?;
+
+    // Fetch one extra row to tell whether the result was truncated
+    let inner = sql.trim().trim_end_matches(';');
+    let limited = format!("SELECT * FROM ({}) LIMIT {}", inner, source.row_limit + 1);
+    debug!("Running SQL query on '{}': {}", source.database, limited);
+    let mut rows = db.read(limited, params).await.map_err(|e| {
+        warn!("SQL query failed on '{}': {}", source.database, e);
+        format!("Query failed: {}", e)
+    })?;
+
+    let truncated = rows.len() as u64 > source.row_limit;
+    rows.truncate(source.row_limit as usize);
+
+    let columns = column_metadata(inner, &rows);
+    let rows: Vec<Value> = rows
+        .into_iter()
+        .map(|row| Value::Object(row.into_iter().collect::<Map<String, Value>>()))
+        .collect();
+    Ok(json!({
+        "columns": columns,
+        "rows": rows,
+        "row_count": rows.len(),
+        "truncated": truncated,
+    })
+    .to_string())
+}
+
+/// Column names in the order they appear in the query, with a type inferred from the values
+fn column_metadata(sql: &str, rows: &[HashMap<String, Value>]) -> Vec<Value> {
+    let mut names: Vec<&String> = Vec::new();
+    for row in rows {
+        for name in row.keys() {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    let lowered = sql.to_lowercase();
+    names.sort_by_key(|name| (lowered.find(&name.to_lowercase()).unwrap_or(usize::MAX), name.to_string()));
+
+    names
+        .into_iter()
+        .map(|name| {
+            let sql_type = rows
+                .iter()
+                .filter_map(|row| row.get(name))
+                .find(|v| !v.is_null())
+                .map(|v| match v {
+                    Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+                    Value::Number(_) => "real",
+                    Value::Bool(_) => "integer",
+                    Value::String(_) => "text",
+                    _ => "blob",
+                })
+                .unwrap_or("null");
+            json!({ "name": name, "type": sql_type })
+        })
+        .collect()
+}
+
+/// Load an uploaded dataset into a table of the provider's sqlite database, replacing it
+pub async fn import_rows(database: &str, table: &str, rows: Vec<Map<String, Value>>) -> Result<u64, String> {
+    let is_identifier = |s: &str| {
+        !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    if !is_identifier(table) {
+        return Err(format!("Invalid table name: {}", table));
+    }
+    let mut columns: Vec<String> = Vec::new();
+    for row in &rows {
+        for key in row.keys() {
+            if !columns.contains(key) {
+                if !is_identifier(key) {
+                    return Err(format!("Column '{}' is not a valid SQL identifier", key));
+                }
+                columns.push(key.clone());
+            }
+        }
+    }
+    if columns.is_empty() {
+        return Err("Dataset has no columns".to_string());
+    }
+
+    let db = sqlite::open(our().package_id(), database, None)
+        .await
+        .map_err(|e| format!("Failed to open database '{}': {}", database, e))?;
+    let tx_id = db.begin_tx().await.map_err(|e| e.to_string())?;
+
+    db.write(format!("DROP TABLE IF EXISTS {}", table), vec![], Some(tx_id))
+        .await
+        .map_err(|e| e.to_string())?;
+    db.write(
+        format!("CREATE TABLE {} ({})", table, columns.join(", ")),
+        vec![],
+        Some(tx_id),
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let insert = format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        table,
+        columns.join(", "),
+        vec!["?"; columns.len()].join(", ")
+    );
+    let row_count = rows.len() as u64;
+    for row in rows {
+        let params = columns
+            .iter()
+            .map(|c| match row.get(c) {
+                // Nested values are stored as JSON text
+                Some(v @ (Value::Array(_) | Value::Object(_))) => Value::String(v.to_string()),
+                Some(v) => v.clone(),
+                None => Value::Null,
+            })
+            .collect();
+        db.write(insert.clone(), params, Some(tx_id))
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    db.commit_tx(tx_id).await.map_err(|e| e.to_string())?;
+
+    debug!("Imported {} rows into {}.{}", row_count, database, table);
+    Ok(row_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FreeFormSql, SqlQueryTemplate};
+
+    #[test]
+    fn tokenizer_skips_literals() {
+        assert_eq!(
+            tokenize("SELECT name, 'it''s' FROM \"Users\" WHERE id = 4.5").unwrap(),
+            vec![
+                Token::Identifier("select".to_string()),
+                Token::Identifier("name".to_string()),
+                Token::Symbol(','),
+                Token::Literal,
+                Token::Identifier("from".to_string()),
+                Token::Identifier("users".to_string()),
+                Token::Identifier("where".to_string()),
+                Token::Identifier("id".to_string()),
+                Token::Symbol('='),
+                Token::Literal,
+            ]
+        );
+        assert!(tokenize("SELECT 'open").is_err());
+        assert!(tokenize("SELECT [open").is_err());
+        assert!(tokenize("SELECT 1 -- comment").is_err());
+        assert!(tokenize("SELECT /* comment */ 1").is_err());
+    }
+
+    #[test]
+    fn only_single_selects_are_read_only() {
+        assert!(check_read_only("SELECT * FROM users;").is_ok());
+        assert!(check_read_only("select 'drop table users' from users").is_ok());
+        assert!(check_read_only("SELECT * FROM users; DROP TABLE users").is_err());
+        assert!(check_read_only("INSERT INTO users VALUES (1)").is_err());
+        assert!(check_read_only("WITH t AS (SELECT 1) DELETE FROM users").is_err());
+        assert!(check_read_only("SELECT * FROM sqlite_master").is_err());
+        assert!(check_read_only("SELECT * FROM pragma_table_info('users')").is_err());
+    }
+
+    #[test]
+    fn recursive_ctes_are_refused() {
+        assert!(check_read_only("WITH t AS (SELECT 1) SELECT * FROM t").is_ok());
+        assert!(check_read_only("WITH t AS (SELECT 't') SELECT * FROM t").is_ok());
+        assert!(check_read_only(
+            "WITH a(x) AS (SELECT 1), b AS NOT MATERIALIZED (SELECT x FROM a) SELECT * FROM b"
+        )
+        .is_ok());
+
+        assert!(check_read_only(
+            "WITH RECURSIVE t(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM t) SELECT n FROM t"
+        )
+        .is_err());
+        // SQLite recurses without the keyword too
+        assert!(check_read_only("WITH t(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM t) SELECT n FROM t").is_err());
+        assert!(check_read_only("WITH a AS (SELECT 1), b AS (SELECT * FROM \"B\") SELECT * FROM b").is_err());
+        assert!(check_read_only("SELECT * FROM (WITH t AS (SELECT * FROM t) SELECT * FROM t)").is_err());
+        assert!(check_read_only("WITH t SELECT 1").is_err());
+    }
+
+    #[test]
+    fn placeholders_in_literals_are_not_counted() {
+        let tokens = check_read_only("SELECT * FROM t WHERE a = ? AND b = '?' AND \"c?\" = ?").unwrap();
+        assert_eq!(count_placeholders(&tokens), 2);
+    }
+
+    fn source(queries: Vec<SqlQueryTemplate>) -> SqlSource {
+        SqlSource {
+            database: "inventory".to_string(),
+            allowed_tables: vec![SqlTable { name: "items".to_string(), columns: vec!["name".to_string()] }],
+            queries,
+            free_form: None,
+            row_limit: 100,
+        }
+    }
+
+    fn template(name: &str, sql: &str, parameters: &[&str]) -> SqlQueryTemplate {
+        SqlQueryTemplate {
+            name: name.to_string(),
+            description: String::new(),
+            sql: sql.to_string(),
+            parameters: parameters.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn sql_sources_are_validated() {
+        let by_name = template("by_name", "SELECT name FROM items WHERE name = ?", &["name"]);
+        assert!(validate_sql_source(&source(vec![by_name.clone()])).is_ok());
+
+        assert!(validate_sql_source(&source(vec![])).is_err());
+        let free_form = SqlSource { free_form: Some(FreeFormSql { timeout_seconds: 5 }), ..source(vec![]) };
+        assert!(validate_sql_source(&free_form).is_ok());
+
+        assert!(validate_sql_source(&source(vec![by_name.clone(), by_name.clone()])).is_err());
+        let wrong_count = template("by_name", "SELECT name FROM items WHERE name = '?'", &["name"]);
+        assert!(validate_sql_source(&source(vec![wrong_count])).is_err());
+        assert!(validate_sql_source(&SqlSource { row_limit: 0, ..source(vec![by_name]) }).is_err());
+    }
+
+    #[test]
+    fn columns_follow_the_query_order() {
+        let row = |pairs: &[(&str, Value)]| -> HashMap<String, Value> {
+            pairs.iter().map(|(name, value)| (name.to_string(), value.clone())).collect()
+        };
+        let rows = vec![
+            row(&[("price", json!(1.5)), ("name", json!("bolt"))]),
+            row(&[("name", json!("nut")), ("qty", Value::Null), ("price", json!(2))]),
+        ];
+        assert_eq!(
+            column_metadata("SELECT name, price, qty FROM items", &rows),
+            vec![
+                json!({"name": "name", "type": "text"}),
+                json!({"name": "price", "type": "real"}),
+                json!({"name": "qty", "type": "null"}),
+            ]
+        );
+        assert!(column_metadata("SELECT name FROM items", &[]).is_empty());
+    }
+}
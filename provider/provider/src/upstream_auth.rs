@@ -0,0 +1,141 @@
+use crate::url_safety::check_upstream_url;
+use crate::util::{now_unix_secs, send_async_http_request};
+use crate::{EndpointDefinition, UpstreamAuth};
+use hyperware_process_lib::{
+    http::Method as HyperwareHttpMethod,
+    logging::{debug, error},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Tokens are refreshed this many seconds before they actually expire
+const EXPIRY_SKEW_SECS: u64 = 30;
+/// Used when the token endpoint does not return `expires_in`
+const DEFAULT_TOKEN_LIFETIME_SECS: u64 = 3600;
+
+/// Access token cached in provider state, keyed by provider name
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CachedAccessToken {
+    pub access_token: String,
+    pub expires_at: u64, // Unix seconds
+    // Latest refresh token, if the token endpoint rotated it
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+/// Return a valid access token for `provider_name`, fetching a new one if the cached token
+/// is missing, expired, or `force_refresh` is set (e.g. after the upstream answered 401).
+/// The token URL goes through the same host checks as the endpoint's own requests.
+pub async fn get_access_token(
+    provider_name: &str,
+    endpoint: &EndpointDefinition,
+    cache: &mut HashMap<String, CachedAccessToken>,
+    force_refresh: bool,
+) -> Result<String, String> {
+    let now = now_unix_secs();
+    if !force_refresh {
+        if let Some(cached) = cache.get(provider_name) {
+            if cached.expires_at > now + EXPIRY_SKEW_SECS {
+                return Ok(cached.access_token.clone());
+            }
+        }
+    }
+
+    let auth = endpoint
+        .upstream_auth
+        .as_ref()
+        .ok_or("Endpoint has no upstream auth configured")?;
+    debug!("Fetching upstream access token for provider '{}'", provider_name);
+
+    let (token_url, form) = match auth {
+        UpstreamAuth::OAuth2ClientCredentials(config) => {
+            let mut form = vec![
+                ("grant_type", "client_credentials".to_string()),
+                ("client_id", config.client_id.clone()),
+                ("client_secret", config.client_secret.clone()),
+            ];
+            if let Some(scope) = &config.scope {
+                form.push(("scope", scope.clone()));
+            }
+            (&config.token_url, form)
+        }
+        UpstreamAuth::OAuth2RefreshToken(config) => {
+            // Prefer a rotated refresh token over the one configured at registration
+            let refresh_token = cache
+                .get(provider_name)
+                .and_then(|c| c.refresh_token.clone())
+                .unwrap_or_else(|| config.refresh_token.clone());
+            let mut form = vec![
+                ("grant_type", "refresh_token".to_string()),
+                ("refresh_token", refresh_token),
+                ("client_id", config.client_id.clone()),
+            ];
+            if let Some(secret) = &config.client_secret {
+                form.push(("client_secret", secret.clone()));
+            }
+            (&config.token_url, form)
+        }
+    };
+
+    let body = form
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, urlencoding::encode(v)))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let url = url::Url::parse(token_url).map_err(|e| format!("Invalid token URL: {}", e))?;
+    check_upstream_url(&url, endpoint)?;
+    let mut headers = HashMap::new();
+    headers.insert(
+        "Content-Type".to_string(),
+        "application/x-www-form-urlencoded".to_string(),
+    );
+    headers.insert("Accept".to_string(), "application/json".to_string());
+
+    let response = send_async_http_request(
+        HyperwareHttpMethod::POST,
+        url,
+        Some(headers),
+        30,
+        body.into_bytes(),
+    )
+    .await
+    .map_err(|e| format!("Failed to reach token endpoint: {:?}", e))?;
+
+    if !response.status().is_success() {
+        // Never log the response body here, it may echo credentials
+        error!(
+            "upstream_token_refresh_failed: provider={}, status={}",
+            provider_name,
+            response.status()
+        );
+        return Err(format!(
+            "Token endpoint returned error status {}",
+            response.status()
+        ));
+    }
+
+    let token: TokenResponse = serde_json::from_slice(response.body())
+        .map_err(|e| format!("Failed to parse token response: {}", e))?;
+
+    let previous_refresh_token = cache
+        .get(provider_name)
+        .and_then(|c| c.refresh_token.clone());
+    cache.insert(
+        provider_name.to_string(),
+        CachedAccessToken {
+            access_token: token.access_token.clone(),
+            expires_at: now + token.expires_in.unwrap_or(DEFAULT_TOKEN_LIFETIME_SECS),
+            refresh_token: token.refresh_token.or(previous_refresh_token),
+        },
+    );
+
+    debug!("Cached new upstream access token for provider '{}'", provider_name);
+    Ok(token.access_token)
+}
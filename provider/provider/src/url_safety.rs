@@ -0,0 +1,290 @@
+use crate::{EndpointDefinition, EndpointKind, PipelineTarget, RegisteredProvider, UpstreamAuth};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use url::{Host, Url};
+
+/// Host names that always point back into the provider's own network
+const PRIVATE_HOST_SUFFIXES: [&str; 4] = [".localhost", ".local", ".internal", ".home.arpa"];
+
+/// Pin the upstream host of every HTTP endpoint of `provider` that has no allowlist yet
+pub fn pin_upstream_hosts(provider: &mut RegisteredProvider) -> Result<(), String> {
+    pin_endpoint_hosts(&mut provider.endpoint)?;
+    if let Some(pipeline) = provider.pipeline.as_mut() {
+        for step in pipeline.steps.iter_mut() {
+            if let PipelineTarget::Local(endpoint) = &mut step.target {
+                pin_endpoint_hosts(endpoint)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn pin_endpoint_hosts(endpoint: &mut EndpointDefinition) -> Result<(), String> {
+    if !is_http_kind(&endpoint.kind) {
+        return Ok(());
+    }
+    let token_host = token_host(endpoint)?;
+    if endpoint.allowed_hosts.is_empty() {
+        let template_host = template_host(&endpoint.url_template)?;
+        let token_host = token_host.filter(|host| *host != template_host);
+        endpoint.allowed_hosts = std::iter::once(template_host).chain(token_host).collect();
+    } else if let Some(host) = token_host {
+        if !endpoint.allowed_hosts.iter().any(|a| a.eq_ignore_ascii_case(&host)) {
+            return Err(format!("Token URL host '{}' is not in allowed_hosts", host));
+        }
+    }
+    for host in &endpoint.allowed_hosts {
+        let url = Url::parse(&format!("https://{}/", host))
+            .map_err(|_| format!("Invalid allowed host: {}", host))?;
+        if !endpoint.allow_private_network {
+            check_public_host(&url)?;
+        }
+    }
+    Ok(())
+}
+
+/// Host of the endpoint's OAuth token URL, if it fetches tokens
+fn token_host(endpoint: &EndpointDefinition) -> Result<Option<String>, String> {
+    let Some(auth) = &endpoint.upstream_auth else {
+        return Ok(None);
+    };
+    let token_url = match auth {
+        UpstreamAuth::OAuth2ClientCredentials(config) => &config.token_url,
+        UpstreamAuth::OAuth2RefreshToken(config) => &config.token_url,
+    };
+    Url::parse(token_url)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| host.to_lowercase()))
+        .map(Some)
+        .ok_or_else(|| format!("Invalid token URL: {}", token_url))
+}
+
+/// Host of a URL template, which must not be parameterized
+fn template_host(url_template: &str) -> Result<String, String> {
+    Url::parse(url_template)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| host.to_lowercase()))
+        .filter(|host| !host.contains('{') && !host.contains("%7b"))
+        .ok_or_else(|| {
+            format!(
+                "Cannot pin the upstream host of '{}', list it in allowed_hosts",
+                url_template
+            )
+        })
+}
+
+fn is_http_kind(kind: &EndpointKind) -> bool {
+    matches!(kind, EndpointKind::Curl | EndpointKind::GraphQl(_))
+}
+
+/// Last check on the fully substituted URL before any request goes out
+pub fn check_upstream_url(url: &Url, endpoint: &EndpointDefinition) -> Result<(), String> {
+    if url.scheme() != "https" && url.scheme() != "http" {
+        return Err(format!("Upstream URL rejected: unsupported scheme '{}'", url.scheme()));
+    }
+    let host = url
+        .host_str()
+        .map(str::to_lowercase)
+        .ok_or("Upstream URL rejected: no host")?;
+
+    // Providers registered before hosts were pinned fall back to their template's and token
+    // endpoint's hosts
+    let allowed = if endpoint.allowed_hosts.is_empty() {
+        let mut hosts = vec![template_host(&endpoint.url_template)?];
+        hosts.extend(token_host(endpoint)?);
+        hosts
+    } else {
+        endpoint.allowed_hosts.clone()
+    };
+    if !allowed.iter().any(|a| a.eq_ignore_ascii_case(&host)) {
+        return Err(format!(
+            "Upstream URL rejected: host '{}' is not in the provider's allowed hosts",
+            host
+        ));
+    }
+    if !endpoint.allow_private_network {
+        check_public_host(url)?;
+    }
+    Ok(())
+}
+
+fn check_public_host(url: &Url) -> Result<(), String> {
+    let private = match url.host() {
+        Some(Host::Ipv4(ip)) => is_private_ipv4(ip),
+        Some(Host::Ipv6(ip)) => is_private_ipv6(ip),
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_lowercase();
+            domain == "localhost"
+                || PRIVATE_HOST_SUFFIXES.iter().any(|suffix| domain.ends_with(suffix))
+        }
+        None => true,
+    };
+    if private {
+        return Err(format!(
+            "Upstream URL rejected: '{}' is a private or local address",
+            url.host_str().unwrap_or_default()
+        ));
+    }
+    Ok(())
+}
+
+fn is_private_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local() // Includes the 169.254.169.254 cloud metadata endpoint
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || (a == 100 && (64..128).contains(&b)) // Carrier-grade NAT
+        || a == 0
+}
+
+fn is_private_ipv6(ip: Ipv6Addr) -> bool {
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_private_ipv4(v4);
+    }
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || (first & 0xfe00) == 0xfc00 // Unique local
+        || (first & 0xffc0) == 0xfe80 // Link-local
+}
+
+/// Path parameters are substituted percent-encoded, and may not be dot segments
+pub fn encode_path_parameter(name: &str, value: &str) -> Result<String, String> {
+    if value == "." || value == ".." {
+        return Err(format!("Upstream URL rejected: invalid value for path parameter '{}'", name));
+    }
+    Ok(urlencoding::encode(value).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(url_template: &str, allowed_hosts: &[&str], allow_private_network: bool) -> EndpointDefinition {
+        EndpointDefinition {
+            url_template: url_template.to_string(),
+            allowed_hosts: allowed_hosts.iter().map(|host| host.to_string()).collect(),
+            allow_private_network,
+            ..EndpointDefinition::empty()
+        }
+    }
+
+    fn check(url: &str, endpoint: &EndpointDefinition) -> Result<(), String> {
+        check_upstream_url(&Url::parse(url).unwrap(), endpoint)
+    }
+
+    #[test]
+    fn only_allowed_hosts_pass() {
+        let endpoint = endpoint("https://api.example.com/v1/{id}", &["api.example.com"], false);
+        assert!(check("https://api.example.com/v1/42", &endpoint).is_ok());
+        assert!(check("https://API.example.com/v1/42", &endpoint).is_ok());
+        assert!(check("https://evil.example.com/v1/42", &endpoint).is_err());
+        assert!(check("ftp://api.example.com/v1/42", &endpoint).is_err());
+    }
+
+    #[test]
+    fn unpinned_endpoints_fall_back_to_the_template_host() {
+        let endpoint = endpoint("https://api.example.com/v1", &[], false);
+        assert!(check("https://api.example.com/v1", &endpoint).is_ok());
+        assert!(check("https://other.example.com/v1", &endpoint).is_err());
+
+        let parameterized = self::endpoint("https://{host}/v1", &[], false);
+        assert!(check("https://api.example.com/v1", &parameterized).is_err());
+    }
+
+    #[test]
+    fn private_addresses_are_refused() {
+        for host in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "[::1]",
+            "[fd00::1]",
+            "[fe80::1]",
+            "[::ffff:10.0.0.1]",
+            "localhost",
+            "db.internal",
+            "printer.local",
+        ] {
+            let url = format!("http://{}/", host);
+            let allowed = Url::parse(&url).unwrap().host_str().unwrap().to_string();
+            let endpoint = endpoint(&url, &[&allowed], false);
+            assert!(check(&url, &endpoint).is_err(), "{} should be refused", host);
+
+            let private = self::endpoint(&url, &[&allowed], true);
+            assert!(check(&url, &private).is_ok(), "{} should pass with allow_private_network", host);
+        }
+    }
+
+    #[test]
+    fn public_addresses_pass() {
+        for host in ["93.184.216.34", "[2606:2800:220:1::1]", "api.example.com"] {
+            let url = format!("https://{}/", host);
+            let allowed = Url::parse(&url).unwrap().host_str().unwrap().to_string();
+            assert!(check(&url, &endpoint(&url, &[&allowed], false)).is_ok(), "{} should pass", host);
+        }
+    }
+
+    #[test]
+    fn pinning_defaults_to_public_hosts() {
+        let mut named = endpoint("https://api.example.com/v1", &[], false);
+        assert!(pin_endpoint_hosts(&mut named).is_ok());
+        assert_eq!(named.allowed_hosts, vec!["api.example.com".to_string()]);
+
+        let mut private = endpoint("http://localhost:8080/v1", &[], false);
+        assert!(pin_endpoint_hosts(&mut private).is_err());
+        private.allow_private_network = true;
+        assert!(pin_endpoint_hosts(&mut private).is_ok());
+    }
+
+    #[test]
+    fn token_url_host_is_pinned_and_checked() {
+        let with_token_url = |allowed_hosts: &[&str], token_url: &str| EndpointDefinition {
+            upstream_auth: Some(UpstreamAuth::OAuth2ClientCredentials(crate::OAuth2ClientCredentials {
+                token_url: token_url.to_string(),
+                client_id: "client".to_string(),
+                client_secret: "secret".to_string(),
+                scope: None,
+            })),
+            ..endpoint("https://api.example.com/v1", allowed_hosts, false)
+        };
+
+        let mut unpinned = with_token_url(&[], "https://auth.example.com/token");
+        assert!(check("https://auth.example.com/token", &unpinned).is_ok());
+        assert!(pin_endpoint_hosts(&mut unpinned).is_ok());
+        assert_eq!(unpinned.allowed_hosts, vec!["api.example.com".to_string(), "auth.example.com".to_string()]);
+
+        let mut unlisted = with_token_url(&["api.example.com"], "https://auth.example.com/token");
+        assert!(pin_endpoint_hosts(&mut unlisted).is_err());
+        assert!(check("https://auth.example.com/token", &unlisted).is_err());
+
+        let mut private = with_token_url(&[], "http://169.254.169.254/token");
+        assert!(pin_endpoint_hosts(&mut private).is_err());
+        assert!(check("http://169.254.169.254/token", &private).is_err());
+    }
+
+    #[test]
+    fn explicit_allowed_hosts_are_kept_and_checked() {
+        let mut listed = endpoint("https://api.example.com/v1", &["cdn.example.com"], false);
+        assert!(pin_endpoint_hosts(&mut listed).is_ok());
+        assert_eq!(listed.allowed_hosts, vec!["cdn.example.com".to_string()]);
+
+        let mut private = endpoint("https://api.example.com/v1", &["api.example.com", "10.0.0.5"], false);
+        assert!(pin_endpoint_hosts(&mut private).is_err());
+        private.allow_private_network = true;
+        assert!(pin_endpoint_hosts(&mut private).is_ok());
+    }
+
+    #[test]
+    fn dot_segments_are_refused_as_path_parameters() {
+        assert!(encode_path_parameter("id", "..").is_err());
+        assert!(encode_path_parameter("id", ".").is_err());
+        assert_eq!(encode_path_parameter("id", "../admin").unwrap(), "..%2Fadmin");
+    }
+}
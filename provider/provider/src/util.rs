@@ -1,12 +1,9 @@
 use crate::{
-    EndpointDefinition, ProviderCall, PaymentPayload, FieldDef, InputSchema, 
+    AccessPass, EndpointDefinition, EndpointKind, ProviderCall, PaymentPayload, FieldDef, InputSchema, 
     OutputSchema, AcceptedPayment, PaymentRequirements, ParameterDefinition, 
-    RegisteredProvider
-};
-use crate::constants::{
-    USDC_BASE_ADDRESS, WALLET_PREFIX, USDC_SEPOLIA_ADDRESS, USDC_EIP712_NAME, 
-    USDC_EIP712_VERSION, X402_PAYMENT_NETWORK
+    PipelineTarget, RegisteredProvider, RequestSigner, SandboxMode
 };
+use crate::constants::{USDC_BASE_ADDRESS, WALLET_PREFIX};
 use hyperware_process_lib::{
     eth::{Address as EthAddress, EthError, TransactionReceipt, TxHash, U256},
     get_blob,
@@ -16,7 +13,7 @@ use hyperware_process_lib::{
         HeaderName, HeaderValue, Method as HyperwareHttpMethod, Response as HyperwareHttpResponse,
         StatusCode,
     },
-    hypermap, Request,
+    hypermap, ProcessId, Request,
     logging::{debug, error, warn, info},
     our,
 };
@@ -25,6 +22,22 @@ use std::collections::HashMap;
 use std::str::FromStr;
 use url::Url;
 use base64ct::{Base64, Encoding};
+use crate::upstream_auth::{get_access_token, CachedAccessToken};
+use crate::x402::{
+    max_timeout_secs, option_amount, option_pay_to, payment_options, validate_payment_options,
+    X402_SCHEME,
+};
+use crate::signing::apply_request_signers;
+use crate::graphql::{build_graphql_body, graphql_errors, graphql_type_to_field_type};
+use crate::url_safety::{check_upstream_url, encode_path_parameter};
+use crate::sandbox::send_upstream;
+use crate::process_backend::call_process_backend;
+use crate::dataset::{query_dataset, DATASET_ARGUMENTS};
+use crate::sql::{query_sql, validate_sql_source, SQL_FREE_FORM_ARGUMENT, SQL_QUERY_ARGUMENT};
+use crate::pipeline::validate_pipeline;
+use crate::continuation::MIN_RESPONSE_CHUNK_BYTES;
+use crate::attestation::{attestation_header, CallerContext, ATTESTATION_HEADER};
+use crate::pipeline::{pipeline_call_arguments, run_pipeline, RemoteSteps};
 
 /// Make an HTTP request using http-client and await its response.
 ///
@@ -421,12 +434,132 @@ pub async fn call_provider(
 }
 */
 
+/// Definition checks shared by registration, updates and validation
+pub fn validate_provider_definition(provider: &RegisteredProvider) -> Result<(), String> {
+    validate_pipeline(provider)?;
+    if let Some(config) = &provider.async_job {
+        if config.upstream_timeout_seconds == 0 {
+            return Err("Async job upstream timeout must be at least one second".to_string());
+        }
+        if config.result_ttl_seconds == 0 {
+            return Err("Async job results must be kept for at least one second".to_string());
+        }
+    }
+    if let Some(config) = &provider.circuit_breaker {
+        if config.consecutive_failures == 0 || config.window_size == 0 || config.open_seconds == 0 {
+            return Err("Circuit breaker thresholds and open duration must be at least 1".to_string());
+        }
+        if !(config.failure_rate_threshold > 0.0 && config.failure_rate_threshold <= 1.0) {
+            return Err("Circuit breaker failure rate threshold must be between 0 and 1".to_string());
+        }
+    }
+    validate_payment_options(provider)?;
+    if let Some(max_bytes) = provider.max_response_bytes {
+        if max_bytes < MIN_RESPONSE_CHUNK_BYTES {
+            return Err(format!(
+                "Maximum response size must be at least {} bytes",
+                MIN_RESPONSE_CHUNK_BYTES
+            ));
+        }
+    }
+    let local_endpoints = std::iter::once(&provider.endpoint).chain(
+        provider
+            .pipeline
+            .iter()
+            .flat_map(|p| p.steps.iter())
+            .filter_map(|step| match &step.target {
+                PipelineTarget::Local(endpoint) => Some(endpoint),
+                PipelineTarget::Remote(_) => None,
+            }),
+    );
+    for endpoint in local_endpoints {
+        // Both would write the Authorization header, and the signer runs last
+        if endpoint.upstream_auth.is_some() {
+            let signs_authorization = endpoint.request_signers.iter().any(|signer| match signer {
+                RequestSigner::AwsSigV4(_) => true,
+                RequestSigner::HmacSha256(hmac) => {
+                    hmac.signature_header.eq_ignore_ascii_case("authorization")
+                        || hmac.timestamp_header.eq_ignore_ascii_case("authorization")
+                }
+            });
+            if signs_authorization {
+                return Err(format!(
+                    "Endpoint {} can't combine OAuth2 upstream auth with a signer that sets the Authorization header",
+                    endpoint.base_url
+                ));
+            }
+        }
+        match &endpoint.kind {
+            EndpointKind::Sql(source) => validate_sql_source(source)?,
+            // The document and variables travel in a JSON body, which only POST sends
+            EndpointKind::GraphQl(_) if !endpoint.method.eq_ignore_ascii_case("POST") => {
+                return Err(format!("GraphQL endpoints must use POST, not {}", endpoint.method));
+            }
+            EndpointKind::Process(backend) => {
+                // A zero timeout would make every call fail at once
+                if backend.timeout_seconds == 0 {
+                    return Err("Process backend timeout must be at least one second".to_string());
+                }
+                ProcessId::from_str(&backend.process_id)
+                    .map_err(|e| format!("Invalid process id '{}': {}", backend.process_id, e))?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Upstream HTTP timeout for synchronous calls, in seconds
+pub const DEFAULT_UPSTREAM_TIMEOUT_SECS: u64 = 30;
+
+/// Execute a registered provider: its pipeline for composite providers, its endpoint otherwise
+pub async fn invoke_provider(
+    provider: &RegisteredProvider,
+    dynamic_args: &Vec<(String, String)>,
+    caller: &CallerContext,
+    token_cache: &mut HashMap<String, CachedAccessToken>,
+    remote_steps: RemoteSteps,
+    timeout_seconds: u64,
+    sandbox: SandboxMode,
+) -> Result<String, String> {
+    match &provider.pipeline {
+        Some(pipeline) => {
+            run_pipeline(
+                provider,
+                pipeline,
+                dynamic_args,
+                caller,
+                token_cache,
+                remote_steps,
+                timeout_seconds,
+                sandbox,
+            )
+            .await
+        }
+        None => {
+            call_provider(
+                provider.provider_name.clone(),
+                provider.endpoint.clone(),
+                dynamic_args,
+                caller,
+                token_cache,
+                timeout_seconds,
+                sandbox,
+            )
+            .await
+        }
+    }
+}
+
 // New curl-based provider call function
 pub async fn call_provider(
     provider_id_for_log: String,
     endpoint_def: EndpointDefinition,
     dynamic_args: &Vec<(String, String)>,
-    source: String,
+    caller: &CallerContext,
+    token_cache: &mut HashMap<String, CachedAccessToken>,
+    timeout_seconds: u64,
+    sandbox: SandboxMode,
 ) -> Result<String, String> {
     debug!(
         "Calling provider via curl template: {}, method: {}",
@@ -436,6 +569,20 @@ pub async fn call_provider(
 
     let args_map: HashMap<String, String> = dynamic_args.iter().cloned().collect();
 
+    // Process backends are local Requests, not HTTP calls
+    if let EndpointKind::Process(backend) = &endpoint_def.kind {
+        return call_process_backend(&provider_id_for_log, &endpoint_def, backend, &args_map).await;
+    }
+    // Datasets are served straight from VFS
+    if let EndpointKind::Dataset(source) = &endpoint_def.kind {
+        debug!("Serving dataset '{}' for provider {}", source.file_name, provider_id_for_log);
+        return query_dataset(source, dynamic_args);
+    }
+    if let EndpointKind::Sql(source) = &endpoint_def.kind {
+        debug!("Querying database '{}' for provider {}", source.database, provider_id_for_log);
+        return query_sql(source, dynamic_args).await;
+    }
+
     // Start with original headers from the curl template
     let mut http_headers = endpoint_def.get_original_headers_map();
 
@@ -473,8 +620,9 @@ pub async fn call_provider(
 
         match param_def.location.as_str() {
             "path" => {
-                // Replace path parameters in URL template
-                final_url = final_url.replace(&format!("{{{}}}", param_def.parameter_name), value);
+                // Replace path parameters in URL template, percent-encoded so they stay in their segment
+                let encoded = encode_path_parameter(&param_def.parameter_name, value)?;
+                final_url = final_url.replace(&format!("{{{}}}", param_def.parameter_name), &encoded);
             }
             "query" => {
                 // Extract the original query parameter name from JSON pointer
@@ -527,6 +675,11 @@ pub async fn call_provider(
         }
     }
 
+    // GraphQL: the body is always {query, operationName, variables}, built from typed variables
+    if let EndpointKind::GraphQl(operation) = &endpoint_def.kind {
+        body_json = Some(build_graphql_body(operation, &args_map)?);
+    }
+
     // Add query parameters to URL if any
     if !query_params.is_empty() {
         let query_string: String = query_params
@@ -542,8 +695,13 @@ pub async fn call_provider(
         };
     }
 
-    // Add source node ID header
-    http_headers.insert("X-Insecure-HPN-Client-Node-Id".to_string(), source);
+    // Attach a bearer token obtained through the endpoint's upstream auth, if any.
+    // Replays never reach the token endpoint, and fixtures don't depend on headers.
+    let uses_upstream_auth = endpoint_def.upstream_auth.is_some() && sandbox != SandboxMode::Replay;
+    if uses_upstream_auth {
+        let token = get_access_token(&provider_id_for_log, &endpoint_def, token_cache, false).await?;
+        http_headers.insert("Authorization".to_string(), format!("Bearer {}", token));
+    }
 
     debug!("Final URL: {}", final_url);
     debug!("Final headers: {:?}", http_headers);
@@ -560,6 +718,7 @@ pub async fn call_provider(
 
     // Parse URL
     let url = Url::parse(&final_url).map_err(|e| format!("Invalid URL: {}", e))?;
+    check_upstream_url(&url, &endpoint_def)?;
 
     // Convert method string to HyperwareHttpMethod
     let http_method = match endpoint_def.method.to_uppercase().as_str() {
@@ -571,8 +730,17 @@ pub async fn call_provider(
         _ => return Err(format!("Unsupported HTTP method: {}", endpoint_def.method)),
     };
 
+    // Signed statement of who the call is for, verifiable by the upstream
+    http_headers.insert(
+        ATTESTATION_HEADER.to_string(),
+        attestation_header(&provider_id_for_log, caller, &body_bytes)?,
+    );
+
+    // Signers run last so they cover the exact method, URL, headers and body being sent
+    let method_str = endpoint_def.method.to_uppercase();
+    apply_request_signers(&endpoint_def.request_signers, &method_str, &url, &mut http_headers, &body_bytes)?;
+
     // Make the HTTP request
-    let timeout: u64 = 30;
     let start_time = std::time::Instant::now();
     // Log HTTP request details (no sensitive data)
     debug!(
@@ -580,10 +748,43 @@ pub async fn call_provider(
         provider_id_for_log,
         endpoint_def.method,
         url.host_str().unwrap_or("unknown"),
-        timeout,
+        timeout_seconds,
         body_bytes.len()
     );
-    match send_async_http_request(http_method, url, Some(http_headers), timeout, body_bytes).await {
+    let mut response_result = send_upstream(
+        sandbox,
+        &provider_id_for_log,
+        http_method.clone(),
+        url.clone(),
+        http_headers.clone(),
+        timeout_seconds,
+        body_bytes.clone(),
+    )
+    .await;
+
+    // A 401 means the cached token was revoked or expired early: refresh once and retry
+    let unauthorized = matches!(&response_result, Ok(response) if response.status() == StatusCode::UNAUTHORIZED);
+    if uses_upstream_auth && unauthorized {
+        warn!(
+            "Upstream returned 401 for provider '{}', refreshing access token and retrying",
+            provider_id_for_log
+        );
+        let token = get_access_token(&provider_id_for_log, &endpoint_def, token_cache, true).await?;
+        http_headers.insert("Authorization".to_string(), format!("Bearer {}", token));
+        apply_request_signers(&endpoint_def.request_signers, &method_str, &url, &mut http_headers, &body_bytes)?;
+        response_result = send_upstream(
+            sandbox,
+            &provider_id_for_log,
+            http_method,
+            url,
+            http_headers,
+            timeout_seconds,
+            body_bytes,
+        )
+        .await;
+    }
+
+    match response_result {
         Ok(response) => {
             let elapsed = start_time.elapsed();
             let status = response.status();
@@ -601,6 +802,18 @@ pub async fn call_provider(
             );
 
             if status.is_success() {
+                // GraphQL servers report failures as 200 responses with an `errors` array
+                if let EndpointKind::GraphQl(_) = &endpoint_def.kind {
+                    if let Some(messages) = graphql_errors(&body_string) {
+                        error!(
+                            "http_request_failed: provider={}, status={}, duration_ms={}, error_type=graphql_errors",
+                            provider_id_for_log,
+                            status,
+                            elapsed.as_millis()
+                        );
+                        return Err(format!("Provider returned GraphQL errors: {}", messages));
+                    }
+                }
                 Ok(body_string)
             } else {
                 // Error tracking log - HTTP error status
@@ -630,7 +843,7 @@ pub async fn call_provider(
 }
 
 // Helper function to update JSON value using JSON pointer
-fn update_json_value_by_pointer(
+pub(crate) fn update_json_value_by_pointer(
     json: &mut serde_json::Value,
     pointer: &str,
     new_value: &str,
@@ -705,7 +918,7 @@ fn update_json_value_by_pointer(
 }
 
 // Helper function to parse parameter values intelligently
-fn parse_parameter_value(value: &str) -> serde_json::Value {
+pub(crate) fn parse_parameter_value(value: &str) -> serde_json::Value {
     // Check if the value is already a valid JSON value
     if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(value) {
         // Successfully parsed as JSON, return the parsed value
@@ -729,29 +942,57 @@ pub async fn validate_transaction_payment(
     mcp_request: &ProviderCall,
     state: &mut super::HypergridProviderState, // Now mutable
     source_node_id: String,                    // Pass source node string directly
+) -> Result<(), String> {
+    let price = state
+        .registered_providers
+        .iter()
+        .find(|p| p.provider_name == mcp_request.provider_name)
+        .map(|p| p.price)
+        .unwrap_or(0.0);
+
+    validate_usdc_payment(
+        &mcp_request.provider_name,
+        mcp_request.payment_tx_hash.as_ref(),
+        price,
+        state,
+        source_node_id,
+    )
+    .await
+}
+
+/// Validates that `payment_tx_hash` transferred at least `expected_price_usdc` to the
+/// provider's wallet from the requester's Hypermap TBA, then marks the hash as spent.
+///
+/// Shared by per-call payments and access pass purchases.
+pub async fn validate_usdc_payment(
+    provider_name: &str,
+    payment_tx_hash: Option<&String>,
+    expected_price_usdc: f64,
+    state: &mut super::HypergridProviderState,
+    source_node_id: String,
 ) -> Result<(), String> {
     // Usage tracking log - payment validation started
     debug!(
         "payment_validation_started: provider={}, source_node={}, has_tx_hash={}",
-        mcp_request.provider_name,
+        provider_name,
         source_node_id,
-        mcp_request.payment_tx_hash.is_some()
+        payment_tx_hash.is_some()
     );
     // --- 0. Check if provider exists at all ---
     if !state
         .registered_providers
         .iter()
-        .any(|p| p.provider_name == mcp_request.provider_name)
+        .any(|p| p.provider_name == provider_name)
     {
         // we double-check for safety, but this validation already happened in the top-level function (call_provider)
-        return Err(format!("Provider '{}' not found. This should never happen, contact Hyperware Discord for help.", mcp_request.provider_name));
+        return Err(format!("Provider '{}' not found. This should never happen, contact Hyperware Discord for help.", provider_name));
     }
 
     // --- 1. Transaction Hash and Initial Validation ---
-    let tx_hash_str_ref = mcp_request.payment_tx_hash.as_ref().ok_or_else(|| {
+    let tx_hash_str_ref = payment_tx_hash.ok_or_else(|| {
         format!(
             "No payment transaction hash provided for provider call to '{}'. Please make sure to provide a valid transaction hash.",
-            mcp_request.provider_name
+            provider_name
         )
     })?;
 
@@ -763,7 +1004,7 @@ pub async fn validate_transaction_payment(
         );
         error!(
             "Duplicate transaction hash used: provider={}, tx_hash={}, source_node={}",
-            mcp_request.provider_name, tx_hash_str_ref, source_node_id
+            provider_name, tx_hash_str_ref, source_node_id
         );
         return Err(error_msg);
     }
@@ -778,7 +1019,7 @@ pub async fn validate_transaction_payment(
             );
             error!(
                 "Failed to fetch transaction receipt: provider={}, tx_hash={}, source_node={}, error={:?}",
-                mcp_request.provider_name, tx_hash_str_ref, source_node_id, e
+                provider_name, tx_hash_str_ref, source_node_id, e
             );
             return Err(error_msg);
         }
@@ -811,11 +1052,11 @@ pub async fn validate_transaction_payment(
     let registered_provider = state
         .registered_providers
         .iter()
-        .find(|p| p.provider_name == mcp_request.provider_name)
+        .find(|p| p.provider_name == provider_name)
         .ok_or_else(|| {
             format!(
                 "Provider with name '{}' not found for payment validation.",
-                mcp_request.provider_name
+                provider_name
             )
         })?;
 
@@ -831,7 +1072,7 @@ pub async fn validate_transaction_payment(
     })?;
 
     // Convert USDC price to raw units (USDC has 6 decimal places)
-    let service_price_usdc = registered_provider.price * 1_000_000.0; // Convert to base units
+    let service_price_usdc = expected_price_usdc * 1_000_000.0; // Convert to base units
     let service_price_u256 = U256::from(service_price_usdc as u64);
     let hypermap_instance = &state.hypermap;
 
@@ -1007,14 +1248,6 @@ pub async fn validate_transaction_payment(
     // This must be the last step after all validations pass.
 
     state.spent_tx_hashes.push(tx_hash_str_ref.to_string());
-    // Get provider price for revenue tracking
-    let provider_price = state
-        .registered_providers
-        .iter()
-        .find(|p| p.provider_name == mcp_request.provider_name)
-        .map(|p| p.price)
-        .unwrap_or(0.0);
-
     // Success tracking log - payment validation successful
     // Convert raw token amount to human-readable USDC (USDC has 6 decimal places)
     let usdc_decimals = U256::from(1_000_000); // 10^6 for USDC's 6 decimals
@@ -1024,17 +1257,48 @@ pub async fn validate_transaction_payment(
     
     info!(
         "payment_validation_success: provider={}, provider_node={}, source_node={}, tx_hash={}, price_usdc={}, transferred_usdc={}",
-        mcp_request.provider_name,
+        provider_name,
         our().node,
         source_node_id,
         tx_hash_str_ref, // Full transaction hash for complete audit trail
-        provider_price,
+        expected_price_usdc,
         transferred_usdc_display
     );
 
     Ok(())
 }
 
+/// Current Unix time in seconds
+pub fn now_unix_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Use one call from an active access pass held by `node_id` for `provider_name`.
+/// Returns the payment tx hash identifying the pass, or None if the node has no usable pass.
+pub fn consume_access_pass(
+    passes: &mut Vec<AccessPass>,
+    provider_name: &str,
+    node_id: &str,
+) -> Option<String> {
+    let now = now_unix_secs();
+    let pass = passes
+        .iter_mut()
+        .find(|p| p.provider_name == provider_name && p.node_id == node_id && p.is_active(now))?;
+    pass.calls_used += 1;
+    Some(pass.payment_tx_hash.clone())
+}
+
+/// Give back a call taken by `consume_access_pass` when the call it covered failed
+pub fn refund_access_pass(passes: &mut Vec<AccessPass>, pass_tx_hash: &str) {
+    if let Some(pass) = passes.iter_mut().find(|p| p.payment_tx_hash == pass_tx_hash) {
+        pass.calls_used = pass.calls_used.saturating_sub(1);
+        debug!("Refunded a call to the access pass {} of {}", pass_tx_hash, pass.node_id);
+    }
+}
+
 pub fn default_provider() -> hyperware_process_lib::eth::Provider {
     let hypermap_timeout = 60;
     hyperware_process_lib::eth::Provider::new(
@@ -1084,6 +1348,39 @@ pub fn validate_response_status(response: &str) -> Result<(), String> {
 
 
 
+/// Check a response against the provider's declared output schema.
+/// Returns an empty list when no schema is declared.
+pub fn output_schema_violations(provider: &RegisteredProvider, response: &str) -> Vec<String> {
+    let Some(schema_str) = provider.output_schema.as_deref() else {
+        return Vec::new();
+    };
+    match serde_json::from_str::<serde_json::Value>(schema_str) {
+        Ok(schema) => crate::schema::validate_response_against_schema(response, &schema),
+        Err(e) => vec![format!("declared output schema is not valid JSON: {}", e)],
+    }
+}
+
+/// Log responses that drift from the declared output schema. The response is still returned
+/// to the caller; drift is surfaced in logs and analytics only.
+pub fn report_output_schema_drift(provider: &RegisteredProvider, response: &str, source_node_id: &str) {
+    let violations = output_schema_violations(provider, response);
+    if violations.is_empty() {
+        return;
+    }
+    // Info level so the event reaches the remote analytics logger
+    info!(
+        "provider_output_schema_drift: provider={}, provider_node={}, source_node={}, violation_count={}",
+        provider.provider_name,
+        our().node,
+        source_node_id,
+        violations.len()
+    );
+    warn!(
+        "Response from provider '{}' does not match its output schema: {:?}",
+        provider.provider_name, violations
+    );
+}
+
 /// Parse X-PAYMENT header value: base64 decode and deserialize to PaymentPayload
 pub fn parse_x_payment_header(header_value: &str) -> Result<PaymentPayload, String> {
     // Allocate buffer for decoded data (base64 decoding produces smaller output than input)
@@ -1141,6 +1438,71 @@ pub fn build_input_schema(endpoint: &EndpointDefinition) -> InputSchema {
         }
     }
 
+    // GraphQL variables are passed as plain arguments
+    if let EndpointKind::GraphQl(operation) = &endpoint.kind {
+        for variable in &operation.variables {
+            query_params.insert(
+                variable.name.clone(),
+                FieldDef {
+                    r#type: Some(graphql_type_to_field_type(&variable.graphql_type).to_string()),
+                    required: Some(serde_json::Value::Bool(
+                        variable.graphql_type.trim().ends_with('!') && variable.default_value.is_none(),
+                    )),
+                    description: Some(format!("GraphQL variable ${}: {}", variable.name, variable.graphql_type)),
+                    r#enum: None,
+                    properties: None,
+                },
+            );
+        }
+    }
+
+    // Dataset providers take the generic query arguments, all optional
+    if let EndpointKind::Dataset(_) = &endpoint.kind {
+        for (name, description) in DATASET_ARGUMENTS {
+            query_params.insert(
+                name.to_string(),
+                FieldDef {
+                    r#type: Some("string".to_string()),
+                    required: Some(serde_json::Value::Bool(false)),
+                    description: Some(description.to_string()),
+                    r#enum: None,
+                    properties: None,
+                },
+            );
+        }
+    }
+
+    // SQL providers take a template name and its parameters, or free-form SQL when enabled
+    if let EndpointKind::Sql(source) = &endpoint.kind {
+        let string_field = |required: bool, description: String| FieldDef {
+            r#type: Some("string".to_string()),
+            required: Some(serde_json::Value::Bool(required)),
+            description: Some(description),
+            r#enum: None,
+            properties: None,
+        };
+        query_params.insert(
+            SQL_QUERY_ARGUMENT.to_string(),
+            FieldDef {
+                r#enum: Some(source.queries.iter().map(|q| q.name.clone()).collect()),
+                ..string_field(source.free_form.is_none(), "Name of the query to run".to_string())
+            },
+        );
+        for template in &source.queries {
+            for parameter in &template.parameters {
+                query_params.entry(parameter.clone()).or_insert_with(|| {
+                    string_field(false, format!("Parameter of query '{}'", template.name))
+                });
+            }
+        }
+        if source.free_form.is_some() {
+            query_params.insert(
+                SQL_FREE_FORM_ARGUMENT.to_string(),
+                string_field(false, format!("Read-only SELECT, at most {} rows", source.row_limit)),
+            );
+        }
+    }
+
     InputSchema {
         r#type: "http".to_string(),
         method: endpoint.method.clone(),
@@ -1157,45 +1519,65 @@ pub fn build_input_schema(endpoint: &EndpointDefinition) -> InputSchema {
 
 /// Build PaymentRequirements structure from provider and resource URL
 pub fn build_payment_requirements(provider: &RegisteredProvider, resource_url: &str) -> PaymentRequirements {
-    // Convert USDC price to atomic units (6 decimals)
-    let max_amount_atomic = ((provider.price * 1_000_000.0).round() as u64).to_string();
-
     // Build input schema from provider's endpoint definition
-    let input_schema = build_input_schema(&provider.endpoint);
+    let mut input_schema = build_input_schema(&provider.endpoint);
+
+    // Composite providers take the arguments their pipeline reads from the call
+    if let Some(pipeline) = &provider.pipeline {
+        let query_params = input_schema.query_params.get_or_insert_with(HashMap::new);
+        for name in pipeline_call_arguments(pipeline) {
+            query_params.insert(
+                name.clone(),
+                FieldDef {
+                    r#type: Some("string".to_string()),
+                    required: Some(serde_json::Value::Bool(true)),
+                    description: Some(format!("Pipeline argument: {}", name)),
+                    r#enum: None,
+                    properties: None,
+                },
+            );
+        }
+    }
+
+    // Publish the provider's declared output schema, falling back to a generic object
+    let output = provider
+        .output_schema
+        .as_deref()
+        .and_then(|schema| serde_json::from_str::<serde_json::Value>(schema).ok())
+        .unwrap_or_else(|| serde_json::json!({
+            "type": "object",
+            "description": "Response from the provider's API endpoint"
+        }));
 
     // Create output schema for x402scan registry compliance
     let output_schema = OutputSchema {
         input: input_schema,
-        output: Some(serde_json::json!({
-            "type": "object",
-            "description": "Response from the provider's API endpoint"
-        })),
+        output: Some(output),
     };
 
-    let accepted_payment = AcceptedPayment {
-        scheme: "exact".to_string(),
-        network: X402_PAYMENT_NETWORK.to_string(),
-        max_amount_required: max_amount_atomic,
-        resource: resource_url.to_string(),
-        description: provider.description.clone(),
-        mime_type: "application/json".to_string(),
-        pay_to: provider.registered_provider_wallet.clone(),
-        max_timeout_seconds: 60,
-        asset: if X402_PAYMENT_NETWORK == "base-sepolia" {
-            USDC_SEPOLIA_ADDRESS.to_string()
-        } else {
-            USDC_BASE_ADDRESS.to_string()
-        },
-        output_schema: Some(output_schema),
-        extra: Some(serde_json::json!({
-            "name": USDC_EIP712_NAME,
-            "version": USDC_EIP712_VERSION
-        })),
-    };
+    let accepts = payment_options(provider)
+        .iter()
+        .map(|option| AcceptedPayment {
+            scheme: X402_SCHEME.to_string(),
+            network: option.network.clone(),
+            max_amount_required: option_amount(provider, option),
+            resource: resource_url.to_string(),
+            description: provider.description.clone(),
+            mime_type: "application/json".to_string(),
+            pay_to: option_pay_to(provider, option),
+            max_timeout_seconds: max_timeout_secs(provider),
+            asset: option.asset.clone(),
+            output_schema: Some(output_schema.clone()),
+            extra: Some(serde_json::json!({
+                "name": option.asset_name,
+                "version": option.asset_version
+            })),
+        })
+        .collect();
 
     PaymentRequirements {
         protocol_version: 1,
-        accepts: Some(vec![accepted_payment]),
+        accepts: Some(accepts),
         error: Some("".to_string()),  // Empty string for no error (x402 clients expect this field)
         payer: None,
     }
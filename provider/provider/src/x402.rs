@@ -0,0 +1,667 @@
+use crate::constants::{
+    USDC_BASE_ADDRESS, USDC_EIP712_NAME, USDC_EIP712_VERSION, USDC_SEPOLIA_ADDRESS,
+    X402_FACILITATOR_BASE_URL, X402_PAYMENT_NETWORK,
+};
+use crate::util::{build_payment_requirements, send_async_http_request};
+use crate::{
+    AcceptedPayment, DiscoveredResource, DiscoveryMetadata, FacilitatorVerifyRequest,
+    PaymentPayload, RegisteredProvider, SettleResponse, VerifyResponse, X402PaymentOption,
+    X402Settlement, CHAIN_ID,
+};
+use alloy_primitives::{Bytes, PrimitiveSignature, B256, U256};
+use alloy_sol_types::{sol, Eip712Domain, SolCall, SolStruct};
+use base64ct::{Base64, Encoding};
+use hyperware_process_lib::{
+    eth::{
+        Address as EthAddress, BlockId, EthAction, EthResponse, TransactionInput,
+        TransactionReceipt, TransactionRequest, TxHash,
+    },
+    hyperapp::{send, sleep},
+    http::Method as HyperwareHttpMethod,
+    logging::{error, info, warn},
+    signer::{LocalSigner, Signer, TransactionData},
+    vfs::{create_file, open_file},
+    Request,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+sol! {
+    // EIP-3009, signed by the payer as EIP-712 typed data
+    struct TransferWithAuthorization {
+        address from;
+        address to;
+        uint256 value;
+        uint256 validAfter;
+        uint256 validBefore;
+        bytes32 nonce;
+    }
+
+    function balanceOf(address account) external view returns (uint256 balance);
+    function authorizationState(address authorizer, bytes32 nonce) external view returns (bool used);
+    function transferWithAuthorization(
+        address from,
+        address to,
+        uint256 value,
+        uint256 validAfter,
+        uint256 validBefore,
+        bytes32 nonce,
+        uint8 v,
+        bytes32 r,
+        bytes32 s
+    ) external;
+}
+
+/// The only scheme this provider settles
+pub const X402_SCHEME: &str = "exact";
+
+/// Path of the discovery list, bound next to /xfour
+pub const X402_DISCOVERY_PATH: &str = "/.well-known/x402";
+
+const RPC_TIMEOUT_SECS: u64 = 30;
+/// Bound on each of the two chain reads in verification, which run before the upstream call
+const VERIFY_RPC_TIMEOUT_SECS: u64 = 10;
+
+/// Longest wait for a self-settled transaction to be mined
+const SETTLEMENT_RECEIPT_TIMEOUT_SECS: u64 = 60;
+const RECEIPT_POLL_MS: u64 = 1000;
+
+/// Time payers get to sign and send an authorization, on top of the settlement margin
+const PAYER_WINDOW_SECS: u64 = 60;
+
+/// How long an authorization must stay valid from the start of verification: its chain reads,
+/// the upstream call, then submitting the settlement and waiting for it to be mined
+pub fn settlement_margin_secs(provider: &RegisteredProvider) -> u64 {
+    2 * VERIFY_RPC_TIMEOUT_SECS
+        + provider.upstream_timeout_seconds()
+        + RPC_TIMEOUT_SECS
+        + SETTLEMENT_RECEIPT_TIMEOUT_SECS
+}
+
+/// `maxTimeoutSeconds` advertised in the requirements, so payers pick a `validBefore` that
+/// verification accepts
+pub fn max_timeout_secs(provider: &RegisteredProvider) -> u64 {
+    settlement_margin_secs(provider) + PAYER_WINDOW_SECS
+}
+
+/// Hot wallet submitting self-settled payments and paying their gas. Its key lives in a VFS
+/// file of the provider drive, never in the persisted process state.
+#[derive(Clone)]
+pub struct RelayerWallet {
+    pub address: String,
+    pub private_key: String, // Hex, never exported or logged
+}
+
+impl std::fmt::Debug for RelayerWallet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RelayerWallet")
+            .field("address", &self.address)
+            .finish_non_exhaustive()
+    }
+}
+
+fn relayer_wallet_from_private_key(private_key: &str) -> Result<RelayerWallet, String> {
+    let signer = LocalSigner::from_private_key(private_key.trim(), CHAIN_ID)
+        .map_err(|e| format!("Invalid private key: {:?}", e))?;
+    Ok(RelayerWallet {
+        address: signer.address().to_string(),
+        private_key: private_key.trim().to_string(),
+    })
+}
+
+fn relayer_key_file(drive_path: &str) -> String {
+    format!("{}/x402_relayer.key", drive_path)
+}
+
+/// Relayer wallet saved on the provider drive, if one was set
+pub fn load_relayer_wallet(drive_path: &str) -> Result<Option<RelayerWallet>, String> {
+    let file = match open_file(&relayer_key_file(drive_path), false, None) {
+        Ok(file) => file,
+        Err(_) => return Ok(None),
+    };
+    let bytes = file.read().map_err(|e| e.to_string())?;
+    relayer_wallet_from_private_key(&String::from_utf8_lossy(&bytes)).map(Some)
+}
+
+/// Check and store the relayer key on the provider drive
+pub fn save_relayer_wallet(drive_path: &str, private_key: &str) -> Result<RelayerWallet, String> {
+    let wallet = relayer_wallet_from_private_key(private_key)?;
+    let file = create_file(&relayer_key_file(drive_path), None).map_err(|e| e.to_string())?;
+    file.write(wallet.private_key.as_bytes()).map_err(|e| e.to_string())?;
+    Ok(wallet)
+}
+
+/// Chain of an x402 network name
+pub fn network_chain_id(network: &str) -> Option<u64> {
+    match network {
+        "base" => Some(8453),
+        "base-sepolia" => Some(84532),
+        "polygon" => Some(137),
+        "polygon-amoy" => Some(80002),
+        "avalanche" => Some(43114),
+        "avalanche-fuji" => Some(43113),
+        _ => None,
+    }
+}
+
+/// USDC on the build's payment network, paid to the provider wallet at the provider price
+fn default_payment_option() -> X402PaymentOption {
+    X402PaymentOption {
+        network: X402_PAYMENT_NETWORK.to_string(),
+        asset: if X402_PAYMENT_NETWORK == "base-sepolia" {
+            USDC_SEPOLIA_ADDRESS.to_string()
+        } else {
+            USDC_BASE_ADDRESS.to_string()
+        },
+        asset_name: USDC_EIP712_NAME.to_string(),
+        asset_version: USDC_EIP712_VERSION.to_string(),
+        decimals: 6,
+        pay_to: None,
+        price: None,
+    }
+}
+
+/// Options advertised in `accepts`, in the provider's order
+pub fn payment_options(provider: &RegisteredProvider) -> Vec<X402PaymentOption> {
+    if provider.x402_accepts.is_empty() {
+        vec![default_payment_option()]
+    } else {
+        provider.x402_accepts.clone()
+    }
+}
+
+pub fn option_pay_to(provider: &RegisteredProvider, option: &X402PaymentOption) -> String {
+    option
+        .pay_to
+        .clone()
+        .unwrap_or_else(|| provider.registered_provider_wallet.clone())
+}
+
+/// Price of `option` in the asset's atomic units
+pub fn option_amount(provider: &RegisteredProvider, option: &X402PaymentOption) -> String {
+    let price = option.price.unwrap_or(provider.price);
+    ((price * 10f64.powi(option.decimals as i32)).round() as u128).to_string()
+}
+
+pub fn validate_payment_options(provider: &RegisteredProvider) -> Result<(), String> {
+    let mut seen: Vec<(String, String, String)> = Vec::new();
+    for option in &provider.x402_accepts {
+        if network_chain_id(&option.network).is_none() {
+            return Err(format!("Unsupported x402 network: {}", option.network));
+        }
+        EthAddress::from_str(&option.asset)
+            .map_err(|_| format!("Invalid x402 asset address: {}", option.asset))?;
+        if option.asset_name.is_empty() || option.asset_version.is_empty() {
+            return Err(format!(
+                "x402 asset {} needs its EIP-712 domain name and version",
+                option.asset
+            ));
+        }
+        if option.decimals > 18 {
+            return Err(format!("Invalid decimals for x402 asset {}", option.asset));
+        }
+        if let Some(pay_to) = &option.pay_to {
+            EthAddress::from_str(pay_to)
+                .map_err(|_| format!("Invalid x402 pay_to address: {}", pay_to))?;
+        }
+        if let Some(price) = option.price {
+            if !(price >= 0.0 && price.is_finite()) {
+                return Err(format!("Invalid x402 price for asset {}", option.asset));
+            }
+        }
+
+        // A payment only names its network, recipient and amount, so those must single out an option
+        let key = (
+            option.network.clone(),
+            option_pay_to(provider, option).to_lowercase(),
+            option_amount(provider, option),
+        );
+        if seen.contains(&key) {
+            return Err(format!(
+                "x402 payment options on {} share a recipient and amount; give them distinct pay_to addresses or prices",
+                option.network
+            ));
+        }
+        seen.push(key);
+    }
+    Ok(())
+}
+
+/// Discovery entry of `provider`, whose /xfour lives under `base_url` (the process's URL)
+pub fn discovered_resource(provider: &RegisteredProvider, base_url: &str) -> DiscoveredResource {
+    let resource = format!(
+        "{}/xfour?providername={}",
+        base_url,
+        url::form_urlencoded::byte_serialize(provider.provider_name.as_bytes()).collect::<String>()
+    );
+    let accepts = build_payment_requirements(provider, &resource)
+        .accepts
+        .unwrap_or_default();
+    let input_schema = accepts
+        .first()
+        .and_then(|method| method.output_schema.as_ref())
+        .map(|schema| schema.input.clone());
+    DiscoveredResource {
+        resource,
+        r#type: "http".to_string(),
+        protocol_version: 1,
+        accepts,
+        metadata: DiscoveryMetadata {
+            provider_name: provider.provider_name.clone(),
+            description: provider.description.clone(),
+            instructions: provider.instructions.clone(),
+            price: provider.price,
+            input_schema,
+        },
+    }
+}
+
+/// The advertised option a payment was made for: same scheme and network, paid to its
+/// address for its exact amount
+pub fn match_payment_method<'a>(
+    accepts: &'a [AcceptedPayment],
+    payment: &PaymentPayload,
+) -> Option<&'a AcceptedPayment> {
+    let authorization = &payment.payload.authorization;
+    accepts.iter().find(|method| {
+        method.scheme == payment.scheme
+            && method.network == payment.network
+            && method.pay_to.eq_ignore_ascii_case(&authorization.to)
+            && method.max_amount_required == authorization.value
+    })
+}
+
+/// Key of an authorization nonce, which is only unique per token and payer
+pub fn nonce_key(method: &AcceptedPayment, payment: &PaymentPayload) -> String {
+    let authorization = &payment.payload.authorization;
+    format!("{}:{}:{}", method.asset, authorization.from, authorization.nonce).to_lowercase()
+}
+
+/// Check an `exact` payment against the option it was matched to, without a facilitator:
+/// the EIP-712 signature, recipient, amount, validity window, nonce and payer balance.
+/// `pending_nonces` holds verified authorizations that are not settled yet.
+/// Err means the check could not be completed, e.g. the chain RPC is unreachable.
+pub async fn verify_exact_payment(
+    payment: &PaymentPayload,
+    method: &AcceptedPayment,
+    pending_nonces: &HashSet<String>,
+    settlement_margin_secs: u64,
+    now: u64,
+) -> Result<VerifyResponse, String> {
+    let payer = payment.payload.authorization.from.clone();
+    let invalid = |reason: String| VerifyResponse {
+        is_valid: false,
+        payer: payer.clone(),
+        invalid_reason: Some(reason),
+    };
+
+    let authorization = match parse_authorization(payment) {
+        Ok(authorization) => authorization,
+        Err(reason) => return Ok(invalid(reason)),
+    };
+    let chain_id = network_chain_id(&method.network)
+        .ok_or_else(|| format!("Unsupported x402 network: {}", method.network))?;
+    let asset = EthAddress::from_str(&method.asset)
+        .map_err(|_| format!("Invalid x402 asset address: {}", method.asset))?;
+
+    if payment.scheme != X402_SCHEME {
+        return Ok(invalid(format!("unsupported_scheme: {}", payment.scheme)));
+    }
+    if EthAddress::from_str(&method.pay_to).ok() != Some(authorization.to) {
+        return Ok(invalid("invalid_exact_evm_payload_recipient_mismatch".to_string()));
+    }
+    if U256::from_str_radix(&method.max_amount_required, 10).ok() != Some(authorization.value) {
+        return Ok(invalid("invalid_exact_evm_payload_authorization_value".to_string()));
+    }
+    if authorization.validAfter >= U256::from(now) {
+        return Ok(invalid("invalid_exact_evm_payload_authorization_valid_after".to_string()));
+    }
+    if authorization.validBefore <= U256::from(now + settlement_margin_secs) {
+        return Ok(invalid("invalid_exact_evm_payload_authorization_valid_before".to_string()));
+    }
+
+    // Signed against the token's own EIP-712 domain
+    let extra = method.extra.as_ref();
+    let domain_field = |field: &str| {
+        extra
+            .and_then(|extra| extra.get(field))
+            .and_then(|value| value.as_str())
+            .map(|value| Cow::Owned(value.to_string()))
+    };
+    let domain = Eip712Domain::new(
+        domain_field("name"),
+        domain_field("version"),
+        Some(U256::from(chain_id)),
+        Some(asset),
+        None,
+    );
+    let signing_hash = authorization.eip712_signing_hash(&domain);
+    let signature = hex::decode(payment.payload.signature.trim_start_matches("0x"))
+        .ok()
+        .and_then(|bytes| PrimitiveSignature::try_from(bytes.as_slice()).ok());
+    let signer = signature.and_then(|s| s.recover_address_from_prehash(&signing_hash).ok());
+    if signer != Some(authorization.from) {
+        return Ok(invalid("invalid_exact_evm_payload_signature".to_string()));
+    }
+
+    if pending_nonces.contains(&nonce_key(method, payment)) {
+        return Ok(invalid("invalid_exact_evm_payload_authorization_nonce_in_use".to_string()));
+    }
+    let used = call_view(
+        chain_id,
+        asset,
+        authorizationStateCall {
+            authorizer: authorization.from,
+            nonce: authorization.nonce,
+        },
+        VERIFY_RPC_TIMEOUT_SECS,
+    )
+    .await?;
+    if used.used {
+        return Ok(invalid("invalid_exact_evm_payload_authorization_nonce_used".to_string()));
+    }
+    let balance = call_view(
+        chain_id,
+        asset,
+        balanceOfCall { account: authorization.from },
+        VERIFY_RPC_TIMEOUT_SECS,
+    )
+    .await?;
+    if balance.balance < authorization.value {
+        return Ok(invalid("insufficient_funds".to_string()));
+    }
+
+    Ok(VerifyResponse {
+        is_valid: true,
+        payer: authorization.from.to_string(),
+        invalid_reason: None,
+    })
+}
+
+fn parse_authorization(payment: &PaymentPayload) -> Result<TransferWithAuthorization, String> {
+    let authorization = &payment.payload.authorization;
+    let address = |value: &str| {
+        EthAddress::from_str(value).map_err(|_| format!("invalid_exact_evm_payload_address: {}", value))
+    };
+    let amount = |value: &str| {
+        U256::from_str_radix(value, 10).map_err(|_| format!("invalid_exact_evm_payload_number: {}", value))
+    };
+    Ok(TransferWithAuthorization {
+        from: address(&authorization.from)?,
+        to: address(&authorization.to)?,
+        value: amount(&authorization.value)?,
+        validAfter: amount(&authorization.valid_after)?,
+        validBefore: amount(&authorization.valid_before)?,
+        nonce: B256::from_str(&authorization.nonce)
+            .map_err(|_| format!("invalid_exact_evm_payload_nonce: {}", authorization.nonce))?,
+    })
+}
+
+/// Send a JSON-RPC request through the eth module without blocking the process, so other
+/// calls keep being served while it is in flight
+async fn rpc_request<T: DeserializeOwned>(
+    chain_id: u64,
+    method: &str,
+    params: serde_json::Value,
+    timeout_secs: u64,
+) -> Result<T, String> {
+    let action = EthAction::Request {
+        chain_id,
+        method: method.to_string(),
+        params,
+    };
+    let body = serde_json::to_vec(&action)
+        .map_err(|e| format!("Failed to serialize {}: {}", method, e))?;
+    let request = Request::to(("our", "eth", "distro", "sys"))
+        .expects_response(timeout_secs)
+        .body(body);
+    match send::<EthResponse>(request).await {
+        Ok(EthResponse::Response(value)) => serde_json::from_value(value)
+            .map_err(|e| format!("Unexpected {} reply: {}", method, e)),
+        Ok(EthResponse::Err(e)) => Err(format!("{} failed: {:?}", method, e)),
+        Ok(_) => Err(format!("Unexpected {} reply", method)),
+        Err(e) => Err(format!("{} failed: {:?}", method, e)),
+    }
+}
+
+fn to_params<P: Serialize>(params: P) -> serde_json::Value {
+    serde_json::to_value(params).unwrap_or_default()
+}
+
+async fn call_view<C: SolCall>(
+    chain_id: u64,
+    contract: EthAddress,
+    call: C,
+    timeout_secs: u64,
+) -> Result<C::Return, String> {
+    let tx = TransactionRequest::default()
+        .to(contract)
+        .input(TransactionInput::new(call.abi_encode().into()));
+    let params = to_params((tx, BlockId::latest()));
+    let output: Bytes = rpc_request(chain_id, "eth_call", params, timeout_secs)
+        .await
+        .map_err(|e| format!("Payment check over RPC failed: {}", e))?;
+    C::abi_decode_returns(&output, true)
+        .map_err(|e| format!("Unexpected reply from token contract: {}", e))
+}
+
+/// Why a self-settlement did not go through
+enum SelfSettleError {
+    /// Nothing was mined and the authorization is still unused, so the facilitator can settle it
+    NotSettled(String),
+    /// The transaction was mined and reverted
+    Reverted { transaction: String },
+}
+
+/// Settle a verified payment after the upstream call, from the relayer wallet in `SelfSettle`
+/// mode and through the facilitator otherwise. Self-settlement that fails before anything is
+/// mined falls back to the facilitator; the authorization nonce keeps it from paying twice.
+pub async fn settle_payment(
+    mode: X402Settlement,
+    relayer: Option<&RelayerWallet>,
+    request: &FacilitatorVerifyRequest,
+    payer: &str,
+) -> SettleResponse {
+    let network = request.payment_payload.network.clone();
+    if mode == X402Settlement::SelfSettle {
+        let Some(relayer) = relayer else {
+            warn!("x402_self_settle_fallback: payer={}, reason=no relayer wallet configured", payer);
+            return facilitator_settle(request, payer).await;
+        };
+        match self_settle(&request.payment_payload, &request.payment_requirements, relayer).await {
+            Ok(transaction) => {
+                info!("x402_self_settled: payer={}, network={}, tx_hash={}", payer, network, transaction);
+                return SettleResponse {
+                    success: true,
+                    payer: payer.to_string(),
+                    transaction: Some(transaction),
+                    network,
+                    error_reason: None,
+                };
+            }
+            Err(SelfSettleError::Reverted { transaction }) => {
+                error!("x402_self_settle_reverted: payer={}, network={}, tx_hash={}", payer, network, transaction);
+                return SettleResponse {
+                    success: false,
+                    payer: payer.to_string(),
+                    transaction: Some(transaction),
+                    network,
+                    error_reason: Some("settlement_transaction_reverted".to_string()),
+                };
+            }
+            Err(SelfSettleError::NotSettled(reason)) => {
+                warn!("x402_self_settle_fallback: payer={}, reason={}", payer, reason);
+            }
+        }
+    }
+    facilitator_settle(request, payer).await
+}
+
+/// Base64 JSON of a settlement, as sent in the `X-PAYMENT-RESPONSE` header
+pub fn encode_settle_response(settle: &SettleResponse) -> String {
+    let json = serde_json::to_vec(settle).unwrap_or_default();
+    let mut buf = vec![0u8; Base64::encoded_len(&json)];
+    Base64::encode(&json, &mut buf).map(str::to_string).unwrap_or_default()
+}
+
+async fn facilitator_settle(request: &FacilitatorVerifyRequest, payer: &str) -> SettleResponse {
+    let failed = |reason: String| SettleResponse {
+        success: false,
+        payer: payer.to_string(),
+        transaction: None,
+        network: request.payment_payload.network.clone(),
+        error_reason: Some(reason),
+    };
+    let body = match serde_json::to_vec(request) {
+        Ok(body) => body,
+        Err(e) => return failed(format!("Failed to serialize settle request: {}", e)),
+    };
+    let url = match url::Url::parse(&format!("{}/settle", X402_FACILITATOR_BASE_URL)) {
+        Ok(url) => url,
+        Err(e) => return failed(format!("Invalid facilitator URL: {}", e)),
+    };
+    let headers = HashMap::from([("Content-Type".to_string(), "application/json".to_string())]);
+
+    match send_async_http_request(HyperwareHttpMethod::POST, url, Some(headers), 30, body).await {
+        Ok(http_response) => {
+            info!("Facilitator /settle response: {:?}", http_response.status());
+            serde_json::from_slice(http_response.body()).unwrap_or_else(|e| {
+                error!("Failed to parse settlement response: {}", e);
+                failed(format!("Failed to parse settlement response: {}", e))
+            })
+        }
+        Err(e) => {
+            error!("Facilitator /settle request failed: {:?}", e);
+            failed(format!("Settlement service error: {:?}", e))
+        }
+    }
+}
+
+/// Submit `transferWithAuthorization` from the relayer wallet and wait for it to be mined,
+/// returning the tx hash. The relayer pays the gas.
+async fn self_settle(
+    payment: &PaymentPayload,
+    method: &AcceptedPayment,
+    relayer: &RelayerWallet,
+) -> Result<String, SelfSettleError> {
+    let not_settled = SelfSettleError::NotSettled;
+    let authorization = parse_authorization(payment).map_err(not_settled)?;
+    let chain_id = network_chain_id(&method.network)
+        .ok_or_else(|| not_settled(format!("Unsupported x402 network: {}", method.network)))?;
+    let asset = EthAddress::from_str(&method.asset)
+        .map_err(|_| not_settled(format!("Invalid x402 asset address: {}", method.asset)))?;
+    let signature = hex::decode(payment.payload.signature.trim_start_matches("0x"))
+        .ok()
+        .and_then(|bytes| PrimitiveSignature::try_from(bytes.as_slice()).ok())
+        .ok_or_else(|| not_settled("Unreadable authorization signature".to_string()))?;
+    let signer = LocalSigner::from_private_key(&relayer.private_key, chain_id)
+        .map_err(|e| not_settled(format!("Relayer wallet unusable: {:?}", e)))?;
+
+    let call_data = transferWithAuthorizationCall {
+        from: authorization.from,
+        to: authorization.to,
+        value: authorization.value,
+        validAfter: authorization.validAfter,
+        validBefore: authorization.validBefore,
+        nonce: authorization.nonce,
+        v: 27 + signature.v() as u8,
+        r: B256::from(signature.r()),
+        s: B256::from(signature.s()),
+    }
+    .abi_encode();
+
+    let rpc_failed = |step: &str, e: String| not_settled(format!("{} failed: {}", step, e));
+    let estimate_tx = TransactionRequest::default()
+        .from(signer.address())
+        .to(asset)
+        .input(TransactionInput::new(call_data.clone().into()));
+    // Estimation reverts, and nothing is sent, if the token would refuse the authorization
+    let gas_estimate = rpc_request::<U256>(
+        chain_id,
+        "eth_estimateGas",
+        to_params((estimate_tx, BlockId::latest())),
+        RPC_TIMEOUT_SECS,
+    )
+    .await
+    .map_err(|e| rpc_failed("Gas estimation", e))?
+    .to::<u64>();
+    let gas_price =
+        rpc_request::<U256>(chain_id, "eth_gasPrice", serde_json::json!([]), RPC_TIMEOUT_SECS)
+            .await
+            .map_err(|e| rpc_failed("Gas price lookup", e))?
+            .to::<u128>();
+    // Pending, so settlements of concurrent calls don't reuse a nonce
+    let nonce = rpc_request::<U256>(
+        chain_id,
+        "eth_getTransactionCount",
+        to_params((signer.address(), BlockId::pending())),
+        RPC_TIMEOUT_SECS,
+    )
+    .await
+    .map_err(|e| rpc_failed("Relayer nonce lookup", e))?
+    .to::<u64>();
+    let raw_transaction = signer
+        .sign_transaction(&TransactionData {
+            to: asset,
+            value: U256::ZERO,
+            data: Some(call_data),
+            nonce,
+            gas_limit: gas_estimate + gas_estimate / 5,
+            gas_price,
+            max_priority_fee: None,
+            chain_id,
+        })
+        .map_err(|e| not_settled(format!("Relayer could not sign: {:?}", e)))?;
+    let tx_hash = rpc_request::<TxHash>(
+        chain_id,
+        "eth_sendRawTransaction",
+        to_params((Bytes::from(raw_transaction),)),
+        RPC_TIMEOUT_SECS,
+    )
+    .await
+    .map_err(|e| rpc_failed("Relayer broadcast", e))?;
+    let transaction = tx_hash.to_string();
+    info!("x402_self_settle_sent: payer={}, tx_hash={}", authorization.from, transaction);
+
+    for _ in 0..SETTLEMENT_RECEIPT_TIMEOUT_SECS * 1000 / RECEIPT_POLL_MS {
+        let receipt = rpc_request::<Option<TransactionReceipt>>(
+            chain_id,
+            "eth_getTransactionReceipt",
+            to_params((tx_hash,)),
+            RPC_TIMEOUT_SECS,
+        )
+        .await;
+        if let Ok(Some(receipt)) = receipt {
+            return if receipt.status() {
+                Ok(transaction)
+            } else {
+                Err(SelfSettleError::Reverted { transaction })
+            };
+        }
+        let _ = sleep(RECEIPT_POLL_MS).await;
+    }
+
+    // Not mined yet. Once the nonce is used the payment went through, whoever mined it;
+    // while it is unused, a facilitator settlement and ours can't both succeed.
+    let state = call_view(
+        chain_id,
+        asset,
+        authorizationStateCall {
+            authorizer: authorization.from,
+            nonce: authorization.nonce,
+        },
+        RPC_TIMEOUT_SECS,
+    )
+    .await
+    .map_err(not_settled)?;
+    if state.used {
+        Ok(transaction)
+    } else {
+        Err(not_settled(format!(
+            "{} not mined within {}s",
+            transaction, SETTLEMENT_RECEIPT_TIMEOUT_SECS
+        )))
+    }
+}
@@ -1,101 +1,1130 @@
-use caller_utils::hpn_provider::{export_state_local_rpc, import_state_local_rpc};
-use caller_utils::{HypergridProviderState, TodoItem};
-// Add this import here, as fail! is expanded in this file
 use crate::hyperware::process::tester::{FailResponse, Response as TesterResponse};
+use crate::provider_api::{
+    api, arguments, backend_provider, echo_provider, failing_provider, local, payment_response,
+    registered_names, signed_x_payment, terminal, unregister, x402_discovery, x_payment, xfour,
+    xfour_with_headers, PROVIDER_WALLET,
+};
+use crate::stand_in::{
+    add_receipt, add_tba, base_url, revert_sent_transactions, sent_transactions, upstream_hits,
+    usdc_payment_receipt,
+};
 
-use serde_json;
+use hyperware_app_common::{send, sleep};
+use hyperware_process_lib::{get_state, hypermap, our, set_state, Request};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+mod provider_api;
+mod stand_in;
 mod tester_lib;
 
+const USDC_BASE_ADDRESS: &str = "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913";
+const USDC_SEPOLIA_ADDRESS: &str = "0x036cbd53842c5426634e7929541ec2318f3dcf7e";
+const EURC_BASE_ADDRESS: &str = "0x60a3e35cc302bfa44cb288bc5a4f316fdb1adb42";
+
+// Hypermap TBA of this node's grid wallet on the stand-in chain
+const OPERATOR_TBA: &str = "0x2222222222222222222222222222222222222222";
+
+// Pays the gas of self-settled x402 payments
+const RELAYER_KEY: &str = "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
+// transferWithAuthorization(address,address,uint256,uint256,uint256,bytes32,uint8,bytes32,bytes32)
+const TRANSFER_WITH_AUTHORIZATION_SELECTOR: &str = "e3ee160e";
+
+// Saved in this process's state by the first run of the suite, see tests.toml
+const RESTART_MARKER: &[u8] = b"persisted-provider-registered";
+const PERSISTED_PROVIDER: &str = "it-persisted";
+
+async fn revision_count(name: &str) -> anyhow::Result<usize> {
+    match api(json!({ "GetProviderRevisions": name })).await? {
+        Ok(Value::Array(revisions)) => Ok(revisions.len()),
+        other => Err(anyhow::anyhow!("GetProviderRevisions failed: {:?}", other)),
+    }
+}
+
+/// Tx hash of a fresh USDC payment of `amount` (atomic units) from this node's grid wallet.
+/// Spent hashes persist with the node home, so every run pays with a new one.
+fn fresh_payment(amount: u64) -> anyhow::Result<String> {
+    let nonce = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_nanos();
+    let tx_hash = format!("0x{:064x}", nonce);
+    add_tba(OPERATOR_TBA, &hypermap::namehash(&format!("grid-wallet.{}", our().node)));
+    add_receipt(&tx_hash, usdc_payment_receipt(&tx_hash, USDC_BASE_ADDRESS, OPERATOR_TBA, PROVIDER_WALLET, amount));
+    Ok(tx_hash)
+}
+
+/// Poll an async job until it succeeds or fails
+async fn finished_job(job_id: &str) -> anyhow::Result<Value> {
+    for _ in 0..50 {
+        match local(json!({ "GetJobResult": { "job_id": job_id, "protocol_version": null } })).await? {
+            Ok(job) if job["status"] == json!("Succeeded") || job["status"] == json!("Failed") => return Ok(job),
+            Ok(_) => {
+                let _ = sleep(200).await;
+            }
+            Err(e) => anyhow::bail!("GetJobResult failed: {}", e),
+        }
+    }
+    anyhow::bail!("job {} did not finish", job_id)
+}
+
 async_test_suite!(
-    "hypergrid-provider-test-template-dot-os-v0",
+    "hypergrid-provider-test-dot-os-v0",
+
+    test_setup_stand_ins: async {
+        stand_in::bind()?;
+
+        // Payments are validated against the stand-in chain instead of Base
+        let rpc_url = format!("ws{}/rpc", base_url().trim_start_matches("http"));
+        let added = send::<Value>(
+            Request::to(("our", "eth", "distro", "sys"))
+                .body(serde_json::to_vec(&json!({
+                    "AddProvider": {
+                        "chain_id": hypermap::HYPERMAP_CHAIN_ID,
+                        "trusted": true,
+                        "provider": { "RpcUrl": { "url": rpc_url, "auth": null } },
+                    }
+                }))?)
+                .expects_response(10),
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("eth AddProvider failed: {:?}", e))?;
+        if added != json!("Ok") {
+            fail!(format!("eth AddProvider answered {}", added));
+        }
+        Ok(())
+    },
+
+    test_register_validate_update_unregister: async {
+        let name = "it-lifecycle";
+        unregister(name).await?;
+        let provider = echo_provider(name, 0.01);
+
+        let validated = api(json!({
+            "ValidateProvider": [provider, arguments(&[("item", "widgets"), ("units", "metric"), ("client", "curl"), ("city", "Paris")])]
+        }))
+        .await?;
+        let validated: Value = match validated {
+            Ok(Value::String(body)) => serde_json::from_str(&body)?,
+            other => {
+                fail!(format!("validation failed: {:?}", other));
+            }
+        };
+        if validated["provider"]["output_schema"].is_null() {
+            fail!("validation did not infer an output schema");
+        }
+        // Registration pins the upstream host
+        if validated["provider"]["endpoint"]["allowed_hosts"] != json!(["localhost"]) {
+            fail!(format!("unexpected allowed_hosts: {}", validated["provider"]["endpoint"]["allowed_hosts"]));
+        }
+
+        if let Err(e) = api(json!({ "RegisterProvider": validated["provider"] })).await? {
+            fail!(format!("registration failed: {}", e));
+        }
+        if !registered_names().await?.contains(&name.to_string()) {
+            fail!("registered provider is not listed");
+        }
+        if api(json!({ "RegisterProvider": validated["provider"] })).await?.is_ok() {
+            fail!("registering the same name twice succeeded");
+        }
+
+        let revisions_before = revision_count(name).await?;
+        if revisions_before != 1 {
+            fail!(format!("expected only the registration revision, found {}", revisions_before));
+        }
+
+        let mut updated = validated["provider"].clone();
+        updated["description"] = json!("Echoes the upstream request, updated");
+        updated["price"] = json!(0.02);
+        let stored = match api(json!({ "UpdateProvider": [name, updated] })).await? {
+            Ok(stored) => stored,
+            Err(e) => {
+                fail!(format!("update failed: {}", e));
+            }
+        };
+        if stored["price"] != json!(0.02) {
+            fail!(format!("update did not apply: {}", stored));
+        }
+        let revisions_after = revision_count(name).await?;
+        if revisions_after != revisions_before + 1 {
+            fail!(format!("expected one new revision, had {} and now {}", revisions_before, revisions_after));
+        }
 
-    test_basic_math: async {
-        if 2 + 2 != 4 {
-            fail!("wrong result");
+        // Rollback restores the validated definition, hosts pinned
+        let restored = match api(json!({
+            "RollbackProvider": [name, 1, arguments(&[("item", "widgets"), ("units", "metric"), ("client", "curl"), ("city", "Paris")])]
+        }))
+        .await?
+        {
+            Ok(restored) => restored,
+            Err(e) => {
+                fail!(format!("rollback failed: {}", e));
+            }
+        };
+        if restored["price"] != json!(0.01) || restored["endpoint"]["allowed_hosts"] != json!(["localhost"]) {
+            fail!(format!("rollback did not restore the validated definition: {}", restored));
+        }
+        if revision_count(name).await? != revisions_after + 1 {
+            fail!("rollback was not recorded as a revision");
+        }
+
+        unregister(name).await?;
+        if registered_names().await?.contains(&name.to_string()) {
+            fail!("unregistered provider is still listed");
+        }
+        if revision_count(name).await? != 0 {
+            fail!("unregistered provider kept its revision history");
         }
         Ok(())
     },
 
-    // Test importing and exporting state locally
-    test_import_export_state: async {
-        let address: Address = ("hypergrid-provider.os", "hypergrid-provider", "hypergrid-provider", "template.os").into();
+    test_call_parameter_substitution: async {
+        let name = "it-substitution";
+        unregister(name).await?;
+        if let Err(e) = api(json!({ "RegisterProvider": echo_provider(name, 0.0) })).await? {
+            fail!(format!("registration failed: {}", e));
+        }
 
-        // 1. Define initial state (dummy data)
-        let initial_state = HypergridProviderState {
-            tasks: vec![
-                TodoItem { id: "1".to_string(), text: "Task 1".to_string(), completed: false },
-                TodoItem { id: "2".to_string(), text: "Task 2".to_string(), completed: true },
-            ],
+        let result = terminal(json!({
+            "TestProvider": {
+                "provider_name": name,
+                "arguments": arguments(&[("item", "gadgets"), ("units", "imperial"), ("client", "hypergrid-test"), ("city", "Lyon")]),
+                "payment_tx_hash": null,
+            }
+        }))
+        .await?;
+        let echo: Value = match result {
+            Ok(Value::String(body)) => serde_json::from_str(&body)?,
+            other => {
+                fail!(format!("TestProvider failed: {:?}", other));
+            }
         };
-        print_to_terminal(0, &format!("Initial state: {:?}", initial_state));
 
+        let path = echo["path"].as_str().unwrap_or_default();
+        if !path.ends_with("/echo/gadgets") {
+            fail!(format!("path parameter not substituted: {}", path));
+        }
+        if echo["method"] != json!("POST") {
+            fail!(format!("unexpected method: {}", echo["method"]));
+        }
+        if echo["query"]["units"] != json!("imperial") {
+            fail!(format!("query parameter not substituted: {}", echo["query"]));
+        }
+        if echo["headers"]["x-client"] != json!("hypergrid-test") {
+            fail!(format!("header parameter not substituted: {}", echo["headers"]));
+        }
+        if echo["body"] != json!({ "city": "Lyon", "days": 1 }) {
+            fail!(format!("body parameter not substituted: {}", echo["body"]));
+        }
+        if echo["headers"]["x-hypergrid-attestation"].is_null() {
+            fail!("upstream request carries no attestation");
+        }
 
-        // 2. Serialize the initial state for import
-        let import_data = serde_json::to_vec(&initial_state)
-            .map_err(|e| anyhow::anyhow!(format!("Failed to serialize initial state: {}", e)))?;
-        print_to_terminal(0, "Serialized initial state for import.");
+        // Path parameters can't climb out of their segment
+        let hits = upstream_hits();
+        let traversal = terminal(json!({
+            "TestProvider": {
+                "provider_name": name,
+                "arguments": arguments(&[("item", ".."), ("units", "metric"), ("client", "curl"), ("city", "Paris")]),
+                "payment_tx_hash": null,
+            }
+        }))
+        .await?;
+        match traversal {
+            Err(e) if e.contains("Upstream URL rejected") => {}
+            other => {
+                fail!(format!("dot-segment path parameter was not rejected: {:?}", other));
+            }
+        }
+        if upstream_hits() != hits {
+            fail!("a rejected URL still reached the upstream");
+        }
+
+        let failing = "it-failing";
+        unregister(failing).await?;
+        if let Err(e) = api(json!({ "RegisterProvider": failing_provider(failing) })).await? {
+            fail!(format!("registration failed: {}", e));
+        }
+        let result = terminal(json!({
+            "TestProvider": { "provider_name": failing, "arguments": [], "payment_tx_hash": null }
+        }))
+        .await?;
+        match result {
+            Err(e) if e.contains("500") => {}
+            other => {
+                fail!(format!("upstream 500 was not reported: {:?}", other));
+            }
+        }
+
+        unregister(name).await?;
+        unregister(failing).await?;
+        Ok(())
+    },
 
-        // 3. Call import_state_local_rpc
-        let import_result = import_state_local_rpc(&address, import_data).await;
-        print_to_terminal(0, &format!("import_state_local_rpc result: {:?}", import_result));
+    test_duplicate_tx_rejected: async {
+        let name = "it-paid";
+        unregister(name).await?;
+        if let Err(e) = api(json!({ "RegisterProvider": echo_provider(name, 0.01) })).await? {
+            fail!(format!("registration failed: {}", e));
+        }
 
-        // Assert import was successful
-        match import_result {
-            Ok(Ok(true)) => print_to_terminal(0, "Import successful (returned true)."),
-            Ok(Ok(false)) => {
-                fail!("import_state_local_rpc returned false");
+        let call = |tx_hash: Option<&str>| json!({
+            "CallProvider": {
+                "provider_name": name,
+                "arguments": arguments(&[("item", "widgets"), ("units", "metric"), ("client", "curl"), ("city", "Paris")]),
+                "payment_tx_hash": tx_hash,
             }
-            Ok(Err(e)) => {
-                fail!(format!("import_state_local_rpc returned an error: {}", e));
+        });
+
+        match local(call(None)).await? {
+            Err(e) if e.contains("No payment transaction hash") => {}
+            other => {
+                fail!(format!("unpaid call was not rejected: {:?}", other));
             }
-            Err(e) => {
-                fail!(format!("import_state_local_rpc failed (send error): {:?}", e));
+        }
+
+        let tx_hash = fresh_payment(10_000)?;
+
+        if let Err(e) = local(call(Some(&tx_hash))).await? {
+            fail!(format!("paid call failed: {}", e));
+        }
+        let hits = upstream_hits();
+        match local(call(Some(&tx_hash))).await? {
+            Err(e) if e.contains("already been used") => {}
+            other => {
+                fail!(format!("reused tx hash was not rejected: {:?}", other));
             }
         }
+        if upstream_hits() != hits {
+            fail!("a call with a reused tx hash reached the upstream");
+        }
 
-        // 4. Call export_state_local_rpc
-        let export_result = export_state_local_rpc(&address).await;
-        print_to_terminal(0, &format!("export_state_local_rpc result: {:?}", export_result));
+        unregister(name).await?;
+        Ok(())
+    },
 
-        // Assert export was successful and get data, handling errors first
-        let inner_result = match export_result {
-            Ok(res) => res,
+    test_health_ping_status: async {
+        let name = "it-health";
+        unregister(name).await?;
+        if let Err(e) = api(json!({ "RegisterProvider": echo_provider(name, 0.01) })).await? {
+            fail!(format!("registration failed: {}", e));
+        }
+
+        let status = match local(json!({ "HealthPing": { "provider_name": name } })).await? {
+            Ok(status) => status,
             Err(e) => {
-                fail!(format!("export_state_local_rpc failed (send error): {:?}", e));
+                fail!(format!("health ping failed: {}", e));
             }
         };
-        let exported_data = match inner_result {
-            Ok(data) => data,
-            Err(e) => {
-                fail!(format!("export_state_local_rpc returned an error: {}", e));
+        if status["available"] != json!(true)
+            || status["configured"] != json!(true)
+            || status["circuit_state"] != json!("closed")
+            || status["price"] != json!(0.01)
+            || status["wallet"] != json!(PROVIDER_WALLET)
+            || status["queue_depth"] != json!(0)
+        {
+            fail!(format!("unexpected health status: {}", status));
+        }
+        if !status["payment_modes"].as_array().is_some_and(|modes| modes.contains(&json!("tx_hash"))) {
+            fail!(format!("tx_hash payments not advertised: {}", status["payment_modes"]));
+        }
+
+        // Operators predating versioning offer nothing and are on version 1
+        if status["protocol_version"] != json!(1) {
+            fail!(format!("unversioned ping did not get version 1: {}", status));
+        }
+        match local(json!({ "HealthPing": { "provider_name": name, "protocol_versions": [1, 99] } })).await? {
+            Ok(status) if status["protocol_version"] == json!(1) => {}
+            other => {
+                fail!(format!("highest common version was not chosen: {:?}", other));
+            }
+        }
+        match local(json!({ "HealthPing": { "provider_name": name, "protocol_versions": [99] } })).await? {
+            Err(e) if e.contains("No common Hypergrid protocol version") => {}
+            other => {
+                fail!(format!("ping without a common version did not fail: {:?}", other));
+            }
+        }
+        let hits = upstream_hits();
+        let call = json!({
+            "CallProvider": {
+                "provider_name": name,
+                "arguments": arguments(&[("item", "widgets"), ("units", "metric"), ("client", "curl"), ("city", "Paris")]),
+                "payment_tx_hash": null,
+                "protocol_version": 99,
+            }
+        });
+        match local(call).await? {
+            Err(e) if e.contains("Unsupported Hypergrid protocol version 99") => {}
+            other => {
+                fail!(format!("call in an unknown version was not refused: {:?}", other));
+            }
+        }
+        if upstream_hits() != hits {
+            fail!("a call in an unknown version reached the upstream");
+        }
+
+        match local(json!({ "HealthPing": { "provider_name": "it-missing" } })).await? {
+            Err(e) if e.contains("not found") => {}
+            other => {
+                fail!(format!("health ping of an unknown provider did not fail: {:?}", other));
+            }
+        }
+
+        unregister(name).await?;
+        Ok(())
+    },
+
+    test_x402_payment_required: async {
+        let name = "it-x402";
+        unregister(name).await?;
+        if let Err(e) = api(json!({ "RegisterProvider": echo_provider(name, 0.01) })).await? {
+            fail!(format!("registration failed: {}", e));
+        }
+
+        let (status, body) = xfour(&format!("providername={}&item=widgets", name), HashMap::new()).await?;
+        if status != 402 {
+            fail!(format!("expected 402 without X-PAYMENT, got {}: {}", status, body));
+        }
+        let accepted = &body["accepts"][0];
+        if body["x402Version"] != json!(1)
+            || accepted["payTo"] != json!(PROVIDER_WALLET)
+            || accepted["maxAmountRequired"] != json!("10000")
+        {
+            fail!(format!("unexpected payment requirements: {}", body));
+        }
+
+        let (status, _) = xfour("providername=it-missing", HashMap::new()).await?;
+        if status != 404 {
+            fail!(format!("expected 404 for an unknown provider, got {}", status));
+        }
+
+        // Payments are verified in-process, before any upstream call or facilitator request
+        let hits = upstream_hits();
+        let query = format!("providername={}&item=widgets&units=metric&client=curl&city=Paris", name);
+        let network = accepted["network"].as_str().unwrap_or_default().to_string();
+        let (status, body) = xfour(&query, x_payment(&network, PROVIDER_WALLET, "10000", 1)).await?;
+        if status != 402 || !body["error"].as_str().unwrap_or_default().contains("valid_before") {
+            fail!(format!("expired authorization was not refused: {} {}", status, body));
+        }
+        // It must outlast the upstream call plus settlement: 30 + 30 + 60 seconds by default
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs();
+        if accepted["maxTimeoutSeconds"].as_u64().unwrap_or_default() <= 120 {
+            fail!(format!("maxTimeoutSeconds leaves no time to settle: {}", accepted["maxTimeoutSeconds"]));
+        }
+        let (status, body) = xfour(&query, x_payment(&network, PROVIDER_WALLET, "10000", now + 60)).await?;
+        if status != 402 || !body["error"].as_str().unwrap_or_default().contains("valid_before") {
+            fail!(format!("authorization expiring before settlement was not refused: {} {}", status, body));
+        }
+        let valid_before = now + 600;
+        let (status, body) = xfour(&query, x_payment(&network, PROVIDER_WALLET, "10000", valid_before)).await?;
+        if status != 402 || !body["error"].as_str().unwrap_or_default().contains("signature") {
+            fail!(format!("forged signature was not refused: {} {}", status, body));
+        }
+        if upstream_hits() != hits {
+            fail!("an unverified x402 payment reached the upstream");
+        }
+        unregister(name).await?;
+
+        // Every configured option is advertised, with its own recipient and amount
+        let name = "it-x402-options";
+        unregister(name).await?;
+        let eurc_wallet = "0x3333333333333333333333333333333333333333";
+        let option = |network: &str, asset: &str, pay_to: Option<&str>, price: Option<f64>| json!({
+            "network": network,
+            "asset": asset,
+            "asset_name": "USD Coin",
+            "asset_version": "2",
+            "decimals": 6,
+            "pay_to": pay_to,
+            "price": price,
+        });
+        let mut provider = echo_provider(name, 0.01);
+        provider["x402_accepts"] = json!([
+            option("base", USDC_BASE_ADDRESS, None, None),
+            option("base", EURC_BASE_ADDRESS, Some(eurc_wallet), Some(0.009)),
+            option("base-sepolia", USDC_SEPOLIA_ADDRESS, None, None),
+        ]);
+        if let Err(e) = api(json!({ "RegisterProvider": provider })).await? {
+            fail!(format!("registration failed: {}", e));
+        }
+        let (status, body) = xfour(&format!("providername={}&item=widgets", name), HashMap::new()).await?;
+        let accepts = body["accepts"].as_array().cloned().unwrap_or_default();
+        if status != 402 || accepts.len() != 3 {
+            fail!(format!("expected three payment options, got {}: {}", status, body));
+        }
+        if accepts[1]["asset"] != json!(EURC_BASE_ADDRESS)
+            || accepts[1]["payTo"] != json!(eurc_wallet)
+            || accepts[1]["maxAmountRequired"] != json!("9000")
+            || accepts[2]["network"] != json!("base-sepolia")
+            || accepts[2]["payTo"] != json!(PROVIDER_WALLET)
+        {
+            fail!(format!("unexpected payment options: {}", body));
+        }
+        unregister(name).await?;
+
+        // Options a payment can't tell apart are refused
+        let mut ambiguous = echo_provider(name, 0.01);
+        ambiguous["x402_accepts"] = json!([
+            option("base", USDC_BASE_ADDRESS, None, None),
+            option("base", EURC_BASE_ADDRESS, None, None),
+        ]);
+        match api(json!({ "RegisterProvider": ambiguous })).await? {
+            Err(e) if e.contains("share a recipient and amount") => {}
+            other => {
+                fail!(format!("ambiguous payment options were accepted: {:?}", other));
+            }
+        }
+        Ok(())
+    },
+
+    test_x402_self_settlement: async {
+        let name = "it-x402-self-settle";
+        unregister(name).await?;
+        let mut provider = echo_provider(name, 0.01);
+        provider["x402_accepts"] = json!([{
+            "network": "base",
+            "asset": USDC_BASE_ADDRESS,
+            "asset_name": "USD Coin",
+            "asset_version": "2",
+            "decimals": 6,
+            "pay_to": null,
+            "price": null,
+        }]);
+        if let Err(e) = api(json!({ "RegisterProvider": provider })).await? {
+            fail!(format!("registration failed: {}", e));
+        }
+        // The key is only accepted from processes on the node
+        if matches!(api(json!({ "SetX402Relayer": RELAYER_KEY })).await, Ok(Ok(_))) {
+            fail!("SetX402Relayer was accepted over /api");
+        }
+        let relayer = match local(json!({ "SetX402Relayer": RELAYER_KEY })).await? {
+            Ok(Value::String(address)) => address,
+            other => {
+                fail!(format!("SetX402Relayer failed: {:?}", other));
+            }
+        };
+        if api(json!({ "GetX402RelayerAddress": null })).await? != Ok(json!(relayer)) {
+            fail!("relayer address was not kept");
+        }
+        if let Err(e) = api(json!({ "SetX402Settlement": "SelfSettle" })).await? {
+            fail!(format!("SetX402Settlement failed: {}", e));
+        }
+
+        // The relayer submits the authorization and the header carries the mined tx hash
+        revert_sent_transactions(false);
+        let sent = sent_transactions().len();
+        let query = format!("providername={}&item=widgets&units=metric&client=curl&city=Paris", name);
+        let payment = signed_x_payment(USDC_BASE_ADDRESS, PROVIDER_WALLET, "10000")?;
+        let (status, headers, body) = xfour_with_headers(&query, payment.clone()).await?;
+        if status != 200 || body["path"] != json!("/echo/widgets") {
+            fail!(format!("self-settled call failed: {} {}", status, body));
+        }
+        let transactions = sent_transactions();
+        if transactions.len() != sent + 1 || !transactions[sent].contains(TRANSFER_WITH_AUTHORIZATION_SELECTOR) {
+            fail!(format!("expected one transferWithAuthorization from the relayer, got {:?}", &transactions[sent..]));
+        }
+        let settled = payment_response(&headers).unwrap_or_default();
+        let raw = hex::decode(transactions[sent].trim_start_matches("0x"))?;
+        if settled["success"] != json!(true)
+            || settled["transaction"] != json!(alloy_primitives::keccak256(&raw).to_string())
+        {
+            fail!(format!("X-PAYMENT-RESPONSE does not carry the relayer's tx: {}", settled));
+        }
+
+        // Retrying the settled payment gets the same response without paying or calling again
+        let hits = upstream_hits();
+        let (status, retry_headers, retry_body) = xfour_with_headers(&query, payment.clone()).await?;
+        if status != 200 || retry_body != body || payment_response(&retry_headers) != Some(settled.clone()) {
+            fail!(format!("retry of a settled payment was not answered from cache: {} {}", status, retry_body));
+        }
+        if upstream_hits() != hits || sent_transactions().len() != sent + 1 {
+            fail!("retry of a settled payment called the upstream or settled again");
+        }
+        // The same payment for another call is a replay
+        let other_query = format!("providername={}&item=gadgets&units=metric&client=curl&city=Paris", name);
+        let (status, body) = xfour(&other_query, payment).await?;
+        if status != 402 || body["error"] != json!("invalid_exact_evm_payload_authorization_nonce_used") {
+            fail!(format!("replayed payment was not refused: {} {}", status, body));
+        }
+        if upstream_hits() != hits {
+            fail!("a replayed payment reached the upstream");
+        }
+
+        // A mined revert is reported with its hash and not handed to the facilitator
+        revert_sent_transactions(true);
+        let (status, body) =
+            xfour(&query, signed_x_payment(USDC_BASE_ADDRESS, PROVIDER_WALLET, "10000")?).await?;
+        revert_sent_transactions(false);
+        if status != 402 || body["reason"] != json!("settlement_transaction_reverted") {
+            fail!(format!("reverted settlement was not refused: {} {}", status, body));
+        }
+        if sent_transactions().len() != sent + 2 {
+            fail!("a reverted settlement was submitted again");
+        }
+
+        if let Err(e) = api(json!({ "SetX402Settlement": "Facilitator" })).await? {
+            fail!(format!("SetX402Settlement failed: {}", e));
+        }
+        unregister(name).await?;
+        Ok(())
+    },
+
+    test_x402_discovery: async {
+        let name = "it-x402-discovery";
+        unregister(name).await?;
+        if let Err(e) = api(json!({ "RegisterProvider": echo_provider(name, 0.01) })).await? {
+            fail!(format!("registration failed: {}", e));
+        }
+
+        let (status, body) = x402_discovery().await?;
+        let item = body["items"]
+            .as_array()
+            .and_then(|items| items.iter().find(|item| item["metadata"]["providerName"] == json!(name)))
+            .cloned();
+        let Some(item) = item.filter(|_| status == 200 && body["x402Version"] == json!(1)) else {
+            fail!(format!("provider missing from discovery: {} {}", status, body));
+        };
+        let resource = item["resource"].as_str().unwrap_or_default().to_string();
+        if !resource.ends_with(&format!("/xfour?providername={}", name))
+            || item["type"] != json!("http")
+            || item["accepts"][0]["payTo"] != json!(PROVIDER_WALLET)
+            || item["accepts"][0]["maxAmountRequired"] != json!("10000")
+            || item["accepts"][0]["resource"] != json!(resource)
+            || item["metadata"]["price"] != json!(0.01)
+            || item["metadata"]["description"] != json!("Echoes the upstream request")
+            || item["metadata"]["inputSchema"]["queryParams"]["units"].is_null()
+        {
+            fail!(format!("unexpected discovery entry: {}", item));
+        }
+
+        // The listed resource is the provider's 402
+        let query = resource.split_once('?').map(|(_, query)| query).unwrap_or_default();
+        let (status, body) = xfour(query, HashMap::new()).await?;
+        if status != 402 || body["accepts"][0]["payTo"] != json!(PROVIDER_WALLET) {
+            fail!(format!("discovered resource did not answer 402: {} {}", status, body));
+        }
+
+        unregister(name).await?;
+        let (_, body) = x402_discovery().await?;
+        if body["items"].as_array().is_some_and(|items| items.iter().any(|item| item["metadata"]["providerName"] == json!(name))) {
+            fail!("unregistered provider is still discoverable");
+        }
+        Ok(())
+    },
+
+    test_sandbox_record_replay: async {
+        let name = "it-sandbox";
+        unregister(name).await?;
+        if let Err(e) = api(json!({ "RegisterProvider": echo_provider(name, 0.0) })).await? {
+            fail!(format!("registration failed: {}", e));
+        }
+        // Fixtures persist in the drive, so start from none
+        local(json!({ "SetSandboxFixtures": [name, []] })).await?.map_err(|e| anyhow::anyhow!(e))?;
+        let test_call = |item: &str| json!({
+            "TestProvider": {
+                "provider_name": name,
+                "arguments": arguments(&[("item", item), ("units", "metric"), ("client", "curl"), ("city", "Paris")]),
+                "payment_tx_hash": null,
+            }
+        });
+
+        // Only processes on the node may switch modes
+        if matches!(api(json!({ "SetSandboxMode": "Replay" })).await, Ok(Ok(_))) {
+            fail!("SetSandboxMode was accepted over /api");
+        }
+
+        // Recording calls the upstream and stores its answer
+        local(json!({ "SetSandboxMode": "Record" })).await?.map_err(|e| anyhow::anyhow!(e))?;
+        let hits = upstream_hits();
+        let recorded = terminal(test_call("widgets")).await?;
+        let recorded_hits = upstream_hits() - hits;
+
+        // Replay answers from the fixture, and misses without going upstream
+        local(json!({ "SetSandboxMode": "Replay" })).await?.map_err(|e| anyhow::anyhow!(e))?;
+        let replayed = terminal(test_call("widgets")).await?;
+        let missed = terminal(test_call("gadgets")).await?;
+        let replay_hits = upstream_hits() - hits - recorded_hits;
+        // Paid calls ignore the sandbox
+        let paid = local(json!({
+            "CallProvider": {
+                "provider_name": name,
+                "arguments": arguments(&[("item", "widgets"), ("units", "metric"), ("client", "curl"), ("city", "Paris")]),
+                "payment_tx_hash": fresh_payment(10_000)?,
+            }
+        }))
+        .await?;
+        let paid_hits = upstream_hits() - hits - recorded_hits - replay_hits;
+        local(json!({ "SetSandboxMode": "Off" })).await?.map_err(|e| anyhow::anyhow!(e))?;
+
+        let recorded = match recorded {
+            Ok(Value::String(body)) => body,
+            other => {
+                fail!(format!("recorded call failed: {:?}", other));
+            }
+        };
+        if recorded_hits != 1 {
+            fail!(format!("recording made {} upstream calls", recorded_hits));
+        }
+        let fixtures = api(json!({ "GetSandboxFixtures": name })).await?.unwrap_or_default();
+        let fixture_url = fixtures[0]["url"].as_str().unwrap_or_default();
+        if fixtures.as_array().map(Vec::len) != Some(1) || !fixture_url.ends_with("/echo/widgets") {
+            fail!(format!("expected one fixture for /echo/widgets without its query: {}", fixtures));
+        }
+        if replayed != Ok(Value::String(recorded)) {
+            fail!(format!("replay did not return the recorded response: {:?}", replayed));
+        }
+        match missed {
+            Err(e) if e.contains("no recorded response") => {}
+            other => {
+                fail!(format!("replay miss was not reported: {:?}", other));
+            }
+        }
+        if replay_hits != 0 {
+            fail!("replay reached the upstream");
+        }
+        if paid.is_err() || paid_hits != 1 {
+            fail!(format!("paid call during replay did not go upstream: {:?}", paid));
+        }
+
+        local(json!({ "SetSandboxFixtures": [name, []] })).await?.map_err(|e| anyhow::anyhow!(e))?;
+        unregister(name).await?;
+        Ok(())
+    },
+
+    test_async_jobs: async {
+        let name = "it-async";
+        unregister(name).await?;
+        let async_job = json!({ "upstream_timeout_seconds": 30, "result_ttl_seconds": 600 });
+        let mut provider = echo_provider(name, 0.01);
+        provider["async_job"] = async_job.clone();
+        if let Err(e) = api(json!({ "RegisterProvider": provider })).await? {
+            fail!(format!("registration failed: {}", e));
+        }
+
+        // The paid call answers with a ticket; the upstream call happens in the background
+        let ticket = local(json!({
+            "CallProvider": {
+                "provider_name": name,
+                "arguments": arguments(&[("item", "widgets"), ("units", "metric"), ("client", "curl"), ("city", "Paris")]),
+                "payment_tx_hash": fresh_payment(10_000)?,
+            }
+        }))
+        .await?;
+        let ticket: Value = match ticket {
+            Ok(Value::String(body)) => serde_json::from_str(&body)?,
+            other => {
+                fail!(format!("async call failed: {:?}", other));
+            }
+        };
+        let job_id = ticket["job_id"].as_str().unwrap_or_default().to_string();
+        if job_id.is_empty() || ticket["status"] != json!("Pending") {
+            fail!(format!("unexpected job ticket: {}", ticket));
+        }
+        let job = finished_job(&job_id).await?;
+        let result: Value = serde_json::from_str(job["result"].as_str().unwrap_or_default()).unwrap_or_default();
+        if job["status"] != json!("Succeeded") || !result["path"].as_str().unwrap_or_default().ends_with("/echo/widgets") {
+            fail!(format!("job did not succeed with the upstream response: {}", job));
+        }
+        // Finished results stay available for their TTL, so a lost poll reply can be fetched again
+        match local(json!({ "GetJobResult": { "job_id": job_id, "protocol_version": null } })).await? {
+            Ok(again) if again == job => {}
+            other => {
+                fail!(format!("finished job changed on a second poll: {:?}", other));
+            }
+        }
+        match local(json!({ "GetJobResult": { "job_id": "it-missing-job", "protocol_version": null } })).await? {
+            Err(e) if e.contains("not found") => {}
+            other => {
+                fail!(format!("unknown job was not refused: {:?}", other));
+            }
+        }
+        unregister(name).await?;
+
+        // Upstream failures end the job as failed, with the error kept for polling
+        let failing = "it-async-failing";
+        unregister(failing).await?;
+        let mut provider = failing_provider(failing);
+        provider["price"] = json!(0.01);
+        provider["async_job"] = async_job;
+        if let Err(e) = api(json!({ "RegisterProvider": provider })).await? {
+            fail!(format!("registration failed: {}", e));
+        }
+        let ticket = local(json!({
+            "CallProvider": { "provider_name": failing, "arguments": [], "payment_tx_hash": fresh_payment(10_000)? }
+        }))
+        .await?;
+        let ticket: Value = match ticket {
+            Ok(Value::String(body)) => serde_json::from_str(&body)?,
+            other => {
+                fail!(format!("async call failed: {:?}", other));
+            }
+        };
+        let job = finished_job(ticket["job_id"].as_str().unwrap_or_default()).await?;
+        if job["status"] != json!("Failed") || !job["error"].as_str().unwrap_or_default().contains("500") {
+            fail!(format!("failed upstream did not fail the job: {}", job));
+        }
+        unregister(failing).await?;
+        Ok(())
+    },
+
+    test_response_chunks: async {
+        let name = "it-chunked";
+        unregister(name).await?;
+        let mut provider = echo_provider(name, 0.01);
+        provider["max_response_bytes"] = json!(1024);
+        if let Err(e) = api(json!({ "RegisterProvider": provider })).await? {
+            fail!(format!("registration failed: {}", e));
+        }
+
+        // Quotes grow when the echoed body is escaped into the chunk envelope
+        let city = "\"Paris\" ".repeat(200);
+        let first = local(json!({
+            "CallProvider": {
+                "provider_name": name,
+                "arguments": arguments(&[("item", "widgets"), ("units", "metric"), ("client", "curl"), ("city", city.as_str())]),
+                "payment_tx_hash": fresh_payment(10_000)?,
+            }
+        }))
+        .await?;
+        let first = match first {
+            Ok(Value::String(envelope)) => envelope,
+            other => {
+                fail!(format!("chunked call failed: {:?}", other));
+            }
+        };
+        let envelope: Value = serde_json::from_str(&first)?;
+        let token = envelope["continuation_token"].as_str().unwrap_or_default().to_string();
+        let total_chunks = envelope["total_chunks"].as_u64().unwrap_or_default();
+        if total_chunks < 2 || envelope["next_chunk_index"] != json!(1) || first.len() > 1024 {
+            fail!(format!("expected a first chunk of at most 1024 bytes: {}", first));
+        }
+
+        let mut response = envelope["chunk"].as_str().unwrap_or_default().to_string();
+        for index in 1..total_chunks {
+            let chunk = local(json!({
+                "GetResponseChunk": { "continuation_token": token, "chunk_index": index, "protocol_version": null }
+            }))
+            .await?;
+            let chunk = match chunk {
+                Ok(Value::String(envelope)) if envelope.len() <= 1024 => envelope,
+                other => {
+                    fail!(format!("chunk {} missing or oversized: {:?}", index, other));
+                }
+            };
+            let chunk: Value = serde_json::from_str(&chunk)?;
+            response.push_str(chunk["chunk"].as_str().unwrap_or_default());
+        }
+        let echo: Value = serde_json::from_str(&response)?;
+        if echo["body"]["city"] != json!(city) {
+            fail!(format!("reassembled response differs: {}", echo["body"]));
+        }
+        // Redeeming is free and repeatable until the token expires
+        match local(json!({
+            "GetResponseChunk": { "continuation_token": token, "chunk_index": 0, "protocol_version": null }
+        }))
+        .await?
+        {
+            Ok(Value::String(envelope)) if envelope == first => {}
+            other => {
+                fail!(format!("first chunk differs when redeemed: {:?}", other));
+            }
+        }
+
+        match local(json!({
+            "GetResponseChunk": { "continuation_token": token, "chunk_index": total_chunks, "protocol_version": null }
+        }))
+        .await?
+        {
+            Err(e) if e.contains("out of range") => {}
+            other => {
+                fail!(format!("out-of-range chunk was not refused: {:?}", other));
+            }
+        }
+        match local(json!({
+            "GetResponseChunk": { "continuation_token": "it-missing-token", "chunk_index": 0, "protocol_version": null }
+        }))
+        .await?
+        {
+            Err(e) if e.contains("not found") => {}
+            other => {
+                fail!(format!("unknown continuation token was not refused: {:?}", other));
+            }
+        }
+        unregister(name).await?;
+        Ok(())
+    },
+
+    test_dataset_and_sql_backends: async {
+        let dataset_name = "it-dataset";
+        let sql_name = "it-sql";
+        unregister(dataset_name).await?;
+        unregister(sql_name).await?;
+        let csv = "name,price,city,supplier\nwidget,12,Paris,acme\ngadget,5,Lyon,globex\ngizmo,20,Paris,initech\n";
+        match api(json!({ "UploadDataset": ["it-items.csv", csv] })).await? {
+            Ok(info) if info["row_count"] == json!(3) => {}
+            other => {
+                fail!(format!("dataset upload failed: {:?}", other));
+            }
+        }
+        let test_call = |name: &str, pairs: &[(&str, &str)]| json!({
+            "TestProvider": { "provider_name": name, "arguments": arguments(pairs), "payment_tx_hash": null }
+        });
+
+        let dataset = json!({ "Dataset": { "file_name": "it-items.csv", "default_limit": 10, "max_limit": 100 } });
+        if let Err(e) = api(json!({ "RegisterProvider": backend_provider(dataset_name, dataset) })).await? {
+            fail!(format!("dataset registration failed: {}", e));
+        }
+        let page = terminal(test_call(dataset_name, &[("filter", "city=Paris"), ("sort", "-price"), ("select", "name,price"), ("limit", "1")])).await?;
+        let page: Value = match page {
+            Ok(Value::String(body)) => serde_json::from_str(&body)?,
+            other => {
+                fail!(format!("dataset query failed: {:?}", other));
+            }
+        };
+        if page["rows"] != json!([{ "name": "gizmo", "price": 20 }]) || page["total_matched"] != json!(2) || page["next_offset"] != json!(1) {
+            fail!(format!("unexpected dataset page: {}", page));
+        }
+        let last_page = terminal(test_call(dataset_name, &[("filter", "city=Paris"), ("sort", "-price"), ("select", "name,price"), ("limit", "1"), ("offset", "1")])).await?;
+        let last_page: Value = match last_page {
+            Ok(Value::String(body)) => serde_json::from_str(&body)?,
+            other => {
+                fail!(format!("dataset page query failed: {:?}", other));
+            }
+        };
+        if last_page["rows"] != json!([{ "name": "widget", "price": 12 }]) || !last_page["next_offset"].is_null() {
+            fail!(format!("unexpected last dataset page: {}", last_page));
+        }
+        match api(json!({ "DeleteDataset": "it-items.csv" })).await? {
+            Err(e) if e.contains(dataset_name) => {}
+            other => {
+                fail!(format!("dataset in use was deleted: {:?}", other));
+            }
+        }
+
+        // The same rows in sqlite, with the supplier column kept from callers
+        match api(json!({ "ImportDatasetToSqlite": ["it-items.csv", "it_inventory", "items"] })).await? {
+            Ok(rows) if rows == json!(3) => {}
+            other => {
+                fail!(format!("sqlite import failed: {:?}", other));
+            }
+        }
+        let sql_source = |sql: &str, parameters: &[&str]| json!({
+            "Sql": {
+                "database": "it_inventory",
+                "allowed_tables": [{ "name": "items", "columns": ["name", "price", "city"] }],
+                "queries": [{ "name": "by_city", "description": "Items in a city", "sql": sql, "parameters": parameters }],
+                "free_form": { "timeout_seconds": 5 },
+                "row_limit": 10,
+            }
+        });
+        let miscounted = backend_provider(sql_name, sql_source("SELECT name FROM items WHERE city = '?'", &["city"]));
+        match api(json!({ "RegisterProvider": miscounted })).await? {
+            Err(e) if e.contains("placeholders") => {}
+            other => {
+                fail!(format!("template with a quoted placeholder was accepted: {:?}", other));
+            }
+        }
+        let sql = backend_provider(sql_name, sql_source("SELECT name, price FROM items WHERE city = ? ORDER BY price", &["city"]));
+        if let Err(e) = api(json!({ "RegisterProvider": sql })).await? {
+            fail!(format!("sql registration failed: {}", e));
+        }
+        let result = terminal(test_call(sql_name, &[("query", "by_city"), ("city", "Paris")])).await?;
+        let result: Value = match result {
+            Ok(Value::String(body)) => serde_json::from_str(&body)?,
+            other => {
+                fail!(format!("sql template query failed: {:?}", other));
+            }
+        };
+        if result["rows"] != json!([{ "name": "widget", "price": 12 }, { "name": "gizmo", "price": 20 }]) || result["truncated"] != json!(false) {
+            fail!(format!("unexpected sql rows: {}", result));
+        }
+        if result["columns"] != json!([{ "name": "name", "type": "text" }, { "name": "price", "type": "integer" }]) {
+            fail!(format!("unexpected sql column metadata: {}", result["columns"]));
+        }
+        let count = terminal(test_call(sql_name, &[("sql", "SELECT count(*) AS n FROM items")])).await?;
+        let count: Value = match count {
+            Ok(Value::String(body)) => serde_json::from_str(&body)?,
+            other => {
+                fail!(format!("free-form sql query failed: {:?}", other));
+            }
+        };
+        if count["rows"][0]["n"] != json!(3) {
+            fail!(format!("unexpected free-form count: {}", count));
+        }
+        for (sql, expected) in [
+            ("SELECT supplier FROM items", "not accessible"),
+            ("SELECT * FROM items", "hidden columns"),
+            ("DELETE FROM items", "Only SELECT"),
+            ("WITH t(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM t) SELECT n FROM t", "Recursive"),
+        ] {
+            match terminal(test_call(sql_name, &[("sql", sql)])).await? {
+                Err(e) if e.contains(expected) => {}
+                other => {
+                    fail!(format!("'{}' was not refused with '{}': {:?}", sql, expected, other));
+                }
+            }
+        }
+
+        unregister(sql_name).await?;
+        unregister(dataset_name).await?;
+        api(json!({ "DeleteDataset": "it-items.csv" })).await?.map_err(|e| anyhow::anyhow!(e))?;
+        Ok(())
+    },
+
+    test_pipelines: async {
+        let downstream = "it-pipeline-downstream";
+        let name = "it-pipeline";
+        unregister(downstream).await?;
+        unregister(name).await?;
+        if let Err(e) = api(json!({ "RegisterProvider": echo_provider(downstream, 0.01) })).await? {
+            fail!(format!("registration failed: {}", e));
+        }
+        let echo_endpoint = echo_provider(name, 0.0)["endpoint"].clone();
+        let call_args = arguments(&[("item", "widgets"), ("units", "metric"), ("client", "curl"), ("city", "Lyon")]);
+
+        // The second step reads the city back out of the first step's output
+        let mut provider = echo_provider(name, 0.02);
+        provider["pipeline"] = json!({ "steps": [
+            { "name": "lookup", "target": { "Local": echo_endpoint }, "bindings": [] },
+            { "name": "forward", "target": { "Local": echo_endpoint }, "bindings": [
+                { "argument_name": "item", "source": { "Literal": "gadgets" } },
+                { "argument_name": "units", "source": { "Literal": "metric" } },
+                { "argument_name": "client", "source": { "CallArgument": "client" } },
+                { "argument_name": "city", "source": { "StepOutput": { "step_name": "lookup", "json_pointer": "/body/city" } } },
+            ] },
+        ] });
+        if let Err(e) = api(json!({ "RegisterProvider": provider })).await? {
+            fail!(format!("pipeline registration failed: {}", e));
+        }
+        let hits = upstream_hits();
+        let result = terminal(json!({
+            "TestProvider": { "provider_name": name, "arguments": call_args, "payment_tx_hash": null }
+        }))
+        .await?;
+        let echo: Value = match result {
+            Ok(Value::String(body)) => serde_json::from_str(&body)?,
+            other => {
+                fail!(format!("pipeline call failed: {:?}", other));
             }
         };
-        print_to_terminal(0, "Exported state data received.");
+        if !echo["path"].as_str().unwrap_or_default().ends_with("/echo/gadgets") || echo["body"]["city"] != json!("Lyon") {
+            fail!(format!("last step did not get its bound arguments: {}", echo));
+        }
+        if upstream_hits() != hits + 2 {
+            fail!(format!("expected two upstream calls, saw {}", upstream_hits() - hits));
+        }
+        unregister(name).await?;
 
-        // 5. Compare initial state with exported state manually
-        if initial_state.tasks.len() != exported_data.tasks.len() {
-            fail!(format!(
-                "Task list lengths differ. Expected: {}, Got: {}",
-                initial_state.tasks.len(),
-                exported_data.tasks.len()
-            ));
+        // Validation runs local steps and only health-pings remote ones, paying nothing
+        let remote_pipeline = |provider_name: &str, price: f64| {
+            let mut provider = echo_provider(name, 0.02);
+            provider["pipeline"] = json!({ "steps": [
+                { "name": "lookup", "target": { "Local": echo_endpoint }, "bindings": [] },
+                { "name": "resell", "target": { "Remote": {
+                    "provider_node": our().node,
+                    "provider_name": provider_name,
+                    "price": price,
+                    "wallet_address": PROVIDER_WALLET,
+                } }, "bindings": [] },
+            ] });
+            provider
+        };
+        let hits = upstream_hits();
+        let validated = api(json!({ "ValidateProvider": [remote_pipeline(downstream, 0.01), call_args] })).await?;
+        let validated: Value = match validated {
+            Ok(Value::String(body)) => serde_json::from_str(&body)?,
+            other => {
+                fail!(format!("pipeline validation failed: {:?}", other));
+            }
+        };
+        let lookup: Value = serde_json::from_str(validated["validation_result"].as_str().unwrap_or_default()).unwrap_or_default();
+        if !lookup["path"].as_str().unwrap_or_default().ends_with("/echo/widgets") || !validated["provider"]["output_schema"].is_null() {
+            fail!(format!("dry run did not stop at the remote step: {}", validated));
+        }
+        if upstream_hits() != hits + 1 {
+            fail!("dry run reached the downstream provider's upstream");
+        }
+        for (provider_name, price, expected) in [(downstream, 0.005, "charges"), ("it-missing", 0.01, "not found")] {
+            match api(json!({ "ValidateProvider": [remote_pipeline(provider_name, price), call_args] })).await? {
+                Err(e) if e.contains(expected) => {}
+                other => {
+                    fail!(format!("dry run against {} at {} was not refused: {:?}", provider_name, price, other));
+                }
+            }
+        }
+
+        // Steps only read earlier outputs, and downstream prices can't exceed the call's own
+        let mut backwards = remote_pipeline(downstream, 0.01);
+        backwards["pipeline"]["steps"][0]["bindings"] = json!([
+            { "argument_name": "city", "source": { "StepOutput": { "step_name": "resell", "json_pointer": "/body/city" } } },
+        ]);
+        let mut underpriced = remote_pipeline(downstream, 0.01);
+        underpriced["price"] = json!(0.005);
+        for (provider, expected) in [(backwards, "does not run before it"), (underpriced, "more than the provider price")] {
+            match api(json!({ "RegisterProvider": provider })).await? {
+                Err(e) if e.contains(expected) => {}
+                other => {
+                    fail!(format!("pipeline was not refused with '{}': {:?}", expected, other));
+                }
+            }
         }
 
-        // Iterate and compare each task field by field
-        for (initial_task, exported_task) in initial_state.tasks.iter().zip(exported_data.tasks.iter()) {
-            if initial_task.id != exported_task.id ||
-               initial_task.text != exported_task.text ||
-               initial_task.completed != exported_task.completed {
-                fail!(format!(
-                    "Task mismatch detected.\nExpected Task: {:?}\nGot Task: {:?}",
-                    initial_task, // Assumes TodoItem derives Debug
-                    exported_task  // Assumes TodoItem derives Debug
-                ));
+        // TestProvider dry-runs remote steps too: it pings them and never pays
+        let mut remote_only = remote_pipeline(downstream, 0.01);
+        remote_only["pipeline"]["steps"] = json!([remote_only["pipeline"]["steps"][1].clone()]);
+        if let Err(e) = api(json!({ "RegisterProvider": remote_only })).await? {
+            fail!(format!("pipeline registration failed: {}", e));
+        }
+        let hits = upstream_hits();
+        let tested = terminal(json!({
+            "TestProvider": { "provider_name": name, "arguments": call_args, "payment_tx_hash": null }
+        }))
+        .await?;
+        match tested {
+            Ok(Value::String(body)) if body == "{}" => {}
+            other => {
+                fail!(format!("TestProvider did not stop at the remote step: {:?}", other));
             }
         }
-        print_to_terminal(0, "Exported state matches initial state.");
+        if upstream_hits() != hits {
+            fail!("TestProvider reached the downstream provider's upstream");
+        }
 
+        unregister(name).await?;
+        unregister(downstream).await?;
         Ok(())
     },
 
+    // The suite runs twice on the same node home (tests.toml): the first run registers a
+    // provider, the second checks it survived the node restart in between
+    test_vfs_persistence_across_restart: async {
+        if get_state().as_deref() != Some(RESTART_MARKER) {
+            unregister(PERSISTED_PROVIDER).await?;
+            if let Err(e) = api(json!({ "RegisterProvider": echo_provider(PERSISTED_PROVIDER, 0.01) })).await? {
+                fail!(format!("registration failed: {}", e));
+            }
+            set_state(RESTART_MARKER);
+            print_to_terminal(0, "Registered provider for the restart check, run the suite again");
+            return Ok(());
+        }
+
+        set_state(b"");
+        if !registered_names().await?.contains(&PERSISTED_PROVIDER.to_string()) {
+            fail!("provider registered before the restart is gone");
+        }
+        if revision_count(PERSISTED_PROVIDER).await? == 0 {
+            fail!("revision history did not survive the restart");
+        }
+        // Hosts pinned at registration are part of the persisted definition
+        let providers = api(json!({ "GetRegisteredProviders": null })).await?.unwrap_or_default();
+        let persisted = providers
+            .as_array()
+            .and_then(|providers| providers.iter().find(|p| p["provider_name"] == json!(PERSISTED_PROVIDER)))
+            .cloned()
+            .unwrap_or_default();
+        if persisted["endpoint"]["allowed_hosts"] != json!(["localhost"]) {
+            fail!(format!("persisted definition changed: {}", persisted));
+        }
+
+        unregister(PERSISTED_PROVIDER).await?;
+        Ok(())
+    },
 );
@@ -0,0 +1,307 @@
+//! Requests to the provider under test, over the same channels its real clients use:
+//! the `/api` and `/xfour` HTTP bindings, and local messages.
+
+use crate::stand_in::{base_url, NODE_PORT};
+use alloy_primitives::{keccak256, Address as EthAddress, U256};
+use alloy_sol_types::{sol, Eip712Domain, SolStruct};
+use base64ct::{Base64, Encoding};
+use hyperware_app_common::send;
+use hyperware_process_lib::http::client::{
+    HttpClientAction, HttpClientError, HttpClientResponse, OutgoingHttpRequest,
+};
+use hyperware_process_lib::{get_blob, Address, Request};
+use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+pub const PROVIDER_PROCESS: &str = "provider:hypergrid:ware.hypr";
+
+/// Wallet the test providers are paid to
+pub const PROVIDER_WALLET: &str = "0x1111111111111111111111111111111111111111";
+
+/// Key of the payer signing x402 authorizations
+const PAYER_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+sol! {
+    struct TransferWithAuthorization {
+        address from;
+        address to;
+        uint256 value;
+        uint256 validAfter;
+        uint256 validBefore;
+        bytes32 nonce;
+    }
+}
+
+pub fn provider_address() -> Address {
+    Address::new("our", PROVIDER_PROCESS.parse::<hyperware_process_lib::ProcessId>().unwrap())
+}
+
+/// POST to the provider's `/api` binding; the body is `{"HandlerName": args}`
+pub async fn api(request: Value) -> anyhow::Result<Result<Value, String>> {
+    let (status, _, body) = http(
+        "POST",
+        &format!("http://localhost:{}/{}/api", NODE_PORT, PROVIDER_PROCESS),
+        HashMap::from([("Content-Type".to_string(), "application/json".to_string())]),
+        request.to_string().into_bytes(),
+    )
+    .await?;
+    if status != 200 {
+        anyhow::bail!("/api answered {}: {}", status, body);
+    }
+    Ok(serde_json::from_value(body)?)
+}
+
+/// Local request to one of the provider's `#[local]` handlers
+pub async fn local(request: Value) -> anyhow::Result<Result<Value, String>> {
+    send::<Result<Value, String>>(
+        Request::to(provider_address())
+            .body(serde_json::to_vec(&request)?)
+            .expects_response(60),
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("local request failed: {:?}", e))
+}
+
+pub async fn terminal(command: Value) -> anyhow::Result<Result<Value, String>> {
+    local(json!({ "TerminalCommand": command })).await
+}
+
+/// GET on the provider's `/xfour` binding, returning status and JSON body
+pub async fn xfour(query: &str, headers: HashMap<String, String>) -> anyhow::Result<(u16, Value)> {
+    let (status, _, body) = xfour_with_headers(query, headers).await?;
+    Ok((status, body))
+}
+
+/// Same as `xfour`, also returning the response headers
+pub async fn xfour_with_headers(
+    query: &str,
+    headers: HashMap<String, String>,
+) -> anyhow::Result<(u16, HashMap<String, String>, Value)> {
+    http(
+        "GET",
+        &format!("http://localhost:{}/{}/xfour?{}", NODE_PORT, PROVIDER_PROCESS, query),
+        headers,
+        Vec::new(),
+    )
+    .await
+}
+
+/// GET on the provider's x402 discovery list
+pub async fn x402_discovery() -> anyhow::Result<(u16, Value)> {
+    let (status, _, body) = http(
+        "GET",
+        &format!("http://localhost:{}/{}/.well-known/x402", NODE_PORT, PROVIDER_PROCESS),
+        HashMap::new(),
+        Vec::new(),
+    )
+    .await?;
+    Ok((status, body))
+}
+
+async fn http(
+    method: &str,
+    url: &str,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+) -> anyhow::Result<(u16, HashMap<String, String>, Value)> {
+    let response = send::<Result<HttpClientResponse, HttpClientError>>(
+        Request::to(("our", "http-client", "distro", "sys"))
+            .body(serde_json::to_vec(&HttpClientAction::Http(OutgoingHttpRequest {
+                method: method.to_string(),
+                version: None,
+                url: url.to_string(),
+                headers,
+            }))?)
+            .blob_bytes(body)
+            .expects_response(60),
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("http-client request failed: {:?}", e))?;
+    let HttpClientResponse::Http(response) =
+        response.map_err(|e| anyhow::anyhow!("{} {} failed: {:?}", method, url, e))?
+    else {
+        anyhow::bail!("http-client gave unexpected response");
+    };
+    let bytes = get_blob().map(|blob| blob.bytes).unwrap_or_default();
+    let body = serde_json::from_slice(&bytes)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+    Ok((response.status, response.headers, body))
+}
+
+/// Provider in front of the echo stand-in, with one parameter in each location
+pub fn echo_provider(name: &str, price: f64) -> Value {
+    let upstream = base_url();
+    json!({
+        "provider_name": name,
+        "provider_id": hyperware_process_lib::our().node,
+        "description": "Echoes the upstream request",
+        "instructions": "Pass item, units, client and city",
+        "registered_provider_wallet": PROVIDER_WALLET,
+        "price": price,
+        "endpoint": {
+            "original_curl": format!(
+                "curl -X POST '{}/echo/{{item}}?units=metric' -H 'Content-Type: application/json' -H 'X-Client: curl' -d '{{\"city\":\"Paris\",\"days\":1}}'",
+                upstream
+            ),
+            "method": "POST",
+            "base_url": upstream,
+            "url_template": format!("{}/echo/{{item}}", upstream),
+            "original_headers": [["Content-Type", "application/json"], ["X-Client", "curl"]],
+            "original_body": "{\"city\":\"Paris\",\"days\":1}",
+            "parameters": [
+                parameter("item", "/path/item", "path", "widgets"),
+                parameter("units", "/queryParams/units", "query", "metric"),
+                parameter("client", "/headers/X-Client", "header", "curl"),
+                parameter("city", "/body/city", "body", "Paris"),
+            ],
+            "parameter_names": ["item", "units", "client", "city"],
+            // The stand-in runs on localhost
+            "allow_private_network": true,
+        },
+    })
+}
+
+/// Same as `echo_provider`, but every upstream call fails with a 500
+pub fn failing_provider(name: &str) -> Value {
+    let upstream = base_url();
+    let mut provider = echo_provider(name, 0.0);
+    provider["endpoint"]["original_curl"] = json!(format!("curl '{}/fail'", upstream));
+    provider["endpoint"]["method"] = json!("GET");
+    provider["endpoint"]["url_template"] = json!(format!("{}/fail", upstream));
+    provider["endpoint"]["original_body"] = Value::Null;
+    provider["endpoint"]["parameters"] = json!([]);
+    provider["endpoint"]["parameter_names"] = json!([]);
+    provider
+}
+
+/// Provider served without an upstream HTTP call, by a dataset, sqlite or process backend
+pub fn backend_provider(name: &str, kind: Value) -> Value {
+    let mut provider = echo_provider(name, 0.0);
+    provider["endpoint"] = json!({
+        "original_curl": "",
+        "method": "GET",
+        "base_url": "",
+        "url_template": "",
+        "original_headers": [],
+        "original_body": null,
+        "parameters": [],
+        "parameter_names": [],
+        "kind": kind,
+    });
+    provider
+}
+
+fn parameter(name: &str, json_pointer: &str, location: &str, example: &str) -> Value {
+    json!({
+        "parameter_name": name,
+        "json_pointer": json_pointer,
+        "location": location,
+        "example_value": example,
+        "value_type": "string",
+    })
+}
+
+/// X-PAYMENT header paying `amount` to `pay_to` on `network` with an `exact` authorization. The signature
+/// is a placeholder, so only checks that run before signature recovery can pass.
+pub fn x_payment(network: &str, pay_to: &str, amount: &str, valid_before: u64) -> HashMap<String, String> {
+    let payload = json!({
+        "x402Version": 1,
+        "scheme": "exact",
+        "network": network,
+        "payload": {
+            "signature": format!("0x{}", "11".repeat(65)),
+            "authorization": {
+                "from": "0x4444444444444444444444444444444444444444",
+                "to": pay_to,
+                "value": amount,
+                "validAfter": "0",
+                "validBefore": valid_before.to_string(),
+                "nonce": format!("0x{}", "22".repeat(32)),
+            },
+        },
+    });
+    HashMap::from([("X-PAYMENT".to_string(), Base64::encode_string(payload.to_string().as_bytes()))])
+}
+
+/// X-PAYMENT header with an `exact` authorization really signed by the test payer, paying
+/// `amount` of `asset` (domain "USD Coin", version "2") to `pay_to` on Base. Every call signs
+/// a fresh nonce, also across runs of the suite on the same node.
+pub fn signed_x_payment(asset: &str, pay_to: &str, amount: &str) -> anyhow::Result<HashMap<String, String>> {
+    let key = k256::ecdsa::SigningKey::from_slice(&hex::decode(PAYER_KEY)?)?;
+    let from = EthAddress::from_public_key(key.verifying_key());
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+    let valid_before = now.as_secs() + 600;
+    let authorization = TransferWithAuthorization {
+        from,
+        to: EthAddress::from_str(pay_to)?,
+        value: U256::from_str_radix(amount, 10)?,
+        validAfter: U256::ZERO,
+        validBefore: U256::from(valid_before),
+        nonce: keccak256(now.as_nanos().to_be_bytes()),
+    };
+    let domain = Eip712Domain::new(
+        Some(Cow::Borrowed("USD Coin")),
+        Some(Cow::Borrowed("2")),
+        Some(U256::from(hyperware_process_lib::hypermap::HYPERMAP_CHAIN_ID)),
+        Some(EthAddress::from_str(asset)?),
+        None,
+    );
+    let (signature, recovery_id) =
+        key.sign_prehash_recoverable(authorization.eip712_signing_hash(&domain).as_slice())?;
+    let mut signature = signature.to_bytes().to_vec();
+    signature.push(27 + recovery_id.to_byte());
+
+    let payload = json!({
+        "x402Version": 1,
+        "scheme": "exact",
+        "network": "base",
+        "payload": {
+            "signature": format!("0x{}", hex::encode(signature)),
+            "authorization": {
+                "from": from.to_string(),
+                "to": pay_to,
+                "value": amount,
+                "validAfter": "0",
+                "validBefore": valid_before.to_string(),
+                "nonce": authorization.nonce.to_string(),
+            },
+        },
+    });
+    Ok(HashMap::from([("X-PAYMENT".to_string(), Base64::encode_string(payload.to_string().as_bytes()))]))
+}
+
+/// Decoded `X-PAYMENT-RESPONSE` of an `/xfour` response
+pub fn payment_response(headers: &HashMap<String, String>) -> Option<Value> {
+    let (_, encoded) = headers.iter().find(|(k, _)| k.eq_ignore_ascii_case("x-payment-response"))?;
+    serde_json::from_slice(&Base64::decode_vec(encoded).ok()?).ok()
+}
+
+pub fn arguments(pairs: &[(&str, &str)]) -> Value {
+    json!(pairs.iter().map(|(k, v)| [k, v]).collect::<Vec<_>>())
+}
+
+/// Registered providers by name
+pub async fn registered_names() -> anyhow::Result<Vec<String>> {
+    let providers = api(json!({ "GetRegisteredProviders": null }))
+        .await?
+        .map_err(|e| anyhow::anyhow!("GetRegisteredProviders failed: {}", e))?;
+    Ok(providers
+        .as_array()
+        .map(|providers| {
+            providers
+                .iter()
+                .filter_map(|p| p["provider_name"].as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Remove leftovers of an earlier run; unknown names are not an error
+pub async fn unregister(name: &str) -> anyhow::Result<()> {
+    terminal(json!({ "UnregisterProvider": name }))
+        .await?
+        .map_err(|e| anyhow::anyhow!("UnregisterProvider failed: {}", e))?;
+    Ok(())
+}
@@ -0,0 +1,263 @@
+//! Local stand-ins for what the provider talks to: an upstream HTTP API that echoes every
+//! request back, and a JSON-RPC endpoint playing the payment chain, which also mines the
+//! transactions of the provider's x402 relayer.
+
+use alloy_primitives::keccak256;
+use hyperware_process_lib::http::server::{
+    send_response, send_ws_push, HttpBindingConfig, HttpServer, HttpServerRequest,
+    IncomingHttpRequest, WsBindingConfig, WsMessageType,
+};
+use hyperware_process_lib::http::StatusCode;
+use hyperware_process_lib::{get_blob, our, print_to_terminal, LazyLoadBlob};
+use serde_json::{json, Map, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Port of the test node, see tests.toml
+pub const NODE_PORT: u16 = 8080;
+
+/// Paths the echo API answers on; path parameters must substitute to one of these
+pub const ECHO_PATHS: [&str; 2] = ["/echo/widgets", "/echo/gadgets"];
+
+/// Path that always answers 500, for upstream error tests
+pub const FAILING_PATH: &str = "/fail";
+
+const RPC_PATH: &str = "/rpc";
+
+const TRANSFER_TOPIC: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+// ERC-6551 token()
+const TOKEN_SELECTOR: &str = "0xfc0c546a";
+// EIP-3009 authorizationState(address,bytes32) and ERC-20 balanceOf(address)
+const AUTHORIZATION_STATE_SELECTOR: &str = "0xe94a0102";
+const BALANCE_OF_SELECTOR: &str = "0x70a08231";
+
+thread_local! {
+    // Transaction receipts served by eth_getTransactionReceipt, keyed by tx hash
+    static RECEIPTS: RefCell<HashMap<String, Value>> = RefCell::new(HashMap::new());
+    // Namehashes returned by token() of fake TBAs, keyed by lowercase address
+    static TBA_NAMEHASHES: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+    // Upstream requests seen, newest last
+    static UPSTREAM_HITS: RefCell<Vec<Value>> = RefCell::new(Vec::new());
+    // Raw transactions sent with eth_sendRawTransaction, hex, newest last
+    static SENT_TRANSACTIONS: RefCell<Vec<String>> = RefCell::new(Vec::new());
+    // Whether sent transactions are mined as reverted
+    static REVERT_SENT: RefCell<bool> = const { RefCell::new(false) };
+}
+
+/// Base URL of this process on the test node, e.g. `http://localhost:8080/<process>`
+pub fn base_url() -> String {
+    format!("http://localhost:{}/{}", NODE_PORT, our().process)
+}
+
+pub fn bind() -> anyhow::Result<()> {
+    let mut server = HttpServer::new(5);
+    let config = HttpBindingConfig::new(false, false, false, None);
+    for path in ECHO_PATHS.iter().chain([&FAILING_PATH]) {
+        server
+            .bind_http_path(*path, config.clone())
+            .map_err(|e| anyhow::anyhow!("failed to bind {}: {:?}", path, e))?;
+    }
+    server
+        .bind_ws_path(RPC_PATH, WsBindingConfig::new(false, false, false))
+        .map_err(|e| anyhow::anyhow!("failed to bind {}: {:?}", RPC_PATH, e))?;
+    Ok(())
+}
+
+pub fn upstream_hits() -> usize {
+    UPSTREAM_HITS.with(|hits| hits.borrow().len())
+}
+
+pub fn sent_transactions() -> Vec<String> {
+    SENT_TRANSACTIONS.with(|sent| sent.borrow().clone())
+}
+
+/// Mine every transaction sent from now on as reverted, or as successful
+pub fn revert_sent_transactions(revert: bool) {
+    REVERT_SENT.with(|r| *r.borrow_mut() = revert);
+}
+
+/// Serve `receipt` for `tx_hash` from the fake chain
+pub fn add_receipt(tx_hash: &str, receipt: Value) {
+    RECEIPTS.with(|r| r.borrow_mut().insert(tx_hash.to_lowercase(), receipt));
+}
+
+/// Make `tba` resolve to `namehash` through its token() call
+pub fn add_tba(tba: &str, namehash: &str) {
+    TBA_NAMEHASHES.with(|t| t.borrow_mut().insert(tba.to_lowercase(), namehash.to_string()));
+}
+
+/// Receipt of a USDC payment as the operator sends it: the paymaster fee first, then the
+/// transfer of `amount` (atomic units) from `tba` to `provider_wallet`
+pub fn usdc_payment_receipt(tx_hash: &str, usdc: &str, tba: &str, provider_wallet: &str, amount: u64) -> Value {
+    let block_hash = format!("0x{}", "11".repeat(32));
+    let log = |index: u64, to: &str, value: u64| {
+        json!({
+            "address": usdc,
+            "topics": [TRANSFER_TOPIC, pad_address(tba), pad_address(to)],
+            "data": format!("0x{:064x}", value),
+            "blockHash": block_hash,
+            "blockNumber": "0x1",
+            "transactionHash": tx_hash,
+            "transactionIndex": "0x0",
+            "logIndex": format!("0x{:x}", index),
+            "removed": false,
+        })
+    };
+    json!({
+        "transactionHash": tx_hash,
+        "transactionIndex": "0x0",
+        "blockHash": block_hash,
+        "blockNumber": "0x1",
+        "from": tba,
+        "to": usdc,
+        "cumulativeGasUsed": "0x5208",
+        "gasUsed": "0x5208",
+        "effectiveGasPrice": "0x1",
+        "contractAddress": null,
+        "logs": [
+            log(0, "0x0578cFB241215b77442a541325d6A4E6dFE700Ec", 1),
+            log(1, provider_wallet, amount),
+        ],
+        "logsBloom": format!("0x{}", "00".repeat(256)),
+        "type": "0x2",
+        "status": "0x1",
+    })
+}
+
+/// Mine a raw transaction: record it and serve a receipt without logs under its hash
+fn mine(raw_transaction: &str) -> String {
+    let bytes = hex::decode(raw_transaction.trim_start_matches("0x")).unwrap_or_default();
+    let tx_hash = keccak256(&bytes).to_string();
+    let status = if REVERT_SENT.with(|r| *r.borrow()) { "0x0" } else { "0x1" };
+    add_receipt(
+        &tx_hash,
+        json!({
+            "transactionHash": tx_hash,
+            "transactionIndex": "0x0",
+            "blockHash": format!("0x{}", "11".repeat(32)),
+            "blockNumber": "0x1",
+            "from": "0x0000000000000000000000000000000000000000",
+            "to": null,
+            "cumulativeGasUsed": "0x186a0",
+            "gasUsed": "0x186a0",
+            "effectiveGasPrice": "0x1",
+            "contractAddress": null,
+            "logs": [],
+            "logsBloom": format!("0x{}", "00".repeat(256)),
+            "type": "0x2",
+            "status": status,
+        }),
+    );
+    SENT_TRANSACTIONS.with(|sent| sent.borrow_mut().push(raw_transaction.to_lowercase()));
+    tx_hash
+}
+
+fn pad_address(address: &str) -> String {
+    format!("0x{:0>64}", address.trim_start_matches("0x").to_lowercase())
+}
+
+pub fn handle_http_server_request(body: &[u8]) {
+    let request = match serde_json::from_slice::<HttpServerRequest>(body) {
+        Ok(request) => request,
+        Err(e) => {
+            print_to_terminal(0, &format!("stand-in: unparseable http-server request: {}", e));
+            return;
+        }
+    };
+    match request {
+        HttpServerRequest::Http(request) => handle_upstream(&request),
+        HttpServerRequest::WebSocketPush { channel_id, .. } => {
+            let bytes = get_blob().map(|blob| blob.bytes).unwrap_or_default();
+            if let Some(reply) = handle_rpc(&bytes) {
+                send_ws_push(
+                    channel_id,
+                    WsMessageType::Text,
+                    LazyLoadBlob {
+                        mime: None,
+                        bytes: reply.to_string().into_bytes(),
+                    },
+                );
+            }
+        }
+        HttpServerRequest::WebSocketOpen { .. } | HttpServerRequest::WebSocketClose(_) => {}
+    }
+}
+
+/// Answer with everything the provider sent, so tests can check parameter substitution
+fn handle_upstream(request: &IncomingHttpRequest) {
+    let path = request.path().unwrap_or_default();
+    let body = get_blob().map(|blob| blob.bytes).unwrap_or_default();
+    let headers: Map<String, Value> = request
+        .headers()
+        .iter()
+        .filter_map(|(k, v)| Some((k.as_str().to_lowercase(), json!(v.to_str().ok()?))))
+        .collect();
+    let echo = json!({
+        "method": request.method().map(|m| m.to_string()).unwrap_or_default(),
+        "path": path,
+        "query": request.query_params(),
+        "headers": headers,
+        "body": serde_json::from_slice::<Value>(&body)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned())),
+    });
+    UPSTREAM_HITS.with(|hits| hits.borrow_mut().push(echo.clone()));
+
+    let status = if path.ends_with(FAILING_PATH) {
+        StatusCode::INTERNAL_SERVER_ERROR
+    } else {
+        StatusCode::OK
+    };
+    send_response(
+        status,
+        Some(HashMap::from([("Content-Type".to_string(), "application/json".to_string())])),
+        echo.to_string().into_bytes(),
+    );
+}
+
+/// JSON-RPC for the chain the provider validates payments on
+fn handle_rpc(bytes: &[u8]) -> Option<Value> {
+    let request: Value = serde_json::from_slice(bytes).ok()?;
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let params = request.get("params").cloned().unwrap_or(json!([]));
+    let result = match request.get("method").and_then(Value::as_str)? {
+        "eth_chainId" => json!(format!("0x{:x}", hyperware_process_lib::hypermap::HYPERMAP_CHAIN_ID)),
+        "eth_blockNumber" => json!("0x1"),
+        "eth_getLogs" => json!([]),
+        "eth_subscribe" => json!("0x1"),
+        "eth_unsubscribe" => json!(true),
+        "eth_getTransactionReceipt" => {
+            let tx_hash = params[0].as_str().unwrap_or_default().to_lowercase();
+            RECEIPTS.with(|r| r.borrow().get(&tx_hash).cloned()).unwrap_or(Value::Null)
+        }
+        "eth_call" => {
+            let to = params[0]["to"].as_str().unwrap_or_default().to_lowercase();
+            let input = params[0]["input"]
+                .as_str()
+                .or_else(|| params[0]["data"].as_str())
+                .unwrap_or_default();
+            match TBA_NAMEHASHES.with(|t| t.borrow().get(&to).cloned()) {
+                Some(namehash) if input.starts_with(TOKEN_SELECTOR) => json!(format!(
+                    "0x{:064x}{:0>64}{}",
+                    hyperware_process_lib::hypermap::HYPERMAP_CHAIN_ID,
+                    hyperware_process_lib::hypermap::HYPERMAP_ADDRESS.trim_start_matches("0x").to_lowercase(),
+                    namehash.trim_start_matches("0x"),
+                )),
+                // Every authorization is unused and every payer can afford it
+                _ if input.starts_with(AUTHORIZATION_STATE_SELECTOR) => json!(format!("0x{:064x}", 0)),
+                _ if input.starts_with(BALANCE_OF_SELECTOR) => json!(format!("0x{:064x}", u64::MAX)),
+                _ => return Some(rpc_error(id, "execution reverted")),
+            }
+        }
+        "eth_estimateGas" => json!("0x186a0"),
+        "eth_gasPrice" => json!("0x3b9aca00"),
+        "eth_maxPriorityFeePerGas" => json!("0x1"),
+        "eth_getTransactionCount" => json!(format!("0x{:x}", SENT_TRANSACTIONS.with(|sent| sent.borrow().len()))),
+        "eth_sendRawTransaction" => json!(mine(params[0].as_str().unwrap_or_default())),
+        method => return Some(rpc_error(id, &format!("method {} not served by the stand-in", method))),
+    };
+    Some(json!({"jsonrpc": "2.0", "id": id, "result": result}))
+}
+
+fn rpc_error(id: Value, message: &str) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": -32601, "message": message}})
+}
@@ -104,6 +104,12 @@ macro_rules! async_test_suite {
                                     registry_mut.insert(correlation_id, body);
                                 });
                             },
+                            hyperware_process_lib::Message::Request { ref source, ref body, .. }
+                                if source.process.to_string() == "http-server:distro:sys" =>
+                            {
+                                // Upstream API and chain RPC calls made by the provider under test
+                                crate::stand_in::handle_http_server_request(body);
+                            }
                             hyperware_process_lib::Message::Request { .. } => {
                                 // The first request triggers test execution
                                 if !tests_triggered {
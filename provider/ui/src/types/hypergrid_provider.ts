@@ -55,6 +55,157 @@ export interface EndpointDefinition {
   // Parameter definitions for substitution
   parameters: ParameterDefinition[];
   parameter_names: string[];
+
+  // Credentials for short-lived upstream bearer tokens
+  upstream_auth?: UpstreamAuth | null;
+  // Signers that add computed headers to each upstream request
+  request_signers?: RequestSigner[];
+
+  // How the call is executed; "Curl" unless stated otherwise
+  kind?: EndpointKind;
+
+  // Hosts upstream requests may go to; pinned to the url_template host when empty
+  allowed_hosts?: string[];
+  // Allow private, loopback and link-local upstream addresses
+  allow_private_network?: boolean;
+}
+
+// Rust enum EndpointKind: unit variants serialize as strings, others as { Variant: payload }
+export type EndpointKind =
+  | "Curl"
+  | { GraphQl: GraphQlOperation }
+  | { Process: ProcessBackend }
+  | { Dataset: DatasetSource }
+  | { Sql: SqlSource };
+
+export interface SqlSource {
+  database: string; // sqlite database owned by the provider process
+  allowed_tables: SqlTable[];
+  queries: SqlQueryTemplate[];
+  free_form?: FreeFormSql | null; // null disables free-form SELECT
+  row_limit: number;
+}
+
+export interface SqlTable {
+  name: string;
+  columns: string[];
+}
+
+export interface SqlQueryTemplate {
+  name: string;
+  description: string;
+  sql: string; // SELECT with "?" placeholders
+  parameters: string[]; // Argument names bound to the placeholders, in order
+}
+
+export interface FreeFormSql {
+  timeout_seconds: number;
+}
+
+// Request body for the import_dataset_to_sqlite endpoint
+export interface ImportDatasetToSqlite {
+  file_name: string;
+  database: string;
+  table: string;
+}
+
+export type ImportDatasetToSqliteResponse = RustResponse<number>; // Rows imported
+
+export interface DatasetSource {
+  file_name: string; // .csv, .json or .jsonl uploaded with upload_dataset
+  default_limit: number; // Rows per page when the caller sets no limit
+  max_limit: number;
+}
+
+export interface DatasetInfo {
+  file_name: string;
+  row_count: number;
+  columns: string[];
+  size_bytes: number;
+}
+
+// Request body for the upload_dataset endpoint
+export interface UploadDataset {
+  file_name: string;
+  content: string; // Raw file contents
+}
+
+export type UploadDatasetResponse = RustResponse<DatasetInfo>;
+export type ListDatasetsResponse = RustResponse<DatasetInfo[]>;
+export type DeleteDatasetResponse = RustResponse<null>;
+
+// Rust enum SandboxMode: live upstream calls, recording them, or replaying recordings
+export type SandboxMode = "Off" | "Record" | "Replay";
+
+export interface SandboxFixture {
+  request_key: string; // Hex SHA-256 over method, full URL and body
+  method: string;
+  url: string; // Without the query string
+  status: number;
+  headers: [string, string][];
+  body: string;
+  recorded_at: number; // Unix seconds
+}
+
+export type SandboxModeResponse = RustResponse<SandboxMode>;
+export type SandboxFixturesResponse = RustResponse<SandboxFixture[]>;
+
+export interface ProcessBackend {
+  process_id: string; // e.g. "my-process:my-package:publisher.os", on the provider's own node
+  body_template: string; // JSON request body; "body" parameters are substituted by JSON pointer
+  timeout_seconds: number;
+}
+
+export interface GraphQlOperation {
+  query: string; // Full query document
+  operation_name?: string | null;
+  variables: GraphQlVariable[];
+}
+
+export interface GraphQlVariable {
+  name: string; // Without the leading "$"
+  graphql_type: string; // e.g. "Int!", "[String]"
+  default_value?: string | null;
+}
+
+// Rust enum RequestSigner, serialized as { Variant: payload }
+export type RequestSigner =
+  | { HmacSha256: HmacSha256Signer }
+  | { AwsSigV4: AwsSigV4Signer };
+
+export interface HmacSha256Signer {
+  secret: string;
+  signature_header: string; // e.g. "X-Signature"
+  timestamp_header: string; // e.g. "X-Timestamp"
+  signature_prefix?: string | null; // e.g. "sha256="
+}
+
+export interface AwsSigV4Signer {
+  access_key_id: string;
+  secret_access_key: string;
+  session_token?: string | null;
+  region: string;
+  service: string;
+}
+
+// Rust enum UpstreamAuth, serialized as { Variant: payload }. Secrets arrive blank from the
+// backend; leaving them blank in an update keeps the stored ones
+export type UpstreamAuth =
+  | { OAuth2ClientCredentials: OAuth2ClientCredentials }
+  | { OAuth2RefreshToken: OAuth2RefreshToken };
+
+export interface OAuth2ClientCredentials {
+  token_url: string;
+  client_id: string;
+  client_secret: string;
+  scope?: string | null;
+}
+
+export interface OAuth2RefreshToken {
+  token_url: string;
+  client_id: string;
+  client_secret?: string | null;
+  refresh_token: string;
 }
 
 // Interface for RegisteredProvider, matching Rust's struct
@@ -66,6 +217,131 @@ export interface RegisteredProvider {
   registered_provider_wallet: string; // Eth address as string
   price: number; // Price per call
   endpoint: EndpointDefinition;
+  access_pass?: AccessPassConfig | null; // Optional time-based pass
+  output_schema?: string | null; // JSON Schema of the response body, serialized as JSON
+  pipeline?: Pipeline | null; // Composite providers run this instead of `endpoint`
+  async_job?: AsyncJobConfig | null; // Calls return a job ticket and run in the background
+  max_response_bytes?: number | null; // Larger responses are chunked behind a continuation token
+  circuit_breaker?: CircuitBreakerConfig | null; // null uses the defaults
+  x402_accepts?: X402PaymentOption[]; // Empty offers USDC on the build's network
+}
+
+// One way of paying over x402, advertised in the 402 `accepts` list
+export interface X402PaymentOption {
+  network: string; // e.g. "base" or "base-sepolia"
+  asset: string; // EIP-3009 token contract
+  asset_name: string; // EIP-712 domain name, e.g. "USD Coin"
+  asset_version: string; // EIP-712 domain version, e.g. "2"
+  decimals: number;
+  pay_to?: string | null; // null pays the provider wallet
+  price?: number | null; // In the asset's units, null uses the provider price
+}
+
+// --- Composite provider pipelines ---
+export interface Pipeline {
+  steps: PipelineStep[]; // Run in order; the last step's output is the result
+}
+
+export interface PipelineStep {
+  name: string;
+  target: PipelineTarget;
+  bindings: ArgumentBinding[]; // Empty passes the call arguments through unchanged
+}
+
+export type PipelineTarget =
+  | { Local: EndpointDefinition }
+  | { Remote: RemoteProviderStep };
+
+export interface RemoteProviderStep {
+  provider_node: string;
+  provider_name: string;
+  price: number; // USDC paid per call from this provider's wallet, through the operator
+  wallet_address: string;
+}
+
+export interface ArgumentBinding {
+  argument_name: string;
+  source: ArgumentSource;
+}
+
+export type ArgumentSource =
+  | { CallArgument: string }
+  | { StepOutput: StepOutputRef }
+  | { Literal: string };
+
+export interface StepOutputRef {
+  step_name: string;
+  json_pointer: string;
+}
+
+// Rust enum X402Settlement: who submits x402 settlements
+export type X402Settlement = "Facilitator" | "SelfSettle";
+
+export type X402SettlementResponse = RustResponse<X402Settlement>;
+export type GetX402RelayerAddressResponse = RustResponse<string | null>;
+
+// Interface for AccessPassConfig, matching Rust's struct
+export interface AccessPassConfig {
+  price: number; // Price of one pass in USDC
+  duration_seconds: number;
+  max_calls?: number | null; // null means unlimited calls until expiry
+}
+
+// --- Async jobs ---
+export interface AsyncJobConfig {
+  upstream_timeout_seconds: number;
+  result_ttl_seconds: number; // How long a finished result stays available
+}
+
+export type JobStatus = 'Pending' | 'Running' | 'Succeeded' | 'Failed';
+
+export interface ProviderJob {
+  job_id: string;
+  provider_name: string;
+  node_id: string; // Node that paid for the job
+  arguments: [string, string][];
+  payment_tx_hash?: string | null;
+  status: JobStatus;
+  created_at: number; // Unix seconds
+  completed_at?: number | null;
+  result?: string | null;
+  error?: string | null;
+  access_pass?: string | null; // Tx hash of the access pass covering the job
+}
+
+export interface CircuitBreakerConfig {
+  consecutive_failures: number; // Opens after this many failed calls in a row
+  failure_rate_threshold: number; // ...or above this share (0-1) of failures in the window
+  window_size: number;
+  open_seconds: number; // Calls are refused this long before a probe is let through
+}
+
+// First and following chunks of a response larger than `max_response_bytes`
+export interface ResponseChunk {
+  chunk: string;
+  chunk_index: number;
+  total_chunks: number;
+  total_bytes: number;
+  continuation_token: string;
+  next_chunk_index?: number | null; // null on the last chunk
+}
+
+// Answer to an operator's HealthPing, checked before it pays
+export interface HealthStatus {
+  provider_name: string;
+  provider_version: string;
+  protocol_version: number; // Negotiated, carried in the operator's following messages
+  supported_protocol_versions: number[];
+  price: number; // USDC per call
+  wallet: string;
+  available: boolean; // false while calls would be refused
+  unavailable_reason?: string | null;
+  configured: boolean;
+  circuit_state: string; // "closed" | "open" | "half_open"
+  latency_p50_ms?: number | null; // null before the first call
+  latency_p95_ms?: number | null;
+  queue_depth: number; // Calls in flight plus queued jobs
+  payment_modes: string[]; // "tx_hash", "access_pass", "x402"
 }
 
 // Request body for the register_provider endpoint
@@ -95,6 +371,36 @@ export interface UpdateProvider {
 // Response type for the update_provider endpoint
 export type UpdateProviderResponse = RustResponse<RegisteredProvider>;
 
+// --- Provider revision history ---
+export type RevisionAction = "Register" | "Update" | "Rollback";
+
+export interface FieldChange {
+  field: string; // JSON pointer into RegisteredProvider
+  before?: string | null; // JSON-encoded value
+  after?: string | null;
+}
+
+export interface ProviderRevision {
+  revision: number;
+  timestamp: number; // Unix seconds
+  action: RevisionAction;
+  source_revision?: number | null;
+  diff: FieldChange[];
+  provider: RegisteredProvider;
+}
+
+export type GetProviderRevisionsResponse = RustResponse<ProviderRevision[]>;
+export type DiffProviderRevisionsResponse = RustResponse<FieldChange[]>;
+
+// Request body for the rollback_provider endpoint
+export interface RollbackProvider {
+  provider_name: string;
+  revision: number;
+  arguments: [string, string][];
+}
+
+export type RollbackProviderResponse = RustResponse<RegisteredProvider>;
+
 // --- HypergridProviderState (matches Rust struct, using JS naming convention for store) ---
 export interface HypergridProviderState {
   registeredProviders: RegisteredProvider[];